@@ -0,0 +1,168 @@
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::form_urlencoded;
+
+/// Overrides the default cache directory, like `--cache-dir`.
+const CACHE_DIR_VARIABLE: &str = "IAM_CAPABLE_CACHE_DIR";
+
+/// Numbers the temporary files of this process, so concurrent writes never share one.
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A cached managed policy document.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    arn: String,
+    version_id: String,
+    /// The document as returned by `GetPolicyVersion`, still URL-encoded.
+    document: String,
+}
+
+/// Managed policy documents stored on disk, one file per policy ARN and version ID.
+///
+/// A policy version never changes once created, so an entry stays valid for as long as the
+/// version is the policy's default; callers look the default version up with `GetPolicy`
+/// before reading the cache.
+#[derive(Debug, Clone)]
+pub struct PolicyCache {
+    dir: PathBuf,
+}
+
+impl PolicyCache {
+    pub fn new(dir: PathBuf) -> Self {
+        PolicyCache { dir }
+    }
+
+    /// The directory used when none is given: `$IAM_CAPABLE_CACHE_DIR` if set, otherwise
+    /// `iam-capable/policies` in the user's cache directory (`$XDG_CACHE_HOME` or
+    /// `~/.cache` on Linux). `None` if neither is known.
+    pub fn default_dir() -> Option<PathBuf> {
+        match std::env::var_os(CACHE_DIR_VARIABLE) {
+            Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+            _ => dirs::cache_dir().map(|dir| dir.join("iam-capable").join("policies")),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the cached document of a policy version, or `None` if it is not cached or the
+    /// entry cannot be read.
+    pub fn get(&self, arn: &str, version_id: &str) -> Option<String> {
+        let contents = fs::read_to_string(self.entry_path(arn, version_id)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+        (entry.arn == arn && entry.version_id == version_id).then_some(entry.document)
+    }
+
+    /// Stores the document of a policy version, creating the cache directory if needed.
+    ///
+    /// The entry is written to a temporary file and renamed into place, so a concurrent run
+    /// never reads a partly written entry.
+    pub fn put(
+        &self,
+        arn: &str,
+        version_id: &str,
+        document: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let entry = CacheEntry {
+            arn: arn.to_string(),
+            version_id: version_id.to_string(),
+            document: document.to_string(),
+        };
+        fs::create_dir_all(&self.dir)?;
+        let path = self.entry_path(arn, version_id);
+        let temporary = path.with_extension(format!(
+            "{}-{}.tmp",
+            std::process::id(),
+            TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temporary, serde_json::to_string(&entry)?)?;
+        if let Err(e) = fs::rename(&temporary, &path) {
+            let _ = fs::remove_file(&temporary);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Removes every cached document. Other files in the directory, including JSON files that
+    /// are not cache entries, are left alone.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the number of documents removed, or an Error.
+    pub fn clear(&self) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+                && is_cache_entry(&path)
+            {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn entry_path(&self, arn: &str, version_id: &str) -> PathBuf {
+        // Percent-encoding keeps the ':' and '/' of the ARN out of the file name.
+        let key: String =
+            form_urlencoded::byte_serialize(format!("{}@{}", arn, version_id).as_bytes()).collect();
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Returns true if the file at `path` holds a cache entry.
+fn is_cache_entry(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<CacheEntry>(&contents).ok())
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip_and_clear() {
+        let dir = std::env::temp_dir().join(format!("iam-capable-cache-{}", std::process::id()));
+        let cache = PolicyCache::new(dir.clone());
+        let arn = "arn:aws:iam::aws:policy/ReadOnlyAccess";
+
+        assert_eq!(cache.clear().unwrap(), 0);
+        assert_eq!(cache.get(arn, "v1"), None);
+
+        cache.put(arn, "v1", "%7B%7D").unwrap();
+        assert_eq!(cache.get(arn, "v1"), Some("%7B%7D".to_string()));
+        assert_eq!(cache.get(arn, "v2"), None);
+        assert_eq!(
+            cache.get("arn:aws:iam::aws:policy/ViewOnlyAccess", "v1"),
+            None
+        );
+
+        cache.put(arn, "v2", "%7B%7D").unwrap();
+        // Only cache entries are written to the directory, without leftover temporary files.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Files the cache did not write survive clearing, even if they are JSON.
+        let unrelated = dir.join("settings.json");
+        fs::write(&unrelated, r#"{"region": "us-east-1"}"#).unwrap();
+        assert_eq!(cache.clear().unwrap(), 2);
+        assert_eq!(cache.get(arn, "v1"), None);
+        assert!(unrelated.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
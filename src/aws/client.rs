@@ -1,32 +1,251 @@
+use aws_config::default_provider::region::DefaultRegionChain;
 use aws_config::meta::region::RegionProviderChain;
+use aws_config::sts::AssumeRoleProvider;
+use aws_credential_types::provider::SharedCredentialsProvider;
 use aws_sdk_iam::Client as IamClient;
 use aws_sdk_sts::Client as StsClient;
+use aws_types::region::Region;
+use aws_types::SdkConfig;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 
+use crate::aws::cache::PolicyCache;
+use crate::aws::throttle::Throttle;
+
+/// The session name used for assumed roles when none is given.
+const DEFAULT_SESSION_NAME: &str = "iam-capable";
+
+/// How to connect to AWS: the profile, region and endpoint to use, and the roles to assume.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// The shared config profile to take credentials and the region from.
+    pub profile: Option<String>,
+    /// The region, overriding the environment and the profile.
+    pub region: Option<String>,
+    /// A custom endpoint for every service, e.g. a LocalStack or Moto server.
+    pub endpoint_url: Option<String>,
+    /// A role to assume with the profile's credentials before doing anything else.
+    pub assume_role_arn: Option<String>,
+    /// The external ID the assumed roles' trust policies require, if any.
+    pub external_id: Option<String>,
+    /// The session name for assumed roles.
+    pub session_name: Option<String>,
+    /// The role to assume in other accounts to read their IAM identities.
+    pub cross_account_role: Option<String>,
+    /// The most IAM requests in flight at once, across every identity fetched.
+    pub concurrency: usize,
+    /// Where to cache managed policy documents, or `None` to always download them.
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl ClientOptions {
+    /// Returns the options with `profile` replacing the configured profile, if given.
+    pub fn with_profile(&self, profile: Option<String>) -> Self {
+        ClientOptions {
+            profile: profile.or_else(|| self.profile.clone()),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct AwsClient {
     pub iam: IamClient,
     pub account_id: String,
+    /// The partition of the caller, e.g. `aws` or `aws-cn`, used to build role ARNs.
+    pub partition: String,
+    /// Shared by every clone of the client, so the concurrency limit and backoff apply to all of
+    /// its requests.
+    pub throttle: Arc<Throttle>,
+    /// The cache of managed policy documents, unless caching is disabled.
+    pub cache: Option<PolicyCache>,
+    /// The clients that have assumed the cross-account role, by account, so the role is only
+    /// assumed once per account.
+    account_clients: Arc<Mutex<HashMap<String, AwsClient>>>,
+    options: ClientOptions,
+    config: SdkConfig,
 }
 
-pub async fn get_aws_client() -> Result<AwsClient, Box<dyn Error + Send + Sync>> {
-    let region_provider = RegionProviderChain::default_provider().or_else("us-east-1");
+/// Builds a client from the default credential chain, adjusted by `options`.
+///
+/// # Arguments
+///
+/// * `options` - The profile, region, endpoint and roles to assume.
+///
+/// # Returns
+///
+/// A Result containing the AwsClient, or an Error if the credentials are missing or invalid,
+/// or a role cannot be assumed.
+pub async fn get_aws_client(
+    options: &ClientOptions,
+) -> Result<AwsClient, Box<dyn Error + Send + Sync>> {
+    let mut config = load_config(options, None).await;
+
+    if let Some(role_arn) = &options.assume_role_arn {
+        let credentials = assume_role(&config, options, role_arn)?;
+        config = load_config(options, Some(credentials)).await;
+    }
+
+    let throttle = Arc::new(Throttle::new(options.concurrency));
+    AwsClient::from_config(config, options.clone(), throttle)
+        .await
+        .map_err(|e| match &options.assume_role_arn {
+            Some(role_arn) => format!("Failed to assume {}: {}", role_arn, e).into(),
+            None => e,
+        })
+}
+
+/// Loads the SDK configuration for `options`, with `credentials` replacing those of the
+/// profile or environment when given.
+async fn load_config(
+    options: &ClientOptions,
+    credentials: Option<SharedCredentialsProvider>,
+) -> SdkConfig {
+    let mut default_region = DefaultRegionChain::builder();
+    if let Some(profile) = &options.profile {
+        default_region = default_region.profile_name(profile);
+    }
+    let region_provider = RegionProviderChain::first_try(options.region.clone().map(Region::new))
+        .or_else(default_region.build())
+        .or_else("us-east-1");
+
+    let mut loader = aws_config::from_env().region(region_provider);
+    if let Some(profile) = &options.profile {
+        loader = loader.profile_name(profile);
+    }
+    if let Some(endpoint_url) = &options.endpoint_url {
+        loader = loader.endpoint_url(endpoint_url);
+    }
+    if let Some(credentials) = credentials {
+        loader = loader.credentials_provider(credentials);
+    }
+    loader.load().await
+}
+
+/// Returns credentials for `role_arn`, assumed with the credentials of `config`.
+fn assume_role(
+    config: &SdkConfig,
+    options: &ClientOptions,
+    role_arn: &str,
+) -> Result<SharedCredentialsProvider, Box<dyn Error + Send + Sync>> {
+    let region = config
+        .region()
+        .cloned()
+        .ok_or("a region is required to assume a role")?;
+    let base_credentials = config
+        .credentials_provider()
+        .ok_or_else(|| format!("no credentials to assume {} with", role_arn))?
+        .clone();
+
+    let mut provider = AssumeRoleProvider::builder(role_arn)
+        .session_name(
+            options
+                .session_name
+                .as_deref()
+                .unwrap_or(DEFAULT_SESSION_NAME),
+        )
+        .region(region);
+    if let Some(external_id) = &options.external_id {
+        provider = provider.external_id(external_id);
+    }
+    Ok(SharedCredentialsProvider::new(
+        provider.build(base_credentials),
+    ))
+}
+
+impl AwsClient {
+    /// Builds a client on `config`, sending its requests through `throttle`.
+    async fn from_config(
+        config: SdkConfig,
+        options: ClientOptions,
+        throttle: Arc<Throttle>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let iam = IamClient::new(&config);
+        let sts = StsClient::new(&config);
+        // Also fails early with a clear message when the credentials are missing or invalid.
+        let (account_id, partition) = fetch_caller_account(&sts).await?;
+        Ok(AwsClient {
+            iam,
+            account_id,
+            partition,
+            throttle,
+            cache: options.cache_dir.clone().map(PolicyCache::new),
+            account_clients: Arc::default(),
+            options,
+            config,
+        })
+    }
+
+    /// The most requests to have in flight at once.
+    pub fn concurrency(&self) -> usize {
+        self.options.concurrency.max(1)
+    }
+
+    /// Returns a client for reading identities in `account_id`: this client when the account is
+    /// the caller's own (or not given), otherwise a client that has assumed the cross-account
+    /// role in that account. The role is assumed once per account, and the client shares this
+    /// client's throttle, so `--concurrency` and the backoff cover every account.
+    ///
+    /// # Arguments
+    ///
+    /// * `account_id` - The account the identity lives in, if known.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the client, or an Error if the account differs and no cross-account
+    /// role is configured, or the role cannot be assumed.
+    pub async fn for_account(
+        &self,
+        account_id: Option<&str>,
+    ) -> Result<Cow<'_, AwsClient>, Box<dyn Error + Send + Sync>> {
+        let account_id = match account_id {
+            Some(account_id) if account_id != self.account_id => account_id,
+            _ => return Ok(Cow::Borrowed(self)),
+        };
+
+        let mut account_clients = self.account_clients.lock().await;
+        if let Some(client) = account_clients.get(account_id) {
+            return Ok(Cow::Owned(client.clone()));
+        }
+
+        let role_name = self.options.cross_account_role.as_deref().ok_or_else(|| {
+            format!(
+                "account {} differs from the caller's account {}; pass --cross-account-role to assume a role in it",
+                account_id, self.account_id
+            )
+        })?;
+        let role_arn = format!(
+            "arn:{}:iam::{}:role/{}",
+            self.partition, account_id, role_name
+        );
 
-    let config = aws_config::from_env().region(region_provider).load().await;
-    let iam = IamClient::new(&config);
-    let sts = StsClient::new(&config);
-    let account_id = fetch_account_id(&sts).await;
-    match account_id {
-        Ok(account_id) => Ok(AwsClient { iam, account_id }),
-        Err(e) => Err(e),
+        let credentials = assume_role(&self.config, &self.options, &role_arn)?;
+        let config = load_config(&self.options, Some(credentials)).await;
+        let client = AwsClient::from_config(config, self.options.clone(), self.throttle.clone())
+            .await
+            .map_err(|e| format!("Failed to assume {}: {}", role_arn, e))?;
+        account_clients.insert(account_id.to_string(), client.clone());
+        Ok(Cow::Owned(client))
     }
 }
 
-async fn fetch_account_id(
+/// Fetches the caller's account ID and partition through `GetCallerIdentity`.
+async fn fetch_caller_account(
     sts: &StsClient,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let account_id =
-        sts.get_caller_identity().send().await?.account.ok_or(
-            "Could not fetch account ID. Please check your AWS credentials and try again.",
-        )?;
-    Ok(account_id)
+) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+    let caller = sts.get_caller_identity().send().await?;
+    let account_id = caller
+        .account
+        .ok_or("Could not fetch account ID. Please check your AWS credentials and try again.")?;
+    let partition = caller
+        .arn
+        .as_deref()
+        .and_then(|arn| arn.split(':').nth(1))
+        .unwrap_or("aws")
+        .to_string();
+    Ok((account_id, partition))
 }
@@ -1,31 +1,255 @@
-use serde::Deserialize as SerdeDeserialize;
+use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Policy {
     #[serde(rename = "Version")]
     pub version: String,
-    #[serde(rename = "Statement")]
+    #[serde(rename = "Statement", deserialize_with = "deserialize_statements")]
     pub statements: Vec<PolicyStatement>,
+    /// Where the policy document was retrieved from. Not part of the IAM document itself.
+    #[serde(skip)]
+    pub origin: PolicyOrigin,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg(test)]
+impl Policy {
+    /// Builds a `2012-10-17` policy from its `Statement` element, for tests.
+    pub fn from_statements(origin: PolicyOrigin, statements: Value) -> Policy {
+        let mut policy: Policy = serde_json::from_value(serde_json::json!({
+            "Version": "2012-10-17",
+            "Statement": statements
+        }))
+        .unwrap();
+        policy.origin = origin;
+        policy
+    }
+
+    /// Like `from_statements`, for an inline policy named `name`.
+    pub fn inline(name: &str, statements: Value) -> Policy {
+        Policy::from_statements(
+            PolicyOrigin::Inline {
+                name: name.to_string(),
+            },
+            statements,
+        )
+    }
+}
+
+/// The policies that determine what a role, user or group can do.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityPolicies {
+    /// The identity policies: attached managed policies, inline policies or local documents.
+    /// A user's policies include those inherited from its groups.
+    pub policies: Vec<Policy>,
+    /// The role's or user's permissions boundary, which caps what the identity policies can grant.
+    pub permissions_boundary: Option<Policy>,
+}
+
+impl IdentityPolicies {
+    /// Returns the identity policies, followed by the permissions boundary if
+    /// `include_boundary` is set. Capability extraction recognises the boundary by its origin
+    /// and intersects the identity policies' grants with it.
+    pub fn into_policies(self, include_boundary: bool) -> Vec<Policy> {
+        let mut policies = self.policies;
+        if include_boundary {
+            policies.extend(self.permissions_boundary);
+        }
+        policies
+    }
+}
+
+/// Describes whether a policy is a managed policy attached to the identity, an inline policy
+/// embedded in it, a policy inherited from one of a user's groups, the identity's permissions
+/// boundary, an organization control policy, or a document read from a local file.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PolicyOrigin {
+    #[default]
+    Unknown,
+    Managed {
+        arn: String,
+        /// The policy version the document was read from, when known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version_id: Option<String>,
+    },
+    Inline {
+        name: String,
+    },
+    /// A managed or inline policy of a group the user belongs to.
+    Group {
+        group: String,
+        policy: Box<PolicyOrigin>,
+    },
+    PermissionsBoundary {
+        arn: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version_id: Option<String>,
+    },
+    /// An AWS Organizations service control policy read from a local file. Each file is one
+    /// level of the organizational unit chain.
+    ServiceControlPolicy {
+        path: String,
+    },
+    /// An AWS Organizations resource control policy read from a local file, capping what can be
+    /// done to the account's resources. Each file is one level of the organizational unit chain.
+    ResourceControlPolicy {
+        path: String,
+    },
+    File {
+        path: String,
+    },
+    /// The resource-based policy of a bucket, key, queue, topic or function.
+    Resource {
+        arn: String,
+    },
+}
+
+impl PolicyOrigin {
+    /// Returns true if the policy caps what the identity policies grant instead of granting
+    /// anything itself: a permissions boundary, or a service or resource control policy.
+    /// Policies with the same origin form one ceiling.
+    pub fn is_ceiling(&self) -> bool {
+        matches!(
+            self,
+            PolicyOrigin::PermissionsBoundary { .. }
+                | PolicyOrigin::ServiceControlPolicy { .. }
+                | PolicyOrigin::ResourceControlPolicy { .. }
+        )
+    }
+
+    /// Returns true if the ceiling must allow a request for it to be allowed, as a permissions
+    /// boundary and service control policies must. Resource control policies only deny: AWS
+    /// attaches an implicit `RCPFullAWSAccess` to every level, so only their `Deny` statements
+    /// apply.
+    pub fn requires_allow(&self) -> bool {
+        matches!(
+            self,
+            PolicyOrigin::PermissionsBoundary { .. } | PolicyOrigin::ServiceControlPolicy { .. }
+        )
+    }
+
+    /// Turns the origin of a managed policy into that of a permissions boundary, which is
+    /// always a managed policy.
+    pub fn into_permissions_boundary(self) -> Self {
+        match self {
+            PolicyOrigin::Managed { arn, version_id } => {
+                PolicyOrigin::PermissionsBoundary { arn, version_id }
+            }
+            origin => origin,
+        }
+    }
+
+    /// Wraps the origin of a group's policy to record that a user inherits it from `group`.
+    pub fn inherited_from(self, group: &str) -> Self {
+        PolicyOrigin::Group {
+            group: group.to_string(),
+            policy: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for PolicyOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyOrigin::Unknown => write!(f, ""),
+            PolicyOrigin::Managed { arn, .. } => write!(f, "managed:{}", arn),
+            PolicyOrigin::Inline { name } => write!(f, "inline:{}", name),
+            PolicyOrigin::Group { group, policy } => write!(f, "group:{}:{}", group, policy),
+            PolicyOrigin::PermissionsBoundary { arn, .. } => write!(f, "boundary:{}", arn),
+            PolicyOrigin::ServiceControlPolicy { path } => write!(f, "scp:{}", path),
+            PolicyOrigin::ResourceControlPolicy { path } => write!(f, "rcp:{}", path),
+            PolicyOrigin::File { path } => write!(f, "file:{}", path),
+            PolicyOrigin::Resource { arn } => write!(f, "resource:{}", arn),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Effect {
+    #[default]
     Allow,
     Deny,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct PolicyStatement {
+    #[serde(rename = "Sid", default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
     #[serde(rename = "Effect")]
     pub effect: Effect,
-    #[serde(rename = "Action")]
+    #[serde(rename = "Principal", default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<Principal>,
+    #[serde(
+        rename = "NotPrincipal",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub not_principal: Option<Principal>,
+    #[serde(rename = "Action", default, skip_serializing_if = "Vec::is_empty")]
     #[serde(deserialize_with = "deserialize_string_or_array")]
     pub action: Vec<String>,
-    #[serde(rename = "Resource")]
+    #[serde(rename = "NotAction", default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "deserialize_string_or_array")]
+    pub not_action: Vec<String>,
+    #[serde(rename = "Resource", default, skip_serializing_if = "Vec::is_empty")]
     #[serde(deserialize_with = "deserialize_string_or_array")]
     pub resource: Vec<String>,
+    #[serde(rename = "NotResource", default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(deserialize_with = "deserialize_string_or_array")]
+    pub not_resource: Vec<String>,
+    #[serde(rename = "Condition", default, skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_condition")]
+    pub condition: Option<Condition>,
+}
+
+/// A statement's `Condition` block, mapping each operator (e.g. `StringEquals`) to the
+/// condition keys it tests and their values. Scalar values are normalised to strings.
+pub type Condition = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+/// The `Principal` or `NotPrincipal` element of a statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// `"Principal": "*"`, i.e. everyone.
+    Wildcard,
+    /// A map of principal type (`AWS`, `Service`, `Federated`, `CanonicalUser`) to identifiers.
+    Typed(BTreeMap<String, Vec<String>>),
+}
+
+impl SerdeSerialize for Principal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Principal::Wildcard => serializer.serialize_str("*"),
+            Principal::Typed(principals) => principals.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> SerdeDeserialize<'de> for Principal {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        match value {
+            Value::String(s) if s == "*" => Ok(Principal::Wildcard),
+            Value::Object(map) => map
+                .into_iter()
+                .map(|(principal_type, ids)| string_or_array(ids).map(|ids| (principal_type, ids)))
+                .collect::<Result<_, _>>()
+                .map(Principal::Typed)
+                .map_err(serde::de::Error::custom),
+            _ => Err(serde::de::Error::custom(
+                "expected \"*\" or a map of principal types",
+            )),
+        }
+    }
 }
 
 fn deserialize_string_or_array<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
@@ -33,14 +257,181 @@ where
     D: serde::Deserializer<'de>,
 {
     let value = Value::deserialize(deserializer)?;
+    string_or_array(value).map_err(serde::de::Error::custom)
+}
+
+/// Accepts `Statement` as either an array of statements or a single statement object.
+fn deserialize_statements<'de, D>(deserializer: D) -> Result<Vec<PolicyStatement>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Array(statements) => statements
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<_, _>>()
+            .map_err(serde::de::Error::custom),
+        statement => serde_json::from_value(statement)
+            .map(|statement| vec![statement])
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+fn deserialize_condition<'de, D>(deserializer: D) -> Result<Option<Condition>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let operators = BTreeMap::<String, BTreeMap<String, Value>>::deserialize(deserializer)?;
+
+    operators
+        .into_iter()
+        .map(|(operator, keys)| {
+            let keys = keys
+                .into_iter()
+                .map(|(key, values)| scalar_or_array(values).map(|values| (key, values)))
+                .collect::<Result<_, _>>()?;
+            Ok((operator, keys))
+        })
+        .collect::<Result<Condition, &'static str>>()
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn string_or_array(value: Value) -> Result<Vec<String>, &'static str> {
     match value {
         Value::String(s) => Ok(vec![s]),
         Value::Array(arr) => Ok(arr
             .into_iter()
             .filter_map(|v| v.as_str().map(String::from))
             .collect()),
-        _ => Err(serde::de::Error::custom(
-            "expected string or array of strings",
-        )),
+        _ => Err("expected string or array of strings"),
+    }
+}
+
+/// Like `string_or_array`, but also accepts booleans and numbers, which condition values
+/// are frequently written as (e.g. `"aws:SecureTransport": false`).
+pub fn scalar_or_array(value: Value) -> Result<Vec<String>, &'static str> {
+    fn scalar(value: Value) -> Result<String, &'static str> {
+        match value {
+            Value::String(s) => Ok(s),
+            Value::Bool(b) => Ok(b.to_string()),
+            Value::Number(n) => Ok(n.to_string()),
+            _ => Err("expected a string, boolean or number condition value"),
+        }
+    }
+
+    match value {
+        Value::Array(arr) => arr.into_iter().map(scalar).collect(),
+        value => scalar(value).map(|s| vec![s]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_full_statement_grammar() {
+        let policy: Policy = serde_json::from_value(serde_json::json!({
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Sid": "DenyOutsideVpc",
+                    "Effect": "Deny",
+                    "NotAction": ["iam:*", "sts:*"],
+                    "NotResource": "arn:aws:s3:::public/*",
+                    "Condition": {
+                        "Bool": { "aws:ViaAWSService": false },
+                        "StringNotEquals": { "aws:SourceVpc": ["vpc-1", "vpc-2"] }
+                    }
+                },
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": "arn:aws:iam::123456789012:root", "Service": ["lambda.amazonaws.com"] },
+                    "Action": "sts:AssumeRole"
+                },
+                {
+                    "Effect": "Allow",
+                    "NotPrincipal": "*",
+                    "Action": "s3:GetObject",
+                    "Resource": "*"
+                }
+            ]
+        }))
+        .unwrap();
+
+        let deny = &policy.statements[0];
+        assert_eq!(deny.sid.as_deref(), Some("DenyOutsideVpc"));
+        assert_eq!(deny.effect, Effect::Deny);
+        assert!(deny.action.is_empty());
+        assert_eq!(deny.not_action, vec!["iam:*", "sts:*"]);
+        assert_eq!(deny.not_resource, vec!["arn:aws:s3:::public/*"]);
+        let condition = deny.condition.as_ref().unwrap();
+        assert_eq!(condition["Bool"]["aws:ViaAWSService"], vec!["false"]);
+        assert_eq!(
+            condition["StringNotEquals"]["aws:SourceVpc"],
+            vec!["vpc-1", "vpc-2"]
+        );
+
+        let trust = &policy.statements[1];
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            "AWS".to_string(),
+            vec!["arn:aws:iam::123456789012:root".to_string()],
+        );
+        expected.insert(
+            "Service".to_string(),
+            vec!["lambda.amazonaws.com".to_string()],
+        );
+        assert_eq!(trust.principal, Some(Principal::Typed(expected)));
+        assert!(trust.resource.is_empty());
+        assert!(trust.condition.is_none());
+
+        assert_eq!(
+            policy.statements[2].not_principal,
+            Some(Principal::Wildcard)
+        );
+    }
+
+    #[test]
+    fn test_serialize_round_trip_omits_absent_elements() {
+        let statement = PolicyStatement {
+            effect: Effect::Allow,
+            principal: Some(Principal::Wildcard),
+            action: vec!["s3:GetObject".to_string()],
+            resource: vec!["*".to_string()],
+            ..Default::default()
+        };
+
+        let value = serde_json::to_value(&statement).unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "Effect": "Allow",
+                "Principal": "*",
+                "Action": ["s3:GetObject"],
+                "Resource": ["*"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_inherited_origin() {
+        let origin = PolicyOrigin::Inline {
+            name: "ReadLogs".to_string(),
+        }
+        .inherited_from("developers");
+
+        assert_eq!(origin.to_string(), "group:developers:inline:ReadLogs");
+        assert!(!origin.is_ceiling());
+
+        let value = serde_json::to_value(&origin).unwrap();
+        assert_eq!(value["type"], "group");
+        assert_eq!(value["policy"]["type"], "inline");
+        assert_eq!(
+            serde_json::from_value::<PolicyOrigin>(value).unwrap(),
+            origin
+        );
     }
 }
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// The kinds of IAM principal whose policies can be fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityKind {
+    Role,
+    User,
+    Group,
+}
+
+impl fmt::Display for IdentityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentityKind::Role => write!(f, "role"),
+            IdentityKind::User => write!(f, "user"),
+            IdentityKind::Group => write!(f, "group"),
+        }
+    }
+}
+
+/// An IAM role, user or group, identified by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub kind: IdentityKind,
+    pub name: String,
+    /// The account the identity lives in, when given as an ARN; otherwise the caller's account.
+    pub account_id: Option<String>,
+}
+
+impl Identity {
+    pub fn role(name: impl Into<String>) -> Self {
+        Identity {
+            kind: IdentityKind::Role,
+            name: name.into(),
+            account_id: None,
+        }
+    }
+
+    pub fn user(name: impl Into<String>) -> Self {
+        Identity {
+            kind: IdentityKind::User,
+            name: name.into(),
+            account_id: None,
+        }
+    }
+
+    pub fn group(name: impl Into<String>) -> Self {
+        Identity {
+            kind: IdentityKind::Group,
+            name: name.into(),
+            account_id: None,
+        }
+    }
+
+    /// Parses an IAM ARN such as `arn:aws:iam::123456789012:user/path/alice`. The path is
+    /// dropped, since the IAM API addresses roles, users and groups by name alone; the account
+    /// is kept so identities in other accounts can be fetched through a cross-account role.
+    fn from_arn(arn: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = arn.splitn(6, ':').collect();
+        if parts.len() != 6 || parts[2] != "iam" {
+            return Err(format!("Not an IAM ARN: {}", arn));
+        }
+
+        let (kind, path) = parts[5]
+            .split_once('/')
+            .ok_or_else(|| format!("Missing resource name in {}", arn))?;
+        let name = path.rsplit('/').next().unwrap_or_default();
+        if name.is_empty() {
+            return Err(format!("Missing resource name in {}", arn));
+        }
+
+        let identity = match kind {
+            "role" => Identity::role(name),
+            "user" => Identity::user(name),
+            "group" => Identity::group(name),
+            _ => return Err(format!("Expected a role, user or group ARN: {}", arn)),
+        };
+        Ok(Identity {
+            account_id: Some(parts[4].to_string()).filter(|account_id| !account_id.is_empty()),
+            ..identity
+        })
+    }
+}
+
+/// Roles are shown by their bare name, as they were before users and groups were supported;
+/// users and groups carry their kind so they cannot be mistaken for a role of the same name.
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            IdentityKind::Role => write!(f, "{}", self.name),
+            kind => write!(f, "{}:{}", kind, self.name),
+        }
+    }
+}
+
+/// Parses an IAM ARN, `role:NAME`, `user:NAME`, `group:NAME`, or a bare role name.
+impl FromStr for Identity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("arn:") {
+            return Identity::from_arn(s);
+        }
+
+        let identity = match s.split_once(':') {
+            Some(("role", name)) => Identity::role(name),
+            Some(("user", name)) => Identity::user(name),
+            Some(("group", name)) => Identity::group(name),
+            _ => Identity::role(s),
+        };
+
+        if identity.name.is_empty() {
+            return Err(format!("Missing {} name in {:?}", identity.kind, s));
+        }
+        Ok(identity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_identities() {
+        assert_eq!("app".parse(), Ok(Identity::role("app")));
+        assert_eq!("user:alice".parse(), Ok(Identity::user("alice")));
+        assert_eq!("group:admins".parse(), Ok(Identity::group("admins")));
+
+        let user: Identity = "arn:aws:iam::123456789012:user/engineering/alice"
+            .parse()
+            .unwrap();
+        assert_eq!(user.kind, IdentityKind::User);
+        assert_eq!(user.name, "alice");
+        assert_eq!(user.account_id.as_deref(), Some("123456789012"));
+
+        let role: Identity = "arn:aws-cn:iam::210987654321:role/service-role/app"
+            .parse()
+            .unwrap();
+        assert_eq!(role.kind, IdentityKind::Role);
+        assert_eq!(role.name, "app");
+        assert_eq!(role.account_id.as_deref(), Some("210987654321"));
+
+        assert!("user:".parse::<Identity>().is_err());
+        assert!("arn:aws:s3:::bucket".parse::<Identity>().is_err());
+        assert!("arn:aws:iam::123456789012:policy/Read"
+            .parse::<Identity>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_display_identities() {
+        assert_eq!(Identity::role("app").to_string(), "app");
+        assert_eq!(Identity::user("alice").to_string(), "user:alice");
+        assert_eq!(Identity::group("admins").to_string(), "group:admins");
+    }
+}
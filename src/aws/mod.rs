@@ -1,3 +1,7 @@
+pub mod cache;
 pub mod client;
 pub mod iam;
+pub mod identity;
+pub mod paginate;
 pub mod policy;
+pub mod throttle;
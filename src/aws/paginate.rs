@@ -0,0 +1,108 @@
+use std::future::Future;
+
+/// Upper bound on the number of pages followed for a single IAM list call.
+///
+/// IAM returns at most 1000 items per page, so hitting this bound means something is wrong
+/// (for example a marker that never advances) rather than a legitimately large listing.
+pub const MAX_PAGES: usize = 100;
+
+/// A single page of results from an IAM list call.
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The marker to request the next page with, or `None` when the listing is complete.
+    pub next_marker: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from the `IsTruncated`/`Marker` pair returned by IAM list calls.
+    pub fn new(items: Vec<T>, is_truncated: bool, marker: Option<String>) -> Self {
+        Page {
+            items,
+            next_marker: if is_truncated { marker } else { None },
+        }
+    }
+}
+
+/// Follows the markers of an IAM list call until the listing is complete.
+///
+/// # Arguments
+///
+/// * `operation` - The name of the IAM operation, used in the error message.
+/// * `fetch_page` - A closure fetching the page starting at the given marker.
+///
+/// # Returns
+///
+/// A Result containing the items of all pages, or an Error if a page fails or more than
+/// `MAX_PAGES` pages are returned.
+pub async fn paginate<T, F, Fut>(
+    operation: &str,
+    mut fetch_page: F,
+) -> Result<Vec<T>, Box<dyn std::error::Error + Send + Sync>>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<Page<T>, Box<dyn std::error::Error + Send + Sync>>>,
+{
+    let mut items = Vec::new();
+    let mut marker = None;
+
+    for _ in 0..MAX_PAGES {
+        let page = fetch_page(marker).await?;
+        items.extend(page.items);
+
+        match page.next_marker {
+            Some(next_marker) => marker = Some(next_marker),
+            None => return Ok(items),
+        }
+    }
+
+    Err(format!(
+        "{} returned more than {} pages of results; refusing to continue",
+        operation, MAX_PAGES
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_paginate_follows_markers() {
+        let items = paginate("ListTest", |marker| async move {
+            let page = match marker.as_deref() {
+                None => Page::new(vec![1, 2], true, Some("page-2".to_string())),
+                Some("page-2") => Page::new(vec![3], true, Some("page-3".to_string())),
+                Some("page-3") => Page::new(vec![4], false, None),
+                Some(other) => return Err(format!("unexpected marker {}", other).into()),
+            };
+            Ok(page)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(items, vec![1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_paginate_ignores_marker_when_not_truncated() {
+        let items = paginate("ListTest", |_| async {
+            Ok(Page::new(vec!["a"], false, Some("stale".to_string())))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(items, vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_paginate_errors_when_bound_is_hit() {
+        let result: Result<Vec<u8>, _> = paginate("ListTest", |_| async {
+            Ok(Page::new(vec![0], true, Some("same".to_string())))
+        })
+        .await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("ListTest"));
+        assert!(error.contains(&MAX_PAGES.to_string()));
+    }
+}
@@ -1,54 +1,315 @@
-use crate::aws::iam::Policy;
-use aws_sdk_iam::Client as IamClient;
+use crate::aws::client::AwsClient;
+use crate::aws::iam::{IdentityPolicies, Policy, PolicyOrigin};
+use crate::aws::identity::{Identity, IdentityKind};
+use crate::aws::paginate::{paginate, Page};
+use crate::source::PolicySource;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use serde_json::Value;
+use std::borrow::Cow;
 use url::form_urlencoded;
 
-/// Fetches the policy statements for a given IAM Role.
+/// Fetches the policies attached directly to an IAM role, user or group.
+///
+/// Both the managed policies attached to the identity and the inline policies embedded in it
+/// are returned, each tagged with its `PolicyOrigin`. Policies a user inherits from its groups
+/// are not included; see `IamPolicySource`.
 ///
 /// # Arguments
 ///
-/// * `iam` - A reference to an IamClient instance.
-/// * `account_id` - A string slice containing the AWS account ID.
-/// * `role_name` - A string slice containing the IAM Role name.
+/// * `client` - The AwsClient to send the requests with.
+/// * `identity` - The IAM role, user or group.
 ///
 /// # Returns
 ///
 /// A Result containing a Vec of Policy, or an Error.
-pub async fn fetch_role_policy(
-    iam: &IamClient,
-    account_id: &str,
-    role_name: &str,
+pub async fn fetch_direct_policies(
+    client: &AwsClient,
+    identity: &Identity,
 ) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
-    let attached_policy_arns = fetch_attached_policy_arns(iam, role_name).await?;
-    let inline_policy_arns = fetch_inline_policy_arns(iam, account_id, role_name).await?;
+    let (attached_policy_arns, inline_policy_names) = futures::try_join!(
+        fetch_attached_policy_arns(client, identity),
+        fetch_inline_policy_names(client, identity),
+    )?;
 
-    let policy_arns = attached_policy_arns
-        .into_iter()
-        .chain(inline_policy_arns.into_iter());
+    // `buffered` keeps the documents in the order IAM listed them.
+    let mut policies: Vec<Policy> = stream::iter(attached_policy_arns)
+        .map(|policy_arn| async move { fetch_json_policy_document(client, &policy_arn).await })
+        .buffered(client.concurrency())
+        .try_collect()
+        .await?;
+
+    let inline_policies: Vec<Policy> = stream::iter(inline_policy_names)
+        .map(|policy_name| async move { fetch_inline_policy(client, identity, &policy_name).await })
+        .buffered(client.concurrency())
+        .try_collect()
+        .await?;
+    policies.extend(inline_policies);
+
+    Ok(policies)
+}
+
+/// Reads identities through the IAM API. Identities given as ARNs in another account are read
+/// through the client's cross-account role.
+pub struct IamPolicySource {
+    client: AwsClient,
+}
+
+impl IamPolicySource {
+    pub fn new(client: AwsClient) -> Self {
+        IamPolicySource { client }
+    }
+
+    /// Returns the identity, which IAM cannot guess, and a client for its account.
+    async fn resolve<'a>(
+        &self,
+        identity: Option<&'a Identity>,
+    ) -> Result<(&'a Identity, Cow<'_, AwsClient>), Box<dyn std::error::Error + Send + Sync>> {
+        let identity = identity.ok_or("a role, user or group is required to read from IAM")?;
+        let client = self
+            .client
+            .for_account(identity.account_id.as_deref())
+            .await?;
+        Ok((identity, client))
+    }
+}
+
+#[async_trait]
+impl PolicySource for IamPolicySource {
+    async fn identity_policies(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        let (identity, client) = self.resolve(identity).await?;
+        fetch_identity_policy_list(&client, identity).await
+    }
+
+    async fn permissions_boundary(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        let (identity, client) = self.resolve(identity).await?;
+        fetch_permissions_boundary(&client, identity).await
+    }
+
+    async fn trust_policy(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        let (identity, client) = self.resolve(identity).await?;
+        match identity.kind {
+            IdentityKind::Role => fetch_trust_policy(&client, &identity.name).await.map(Some),
+            IdentityKind::User | IdentityKind::Group => Ok(None),
+        }
+    }
+
+    /// Assumes the cross-account role, if needed, only once for both the policies and the
+    /// boundary.
+    async fn load(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+        let (identity, client) = self.resolve(identity).await?;
+        Ok(IdentityPolicies {
+            policies: fetch_identity_policy_list(&client, identity).await?,
+            permissions_boundary: fetch_permissions_boundary(&client, identity).await?,
+        })
+    }
+}
 
-    let mut policies = Vec::<Policy>::new();
+/// Fetches an identity's own policies and, for a user, the policies it inherits from each of
+/// its groups.
+async fn fetch_identity_policy_list(
+    client: &AwsClient,
+    identity: &Identity,
+) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut policies = fetch_direct_policies(client, identity).await?;
+
+    if identity.kind == IdentityKind::User {
+        let groups: Vec<(Identity, Vec<Policy>)> =
+            stream::iter(fetch_group_names_for_user(client, &identity.name).await?)
+                .map(|group_name| async move {
+                    let group = Identity::group(group_name);
+                    let group_policies = fetch_direct_policies(client, &group).await?;
+                    Ok::<_, Box<dyn std::error::Error + Send + Sync>>((group, group_policies))
+                })
+                .buffered(client.concurrency())
+                .try_collect()
+                .await?;
 
-    for policy_arn in policy_arns {
-        let json_document = fetch_json_policy_document(iam, &policy_arn).await?;
-        policies.push(json_document);
+        for (group, group_policies) in groups {
+            for mut policy in group_policies {
+                policy.origin = policy.origin.inherited_from(&group.name);
+                policies.push(policy);
+            }
+        }
     }
 
     Ok(policies)
 }
 
-async fn fetch_attached_policy_arns(
-    iam: &IamClient,
+/// Fetches the trust policy of a role through `GetRole`.
+async fn fetch_trust_policy(
+    client: &AwsClient,
     role_name: &str,
+) -> Result<Policy, Box<dyn std::error::Error + Send + Sync>> {
+    let document = client
+        .throttle
+        .send("GetRole", || {
+            client.iam.get_role().role_name(role_name).send()
+        })
+        .await?
+        .role
+        .and_then(|role| role.assume_role_policy_document)
+        .ok_or("Failed to get the role's trust policy")?;
+
+    decode_policy_document(&document)
+}
+
+/// Fetches the managed policy set as the permissions boundary of a role (through `GetRole`) or
+/// a user (through `GetUser`). Groups cannot have a permissions boundary.
+async fn fetch_permissions_boundary(
+    client: &AwsClient,
+    identity: &Identity,
+) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    let boundary = match identity.kind {
+        IdentityKind::Role => {
+            let role_output = client
+                .throttle
+                .send("GetRole", || {
+                    client.iam.get_role().role_name(&identity.name).send()
+                })
+                .await?;
+            role_output.role.and_then(|role| role.permissions_boundary)
+        }
+        IdentityKind::User => {
+            let user_output = client
+                .throttle
+                .send("GetUser", || {
+                    client.iam.get_user().user_name(&identity.name).send()
+                })
+                .await?;
+            user_output.user.and_then(|user| user.permissions_boundary)
+        }
+        IdentityKind::Group => None,
+    };
+
+    match boundary.and_then(|boundary| boundary.permissions_boundary_arn) {
+        Some(arn) => {
+            let mut policy = fetch_json_policy_document(client, &arn).await?;
+            policy.origin = policy.origin.into_permissions_boundary();
+            Ok(Some(policy))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn fetch_group_names_for_user(
+    client: &AwsClient,
+    user_name: &str,
 ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
-    let attached_policies = iam
-        .list_attached_role_policies()
-        .role_name(role_name)
-        .send()
-        .await?;
+    let groups = paginate("ListGroupsForUser", |marker| async move {
+        let output = client
+            .throttle
+            .send("ListGroupsForUser", || {
+                client
+                    .iam
+                    .list_groups_for_user()
+                    .user_name(user_name)
+                    .set_marker(marker.clone())
+                    .send()
+            })
+            .await?;
+
+        Ok(Page::new(
+            output.groups.unwrap_or_default(),
+            output.is_truncated,
+            output.marker,
+        ))
+    })
+    .await?;
+
+    Ok(groups
+        .into_iter()
+        .filter_map(|group| group.group_name)
+        .collect())
+}
+
+async fn fetch_attached_policy_arns(
+    client: &AwsClient,
+    identity: &Identity,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let name = identity.name.as_str();
+    let attached_policies = match identity.kind {
+        IdentityKind::Role => {
+            paginate("ListAttachedRolePolicies", |marker| async move {
+                let output = client
+                    .throttle
+                    .send("ListAttachedRolePolicies", || {
+                        client
+                            .iam
+                            .list_attached_role_policies()
+                            .role_name(name)
+                            .set_marker(marker.clone())
+                            .send()
+                    })
+                    .await?;
+
+                Ok(Page::new(
+                    output.attached_policies.unwrap_or_default(),
+                    output.is_truncated,
+                    output.marker,
+                ))
+            })
+            .await?
+        }
+        IdentityKind::User => {
+            paginate("ListAttachedUserPolicies", |marker| async move {
+                let output = client
+                    .throttle
+                    .send("ListAttachedUserPolicies", || {
+                        client
+                            .iam
+                            .list_attached_user_policies()
+                            .user_name(name)
+                            .set_marker(marker.clone())
+                            .send()
+                    })
+                    .await?;
+
+                Ok(Page::new(
+                    output.attached_policies.unwrap_or_default(),
+                    output.is_truncated,
+                    output.marker,
+                ))
+            })
+            .await?
+        }
+        IdentityKind::Group => {
+            paginate("ListAttachedGroupPolicies", |marker| async move {
+                let output = client
+                    .throttle
+                    .send("ListAttachedGroupPolicies", || {
+                        client
+                            .iam
+                            .list_attached_group_policies()
+                            .group_name(name)
+                            .set_marker(marker.clone())
+                            .send()
+                    })
+                    .await?;
+
+                Ok(Page::new(
+                    output.attached_policies.unwrap_or_default(),
+                    output.is_truncated,
+                    output.marker,
+                ))
+            })
+            .await?
+        }
+    };
 
     let policy_arns: Vec<String> = attached_policies
-        .attached_policies
-        .unwrap_or_default()
         .into_iter()
         .filter_map(|policy| policy.policy_arn)
         .collect();
@@ -56,34 +317,165 @@ async fn fetch_attached_policy_arns(
     Ok(policy_arns)
 }
 
-async fn fetch_inline_policy_arns(
-    iam: &IamClient,
-    account_id: &str,
-    role_name: &str,
+async fn fetch_inline_policy_names(
+    client: &AwsClient,
+    identity: &Identity,
 ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
-    let inline_policies = iam.list_role_policies().role_name(role_name).send().await?;
+    let name = identity.name.as_str();
+    match identity.kind {
+        IdentityKind::Role => {
+            paginate("ListRolePolicies", |marker| async move {
+                let output = client
+                    .throttle
+                    .send("ListRolePolicies", || {
+                        client
+                            .iam
+                            .list_role_policies()
+                            .role_name(name)
+                            .set_marker(marker.clone())
+                            .send()
+                    })
+                    .await?;
 
-    let inline_policy_arns: Vec<String> = inline_policies
-        .policy_names
-        .unwrap_or_default()
-        .into_iter()
-        .map(|policy_name| {
-            format!(
-                "arn:aws:iam::{}:role/{}/{}",
-                account_id, role_name, policy_name
-            )
-        })
-        .collect();
+                Ok(Page::new(
+                    output.policy_names.unwrap_or_default(),
+                    output.is_truncated,
+                    output.marker,
+                ))
+            })
+            .await
+        }
+        IdentityKind::User => {
+            paginate("ListUserPolicies", |marker| async move {
+                let output = client
+                    .throttle
+                    .send("ListUserPolicies", || {
+                        client
+                            .iam
+                            .list_user_policies()
+                            .user_name(name)
+                            .set_marker(marker.clone())
+                            .send()
+                    })
+                    .await?;
+
+                Ok(Page::new(
+                    output.policy_names.unwrap_or_default(),
+                    output.is_truncated,
+                    output.marker,
+                ))
+            })
+            .await
+        }
+        IdentityKind::Group => {
+            paginate("ListGroupPolicies", |marker| async move {
+                let output = client
+                    .throttle
+                    .send("ListGroupPolicies", || {
+                        client
+                            .iam
+                            .list_group_policies()
+                            .group_name(name)
+                            .set_marker(marker.clone())
+                            .send()
+                    })
+                    .await?;
 
-    Ok(inline_policy_arns)
+                Ok(Page::new(
+                    output.policy_names.unwrap_or_default(),
+                    output.is_truncated,
+                    output.marker,
+                ))
+            })
+            .await
+        }
+    }
+}
+
+/// Fetches an inline policy embedded in an identity through `GetRolePolicy`, `GetUserPolicy`
+/// or `GetGroupPolicy`.
+///
+/// Inline policies have no ARN and no versions, so they cannot go through
+/// `fetch_policy_document`.
+async fn fetch_inline_policy(
+    client: &AwsClient,
+    identity: &Identity,
+    policy_name: &str,
+) -> Result<Policy, Box<dyn std::error::Error + Send + Sync>> {
+    let name = identity.name.as_str();
+    let document = match identity.kind {
+        IdentityKind::Role => {
+            client
+                .throttle
+                .send("GetRolePolicy", || {
+                    client
+                        .iam
+                        .get_role_policy()
+                        .role_name(name)
+                        .policy_name(policy_name)
+                        .send()
+                })
+                .await?
+                .policy_document
+        }
+        IdentityKind::User => {
+            client
+                .throttle
+                .send("GetUserPolicy", || {
+                    client
+                        .iam
+                        .get_user_policy()
+                        .user_name(name)
+                        .policy_name(policy_name)
+                        .send()
+                })
+                .await?
+                .policy_document
+        }
+        IdentityKind::Group => {
+            client
+                .throttle
+                .send("GetGroupPolicy", || {
+                    client
+                        .iam
+                        .get_group_policy()
+                        .group_name(name)
+                        .policy_name(policy_name)
+                        .send()
+                })
+                .await?
+                .policy_document
+        }
+    }
+    .ok_or("Failed to get inline policy document")?;
+
+    let mut policy = decode_policy_document(&document)?;
+    policy.origin = PolicyOrigin::Inline {
+        name: policy_name.to_string(),
+    };
+
+    Ok(policy)
 }
 
 async fn fetch_json_policy_document(
-    iam: &IamClient,
+    client: &AwsClient,
     policy_arn: &str,
 ) -> Result<Policy, Box<dyn std::error::Error + Send + Sync>> {
-    let document = fetch_policy_document(iam, policy_arn).await?;
+    let (version_id, document) = fetch_policy_document(client, policy_arn).await?;
+
+    let mut policy = decode_policy_document(&document)?;
+    policy.origin = PolicyOrigin::Managed {
+        arn: policy_arn.to_string(),
+        version_id: Some(version_id),
+    };
 
+    Ok(policy)
+}
+
+/// Decodes a URL-encoded policy document, as returned by the IAM API, into a `Policy`.
+pub fn decode_policy_document(
+    document: &str,
+) -> Result<Policy, Box<dyn std::error::Error + Send + Sync>> {
     let decoded_document: String = form_urlencoded::parse(document.as_bytes())
         .map(|(key, _)| key)
         .collect();
@@ -96,55 +488,85 @@ async fn fetch_json_policy_document(
 
 /// Fetches the policy document for a given policy ARN, focusing on the default policy version.
 ///
+/// The default version ID is looked up with `GetPolicy`; the document itself comes from the
+/// client's cache when that version is cached, and from `GetPolicyVersion` otherwise.
+///
 /// # Arguments
 ///
-/// * `iam` - A reference to an IamClient instance.
+/// * `client` - The AwsClient to send the requests with.
 /// * `policy_arn` - A string slice containing the policy ARN.
 ///
 /// # Returns
 ///
-/// A Result containing a String representing the policy document, or an Error.
+/// A Result containing the default version ID and the policy document, or an Error.
 async fn fetch_policy_document(
-    iam: &IamClient,
+    client: &AwsClient,
     policy_arn: &str,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let policy_versions_output = iam
-        .list_policy_versions()
-        .policy_arn(policy_arn)
-        .send()
-        .await?;
-
-    let policy_versions = policy_versions_output.versions.unwrap_or_default();
+) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+    let policy_version_id = client
+        .throttle
+        .send("GetPolicy", || {
+            client.iam.get_policy().policy_arn(policy_arn).send()
+        })
+        .await?
+        .policy
+        .and_then(|policy| policy.default_version_id)
+        .ok_or("Failed to find default policy version")?;
 
-    let default_policy_version = policy_versions
-        .iter()
-        .find(|version| version.is_default_version);
+    if let Some(document) = client
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.get(policy_arn, &policy_version_id))
+    {
+        return Ok((policy_version_id, document));
+    }
 
-    match default_policy_version {
-        Some(version) => {
-            let policy_version_id = version.version_id.as_ref().ok_or_else(|| {
-                Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Failed to get policy version ID",
-                ))
-            })?;
-            let policy_version_output = iam
+    let document = client
+        .throttle
+        .send("GetPolicyVersion", || {
+            client
+                .iam
                 .get_policy_version()
                 .policy_arn(policy_arn)
-                .version_id(policy_version_id)
+                .version_id(&policy_version_id)
                 .send()
-                .await?;
-
-            let document = policy_version_output
-                .policy_version
-                .and_then(|pv| pv.document)
-                .ok_or("Failed to get policy document")?;
+        })
+        .await?
+        .policy_version
+        .and_then(|pv| pv.document)
+        .ok_or("Failed to get policy document")?;
 
-            Ok(document)
+    if let Some(cache) = &client.cache {
+        if let Err(e) = cache.put(policy_arn, &policy_version_id, &document) {
+            eprintln!(
+                "Could not cache {} in {}: {}",
+                policy_arn,
+                cache.dir().display(),
+                e
+            );
         }
-        None => Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Failed to find default policy version",
-        ))),
+    }
+
+    Ok((policy_version_id, document))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_policy_document() {
+        let document = "%7B%22Version%22%3A%222012-10-17%22%2C%22Statement%22%3A%5B%7B%22Effect%22%3A%22Allow%22%2C%22Action%22%3A%22s3%3AGetObject%22%2C%22Resource%22%3A%22arn%3Aaws%3As3%3A%3A%3Amy-bucket%2F%2A%22%7D%5D%7D";
+
+        let policy = decode_policy_document(document).unwrap();
+
+        assert_eq!(policy.version, "2012-10-17");
+        assert_eq!(policy.statements.len(), 1);
+        assert_eq!(policy.statements[0].action, vec!["s3:GetObject"]);
+        assert_eq!(
+            policy.statements[0].resource,
+            vec!["arn:aws:s3:::my-bucket/*"]
+        );
+        assert_eq!(policy.origin, PolicyOrigin::Unknown);
     }
 }
@@ -0,0 +1,170 @@
+use aws_sdk_iam::types::SdkError;
+use aws_smithy_types::retry::ProvideErrorKind;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+
+/// The delay after the first throttling error, in milliseconds.
+const INITIAL_BACKOFF_MS: u64 = 100;
+
+/// The longest delay between requests, in milliseconds.
+const MAX_BACKOFF_MS: u64 = 20_000;
+
+/// How often a throttled request is sent before giving up.
+const MAX_ATTEMPTS: u32 = 8;
+
+/// The error codes IAM and STS use when a caller exceeds its request rate.
+const THROTTLING_CODES: &[&str] = &[
+    "Throttling",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+    "TooManyRequestsException",
+];
+
+/// Limits the requests of a client in flight at once, and backs off on top of the SDK's own
+/// retries when IAM throttles them.
+///
+/// Each throttling error doubles the delay before every request, and each success halves it
+/// again, so concurrent fetches slow down together while IAM pushes back and speed up once it
+/// stops.
+#[derive(Debug)]
+pub struct Throttle {
+    permits: Semaphore,
+    delay_ms: AtomicU64,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing `concurrency` requests at once (at least one).
+    pub fn new(concurrency: usize) -> Self {
+        Throttle {
+            permits: Semaphore::new(concurrency.max(1)),
+            delay_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Sends a request once a permit is free, waiting for the current delay first and retrying
+    /// it while it is throttled.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The name of the IAM operation, used in the error message.
+    /// * `send` - A closure building and sending the request.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the response, or an Error if the request fails for another reason
+    /// or is still throttled after `MAX_ATTEMPTS` attempts.
+    pub async fn send<T, E, F, Fut>(
+        &self,
+        operation: &str,
+        mut send: F,
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E>>>,
+        E: ProvideErrorKind + std::error::Error + Send + Sync + 'static,
+    {
+        for _ in 0..MAX_ATTEMPTS {
+            let delay = self.delay_ms.load(Ordering::Relaxed);
+            if delay > 0 {
+                tokio::time::sleep(Duration::from_millis(delay + jitter(delay))).await;
+            }
+
+            let _permit = self.permits.acquire().await?;
+            match send().await {
+                Ok(output) => {
+                    self.record_success();
+                    return Ok(output);
+                }
+                Err(SdkError::ServiceError(error)) if is_throttling_code(error.err().code()) => {
+                    self.record_throttling();
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        Err(format!(
+            "{} was still throttled after {} attempts; try a lower --concurrency",
+            operation, MAX_ATTEMPTS
+        )
+        .into())
+    }
+
+    fn record_throttling(&self) {
+        let _ = self
+            .delay_ms
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |delay| {
+                Some((delay * 2).clamp(INITIAL_BACKOFF_MS, MAX_BACKOFF_MS))
+            });
+    }
+
+    fn record_success(&self) {
+        let _ =
+            self.delay_ms
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |delay| {
+                    match delay / 2 {
+                        halved if halved < INITIAL_BACKOFF_MS => Some(0),
+                        halved => Some(halved),
+                    }
+                });
+    }
+}
+
+fn is_throttling_code(code: Option<&str>) -> bool {
+    code.is_some_and(|code| THROTTLING_CODES.contains(&code))
+}
+
+/// Up to half of `delay` at random, so that throttled requests do not all retry at once.
+fn jitter(delay: u64) -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos() as u64)
+        .unwrap_or(0);
+    nanos % (delay / 2 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_adapts_to_throttling() {
+        let throttle = Throttle::new(1);
+
+        throttle.record_throttling();
+        assert_eq!(
+            throttle.delay_ms.load(Ordering::Relaxed),
+            INITIAL_BACKOFF_MS
+        );
+        throttle.record_throttling();
+        throttle.record_throttling();
+        assert_eq!(
+            throttle.delay_ms.load(Ordering::Relaxed),
+            4 * INITIAL_BACKOFF_MS
+        );
+
+        for _ in 0..20 {
+            throttle.record_throttling();
+        }
+        assert_eq!(throttle.delay_ms.load(Ordering::Relaxed), MAX_BACKOFF_MS);
+
+        throttle.record_success();
+        assert_eq!(
+            throttle.delay_ms.load(Ordering::Relaxed),
+            MAX_BACKOFF_MS / 2
+        );
+        for _ in 0..20 {
+            throttle.record_success();
+        }
+        assert_eq!(throttle.delay_ms.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_is_throttling_code() {
+        assert!(is_throttling_code(Some("Throttling")));
+        assert!(is_throttling_code(Some("RequestLimitExceeded")));
+        assert!(!is_throttling_code(Some("NoSuchEntity")));
+        assert!(!is_throttling_code(None));
+    }
+}
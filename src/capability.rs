@@ -1,71 +1,320 @@
 use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
-use crate::aws::iam::PolicyStatement;
+use crate::aws::iam::{Effect, PolicyStatement};
+use crate::catalog::ActionCatalog;
+use crate::wildcard::PatternSet;
 use crate::{aws::iam::Policy, output::format::OutputSerializable};
 
-#[derive(Hash, PartialEq, Eq, Debug)]
+/// Whether a granted capability survives the explicit denies in the same set of policies and
+/// the permissions boundary. Variants are ordered from least to most restricted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CapabilityStatus {
+    Allowed,
+    /// A deny overlaps the grant, or covers it only under a condition; or the permissions
+    /// boundary allows only part of it.
+    PartiallyDenied,
+    /// An unconditional deny covers every action and resource of the grant, or the
+    /// permissions boundary allows none of it.
+    Denied,
+}
+
+impl fmt::Display for CapabilityStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapabilityStatus::Allowed => write!(f, "allowed"),
+            CapabilityStatus::Denied => write!(f, "denied"),
+            CapabilityStatus::PartiallyDenied => write!(f, "partially-denied"),
+        }
+    }
+}
+
+/// Whether a row is a capability the identity has, or the upper limit a ceiling puts on its
+/// capabilities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilityKind {
+    Grant,
+    /// Allowed by a permissions boundary, which grants nothing by itself.
+    Ceiling,
+}
+
+impl fmt::Display for CapabilityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapabilityKind::Grant => write!(f, "grant"),
+            CapabilityKind::Ceiling => write!(f, "ceiling"),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct CapabilityRow {
     pub resource: String,
     pub action: String,
+    /// The statement's `Condition` block as compact JSON, or empty if the grant is
+    /// unconditional.
+    pub condition: String,
+    pub kind: CapabilityKind,
+    pub status: CapabilityStatus,
+    /// The policy the capability was granted by, e.g. `managed:<arn>` or `inline:<name>`.
+    pub source: String,
+    /// The ceiling (permissions boundary or service control policy) that restricted the
+    /// capability, or empty if none did.
+    pub restricted_by: String,
+}
+
+// Two rows describe the same capability regardless of which policy granted it or how it is
+// affected by denies.
+impl PartialEq for CapabilityRow {
+    fn eq(&self, other: &Self) -> bool {
+        self.resource == other.resource
+            && self.action == other.action
+            && self.condition == other.condition
+    }
+}
+
+impl Eq for CapabilityRow {}
+
+impl Hash for CapabilityRow {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.resource.hash(state);
+        self.action.hash(state);
+        self.condition.hash(state);
+    }
 }
 
 impl OutputSerializable for CapabilityRow {
     fn csv_header() -> Vec<&'static str> {
-        vec!["Resource", "Action"]
+        vec![
+            "Resource",
+            "Action",
+            "Condition",
+            "Kind",
+            "Status",
+            "Source",
+            "Restricted By",
+        ]
     }
 
     fn csv_record(&self) -> Vec<String> {
-        vec![self.resource.clone(), self.action.clone()]
+        vec![
+            self.resource.clone(),
+            self.action.clone(),
+            self.condition.clone(),
+            self.kind.to_string(),
+            self.status.to_string(),
+            self.source.clone(),
+            self.restricted_by.clone(),
+        ]
     }
 
     fn to_json_value(&self) -> serde_json::Value {
         json!({
             "resource": self.resource,
-            "action": self.action
+            "action": self.action,
+            "condition": self.condition,
+            "kind": self.kind.to_string(),
+            "status": self.status.to_string(),
+            "source": self.source,
+            "restricted_by": self.restricted_by
         })
     }
 }
 
+impl CapabilityRow {
+    /// The set of actions the row grants, undoing the `NotAction(...)` rendering.
+    pub fn action_set(&self) -> PatternSet {
+        parse_target(&self.action, "NotAction")
+    }
+
+    /// The set of resources the row grants, undoing the `NotResource(...)` rendering.
+    pub fn resource_set(&self) -> PatternSet {
+        parse_target(&self.resource, "NotResource")
+    }
+}
+
 /// Represents a row in the comparison table.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct CapabilityComparisonRow {
     pub resource: String,
     pub action: String,
-    pub has_capability1: bool,
-    pub has_capability2: bool,
+    pub condition: String,
+    /// The names of the compared roles, in the order they were given.
+    pub roles: Vec<String>,
+    /// Whether each role in `roles` has the capability.
+    pub has_capability: Vec<bool>,
+}
+
+impl CapabilityComparisonRow {
+    /// The CSV header for a comparison of `roles`: the fixed columns followed by one column
+    /// per role, named after it.
+    pub fn header(roles: &[String]) -> Vec<String> {
+        Self::csv_header()
+            .into_iter()
+            .map(String::from)
+            .chain(roles.iter().cloned())
+            .collect()
+    }
 }
 
 impl OutputSerializable for CapabilityComparisonRow {
+    /// The fixed columns only; use `CapabilityComparisonRow::header` for the role columns.
     fn csv_header() -> Vec<&'static str> {
-        vec!["Resource", "Action", "Role1", "Role2"]
+        vec!["Resource", "Action", "Condition"]
     }
 
     fn csv_record(&self) -> Vec<String> {
         vec![
             self.resource.clone(),
             self.action.clone(),
-            self.has_capability1.to_string(),
-            self.has_capability2.to_string(),
+            self.condition.clone(),
         ]
+        .into_iter()
+        .chain(self.has_capability.iter().map(bool::to_string))
+        .collect()
     }
 
+    /// The per-role flags are nested under `roles`, so a role named like a fixed field (e.g.
+    /// `action`) cannot overwrite it.
     fn to_json_value(&self) -> serde_json::Value {
+        let roles: serde_json::Map<String, serde_json::Value> = self
+            .roles
+            .iter()
+            .zip(&self.has_capability)
+            .map(|(role, has_capability)| (role.clone(), json!(has_capability)))
+            .collect();
         json!({
             "resource": self.resource,
             "action": self.action,
-            "role1": self.has_capability1,
-            "role2": self.has_capability2
+            "condition": self.condition,
+            "roles": roles
         })
     }
 }
 
-pub fn extract_capabilities_from_policies(policies: Vec<Policy>) -> Vec<CapabilityRow> {
+/// How a capability of one role relates to the capabilities of another, treating actions and
+/// resources as wildcard patterns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Relation {
+    OnlyInRole1,
+    OnlyInRole2,
+    /// Role1's grant covers role2's, but not the other way around.
+    Role1Broader,
+    /// Role2's grant covers role1's, but not the other way around.
+    Role2Broader,
+    Equivalent,
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Relation::OnlyInRole1 => write!(f, "only-in-role1"),
+            Relation::OnlyInRole2 => write!(f, "only-in-role2"),
+            Relation::Role1Broader => write!(f, "role1-broader"),
+            Relation::Role2Broader => write!(f, "role2-broader"),
+            Relation::Equivalent => write!(f, "equivalent"),
+        }
+    }
+}
+
+/// Represents a row in the semantic comparison table, pairing a grant of role1 with the
+/// related grant of role2. The side a row is missing from is left empty.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SemanticComparisonRow {
+    pub resource1: String,
+    pub action1: String,
+    pub resource2: String,
+    pub action2: String,
+    /// The condition of the narrower grant (of either grant, for equivalent ones).
+    pub condition: String,
+    pub relation: Relation,
+}
+
+impl OutputSerializable for SemanticComparisonRow {
+    fn csv_header() -> Vec<&'static str> {
+        vec![
+            "Role1 Resource",
+            "Role1 Action",
+            "Role2 Resource",
+            "Role2 Action",
+            "Condition",
+            "Relation",
+        ]
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.resource1.clone(),
+            self.action1.clone(),
+            self.resource2.clone(),
+            self.action2.clone(),
+            self.condition.clone(),
+            self.relation.to_string(),
+        ]
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        json!({
+            "role1": { "resource": self.resource1, "action": self.action1 },
+            "role2": { "resource": self.resource2, "action": self.action2 },
+            "condition": self.condition,
+            "relation": self.relation.to_string()
+        })
+    }
+}
+
+/// Extracts the capabilities granted by the `Allow` statements of the policies, marking each
+/// one with how the `Deny` statements across all of the policies affect it.
+///
+/// When a `catalog` is given, wildcard actions (and `NotAction`) are expanded into the concrete
+/// actions they grant, so that e.g. `s3:*` and `s3:GetObject` can be compared directly.
+///
+/// Ceilings among the policies (a permissions boundary, service control policies) grant nothing
+/// themselves; instead each capability is restricted to what every ceiling allows, as AWS does,
+/// and records the ceiling that restricted it most.
+pub fn extract_capabilities_from_policies(
+    policies: Vec<Policy>,
+    catalog: Option<&ActionCatalog>,
+) -> Vec<CapabilityRow> {
+    let (ceiling_policies, policies): (Vec<Policy>, Vec<Policy>) = policies
+        .into_iter()
+        .partition(|policy| policy.origin.is_ceiling());
+    let mut ceilings = BTreeMap::<String, Vec<Policy>>::new();
+    for policy in ceiling_policies {
+        ceilings
+            .entry(policy.origin.to_string())
+            .or_default()
+            .push(policy);
+    }
     let mut capabilities: Vec<CapabilityRow> = Vec::new();
 
+    let denies: Vec<PolicyStatement> = policies
+        .iter()
+        .flat_map(|policy| policy.statements.iter())
+        .filter(|statement| statement.effect == Effect::Deny)
+        .cloned()
+        .collect();
+
     for policy in policies {
-        for statement in policy.statements {
-            let capabilities_from_statement = extract_capabilities_from_statement(vec![statement]);
-            capabilities.extend(capabilities_from_statement);
+        let source = policy.origin.to_string();
+        let allows: Vec<PolicyStatement> = policy
+            .statements
+            .into_iter()
+            .filter(|statement| statement.effect == Effect::Allow)
+            .collect();
+        capabilities.extend(extract_capabilities_from_statement(
+            allows, &source, &denies, catalog,
+        ));
+    }
+    for (source, ceiling) in &ceilings {
+        for capability in &mut capabilities {
+            let status = ceiling_status(capability, ceiling);
+            if status > capability.status {
+                capability.status = status;
+                capability.restricted_by = source.clone();
+            }
         }
     }
     capabilities.sort_by(|a, b| match a.resource.cmp(&b.resource) {
@@ -76,19 +325,59 @@ pub fn extract_capabilities_from_policies(policies: Vec<Policy>) -> Vec<Capabili
     capabilities
 }
 
+/// Extracts what a permissions boundary allows, for reporting it next to the capabilities it
+/// restricts. The rows are of the `Ceiling` kind and their `Source` is the boundary itself.
+pub fn extract_boundary_capabilities(
+    boundary: Policy,
+    catalog: Option<&ActionCatalog>,
+) -> Vec<CapabilityRow> {
+    let source = boundary.origin.to_string();
+    let (allows, denies): (Vec<PolicyStatement>, Vec<PolicyStatement>) = boundary
+        .statements
+        .into_iter()
+        .partition(|statement| statement.effect == Effect::Allow);
+
+    let mut capabilities = extract_capabilities_from_statement(allows, &source, &denies, catalog);
+    for capability in &mut capabilities {
+        capability.kind = CapabilityKind::Ceiling;
+    }
+    capabilities
+}
+
 /// Extracts the capabilities from the policy statements.
-fn extract_capabilities_from_statement(statements: Vec<PolicyStatement>) -> Vec<CapabilityRow> {
+///
+/// `NotAction` and `NotResource` are reported as a single `NotAction(...)`/`NotResource(...)`
+/// entry, since they grant everything except the listed values.
+fn extract_capabilities_from_statement(
+    statements: Vec<PolicyStatement>,
+    source: &str,
+    denies: &[PolicyStatement],
+    catalog: Option<&ActionCatalog>,
+) -> Vec<CapabilityRow> {
     let mut capabilities: Vec<CapabilityRow> = Vec::new();
 
     for stmt in statements {
-        let actions = stmt.action;
+        let actions = statement_targets(&stmt.action, &stmt.not_action, "NotAction");
+        let actions = match catalog {
+            Some(catalog) => expand_actions(actions, catalog),
+            None => actions,
+        };
+        let resources = statement_targets(&stmt.resource, &stmt.not_resource, "NotResource");
+        let condition = stmt
+            .condition
+            .map(|condition| serde_json::to_string(&condition).unwrap_or_default())
+            .unwrap_or_default();
 
-        let resources = stmt.resource;
-        for action in &actions {
-            for resource in &resources {
+        for (action, action_set) in &actions {
+            for (resource, resource_set) in &resources {
                 let capability = CapabilityRow {
                     resource: resource.clone(),
                     action: action.clone(),
+                    condition: condition.clone(),
+                    kind: CapabilityKind::Grant,
+                    status: deny_status(&action_set.to_lowercase(), resource_set, denies),
+                    source: source.to_string(),
+                    restricted_by: String::new(),
                 };
                 capabilities.push(capability);
             }
@@ -97,10 +386,136 @@ fn extract_capabilities_from_statement(statements: Vec<PolicyStatement>) -> Vec<
     capabilities
 }
 
+/// Returns the entries of an `Action`/`Resource` element together with the set of values each
+/// one grants. A negated element is rendered as a single `NotAction(a, b)` style entry.
+fn statement_targets(
+    targets: &[String],
+    not_targets: &[String],
+    label: &str,
+) -> Vec<(String, PatternSet)> {
+    if not_targets.is_empty() {
+        targets
+            .iter()
+            .map(|target| (target.clone(), PatternSet::Include(vec![target.clone()])))
+            .collect()
+    } else {
+        vec![(
+            format!("{}({})", label, not_targets.join(", ")),
+            PatternSet::Exclude(not_targets.to_vec()),
+        )]
+    }
+}
+
+/// Parses a target rendered by `statement_targets` back into the set of values it grants.
+fn parse_target(target: &str, label: &str) -> PatternSet {
+    let negated = target
+        .strip_prefix(label)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'));
+
+    match negated {
+        Some(not_targets) => {
+            PatternSet::Exclude(not_targets.split(", ").map(String::from).collect())
+        }
+        None => PatternSet::Include(vec![target.to_string()]),
+    }
+}
+
+/// Replaces each action entry with the concrete catalog actions it grants. Entries that also
+/// match actions of services missing from the catalog (`*`, `NotAction`, an unknown service) are
+/// kept as they are next to their expansion, so those grants are not lost.
+fn expand_actions(
+    actions: Vec<(String, PatternSet)>,
+    catalog: &ActionCatalog,
+) -> Vec<(String, PatternSet)> {
+    let mut expanded = BTreeMap::new();
+
+    for (action, action_set) in actions {
+        for concrete in catalog.expand(&action_set) {
+            let concrete_set = PatternSet::Include(vec![concrete.clone()]);
+            expanded.insert(concrete, concrete_set);
+        }
+        if !catalog.knows_services_of(&action_set) {
+            expanded.insert(action, action_set);
+        }
+    }
+
+    expanded.into_iter().collect()
+}
+
+/// Works out how a ceiling (the policies of a permissions boundary or of one service or
+/// resource control policy level) restricts a capability: a permissions boundary or service
+/// control policy must allow all of it, unconditionally, for it to stay allowed, and denies in
+/// the ceiling apply as usual. Resource control policies only apply their denies.
+fn ceiling_status(capability: &CapabilityRow, ceiling: &[Policy]) -> CapabilityStatus {
+    let actions = capability.action_set().to_lowercase();
+    let resources = capability.resource_set();
+    let (allows, denies): (Vec<PolicyStatement>, Vec<PolicyStatement>) = ceiling
+        .iter()
+        .flat_map(|policy| policy.statements.iter().cloned())
+        .partition(|statement| statement.effect == Effect::Allow);
+    if !ceiling.iter().all(|policy| policy.origin.requires_allow()) {
+        return deny_status(&actions, &resources, &denies);
+    }
+
+    let allow_sets: Vec<(PatternSet, PatternSet, bool)> = allows
+        .iter()
+        .map(|allow| {
+            (
+                PatternSet::from_element(&allow.action, &allow.not_action).to_lowercase(),
+                PatternSet::from_element(&allow.resource, &allow.not_resource),
+                allow.condition.is_none(),
+            )
+        })
+        .collect();
+
+    let allow_status = if allow_sets
+        .iter()
+        .any(|(a, r, unconditional)| *unconditional && a.covers(&actions) && r.covers(&resources))
+    {
+        CapabilityStatus::Allowed
+    } else if allow_sets
+        .iter()
+        .any(|(a, r, _)| a.overlaps(&actions) && r.overlaps(&resources))
+    {
+        CapabilityStatus::PartiallyDenied
+    } else {
+        CapabilityStatus::Denied
+    };
+
+    allow_status.max(deny_status(&actions, &resources, &denies))
+}
+
+/// Works out how the deny statements affect a grant of `actions` (lowercased) on `resources`.
+fn deny_status(
+    actions: &PatternSet,
+    resources: &PatternSet,
+    denies: &[PolicyStatement],
+) -> CapabilityStatus {
+    let mut status = CapabilityStatus::Allowed;
+
+    for deny in denies {
+        let deny_actions = PatternSet::from_element(&deny.action, &deny.not_action).to_lowercase();
+        let deny_resources = PatternSet::from_element(&deny.resource, &deny.not_resource);
+
+        if deny.condition.is_none()
+            && deny_actions.covers(actions)
+            && deny_resources.covers(resources)
+        {
+            return CapabilityStatus::Denied;
+        }
+        if deny_actions.overlaps(actions) && deny_resources.overlaps(resources) {
+            status = CapabilityStatus::PartiallyDenied;
+        }
+    }
+
+    status
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::aws::iam::{Effect, Policy, PolicyStatement};
+    use crate::aws::iam::{Effect, Policy, PolicyOrigin, PolicyStatement};
 
     #[test]
     fn test_extract_capabilities_from_policies() {
@@ -111,31 +526,51 @@ mod tests {
                     effect: Effect::Allow,
                     action: vec!["s3:ListBucket".to_string(), "s3:GetObject".to_string()],
                     resource: vec!["arn:aws:s3:::my-bucket".to_string()],
+                    ..Default::default()
                 },
                 PolicyStatement {
                     effect: Effect::Allow,
                     action: vec!["s3:GetObject".to_string()],
                     resource: vec!["arn:aws:s3:::my-bucket/*".to_string()],
+                    ..Default::default()
                 },
             ],
+            origin: PolicyOrigin::Inline {
+                name: "s3-access".to_string(),
+            },
         }];
 
         let mut expected_capabilities = vec![
             CapabilityRow {
                 resource: "arn:aws:s3:::my-bucket".to_string(),
                 action: "s3:ListBucket".to_string(),
+                condition: String::new(),
+                kind: CapabilityKind::Grant,
+                status: CapabilityStatus::Allowed,
+                source: "inline:s3-access".to_string(),
+                restricted_by: String::new(),
             },
             CapabilityRow {
                 resource: "arn:aws:s3:::my-bucket".to_string(),
                 action: "s3:GetObject".to_string(),
+                condition: String::new(),
+                kind: CapabilityKind::Grant,
+                status: CapabilityStatus::Allowed,
+                source: "inline:s3-access".to_string(),
+                restricted_by: String::new(),
             },
             CapabilityRow {
                 resource: "arn:aws:s3:::my-bucket/*".to_string(),
                 action: "s3:GetObject".to_string(),
+                condition: String::new(),
+                kind: CapabilityKind::Grant,
+                status: CapabilityStatus::Allowed,
+                source: "inline:s3-access".to_string(),
+                restricted_by: String::new(),
             },
         ];
 
-        let mut capabilities = extract_capabilities_from_policies(policies);
+        let mut capabilities = extract_capabilities_from_policies(policies, None);
 
         // Sort both vectors by resource and action
         capabilities.sort_by(|a, b| (a.resource.cmp(&b.resource)).then(a.action.cmp(&b.action)));
@@ -143,5 +578,298 @@ mod tests {
             .sort_by(|a, b| (a.resource.cmp(&b.resource)).then(a.action.cmp(&b.action)));
 
         assert_eq!(capabilities, expected_capabilities);
+        assert!(capabilities
+            .iter()
+            .all(|capability| capability.source == "inline:s3-access"));
+    }
+
+    #[test]
+    fn test_extract_capabilities_from_negated_and_conditional_statements() {
+        let policy: Policy = serde_json::from_value(serde_json::json!({
+            "Version": "2012-10-17",
+            "Statement": [{
+                "Effect": "Allow",
+                "NotAction": ["iam:*", "organizations:*"],
+                "NotResource": "arn:aws:s3:::secrets/*",
+                "Condition": { "Bool": { "aws:MultiFactorAuthPresent": true } }
+            }]
+        }))
+        .unwrap();
+
+        let capabilities = extract_capabilities_from_policies(vec![policy], None);
+
+        assert_eq!(capabilities.len(), 1);
+        assert_eq!(
+            capabilities[0].resource,
+            "NotResource(arn:aws:s3:::secrets/*)"
+        );
+        assert_eq!(capabilities[0].action, "NotAction(iam:*, organizations:*)");
+        assert_eq!(
+            capabilities[0].condition,
+            r#"{"Bool":{"aws:MultiFactorAuthPresent":["true"]}}"#
+        );
+    }
+
+    fn deny_test_policy(statements: serde_json::Value) -> Vec<Policy> {
+        vec![Policy::from_statements(PolicyOrigin::Unknown, statements)]
+    }
+
+    fn status_of(capabilities: &[CapabilityRow], action: &str) -> CapabilityStatus {
+        capabilities
+            .iter()
+            .find(|capability| capability.action == action)
+            .map(|capability| capability.status)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_deny_statements_subtract_from_allows() {
+        let policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": ["s3:*", "s3:GetObject", "ec2:DescribeInstances"], "Resource": "*" },
+            { "Effect": "Allow", "Action": "iam:PassRole", "Resource": "*" },
+            { "Effect": "Deny", "Action": "S3:getobject", "Resource": "*" },
+            { "Effect": "Deny", "Action": "iam:*", "Resource": "*", "Condition": { "Bool": { "aws:MultiFactorAuthPresent": false } } }
+        ]));
+
+        let capabilities = extract_capabilities_from_policies(policies, None);
+
+        // Deny statements never show up as granted capabilities.
+        assert_eq!(capabilities.len(), 4);
+        assert_eq!(
+            status_of(&capabilities, "s3:*"),
+            CapabilityStatus::PartiallyDenied
+        );
+        assert_eq!(
+            status_of(&capabilities, "s3:GetObject"),
+            CapabilityStatus::Denied
+        );
+        assert_eq!(
+            status_of(&capabilities, "ec2:DescribeInstances"),
+            CapabilityStatus::Allowed
+        );
+        assert_eq!(
+            status_of(&capabilities, "iam:PassRole"),
+            CapabilityStatus::PartiallyDenied
+        );
+    }
+
+    #[test]
+    fn test_not_action_deny() {
+        let policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": ["s3:GetObject", "iam:GetRole"], "Resource": "*" },
+            { "Effect": "Deny", "NotAction": "iam:*", "Resource": "*" }
+        ]));
+
+        let capabilities = extract_capabilities_from_policies(policies, None);
+
+        assert_eq!(
+            status_of(&capabilities, "s3:GetObject"),
+            CapabilityStatus::Denied
+        );
+        assert_eq!(
+            status_of(&capabilities, "iam:GetRole"),
+            CapabilityStatus::Allowed
+        );
+    }
+
+    #[test]
+    fn test_deny_on_narrower_resource_is_partial() {
+        let policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*" },
+            { "Effect": "Deny", "Action": "s3:*", "Resource": "arn:aws:s3:::bucket/secrets/*" }
+        ]));
+
+        let capabilities = extract_capabilities_from_policies(policies, None);
+
+        assert_eq!(
+            status_of(&capabilities, "s3:GetObject"),
+            CapabilityStatus::PartiallyDenied
+        );
+    }
+
+    #[test]
+    fn test_permissions_boundary_restricts_capabilities() {
+        let mut policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": ["s3:GetObject", "s3:*", "iam:CreateUser"], "Resource": "*" }
+        ]));
+        let mut boundary = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": "s3:Get*", "Resource": "*" },
+            { "Effect": "Allow", "Action": "iam:*", "Resource": "*" },
+            { "Effect": "Deny", "Action": "iam:CreateUser", "Resource": "*" }
+        ]))
+        .remove(0);
+        boundary.origin = PolicyOrigin::PermissionsBoundary {
+            arn: "arn:aws:iam::123456789012:policy/Boundary".to_string(),
+            version_id: None,
+        };
+        policies.push(boundary.clone());
+
+        let capabilities = extract_capabilities_from_policies(policies, None);
+
+        // The boundary grants nothing by itself.
+        assert_eq!(capabilities.len(), 3);
+        assert_eq!(
+            status_of(&capabilities, "s3:GetObject"),
+            CapabilityStatus::Allowed
+        );
+        assert_eq!(
+            status_of(&capabilities, "s3:*"),
+            CapabilityStatus::PartiallyDenied
+        );
+        assert_eq!(
+            status_of(&capabilities, "iam:CreateUser"),
+            CapabilityStatus::Denied
+        );
+        assert!(capabilities
+            .iter()
+            .filter(|capability| capability.status != CapabilityStatus::Allowed)
+            .all(|capability| capability.restricted_by
+                == "boundary:arn:aws:iam::123456789012:policy/Boundary"));
+
+        assert!(capabilities
+            .iter()
+            .all(|capability| capability.kind == CapabilityKind::Grant));
+
+        let boundary_rows = extract_boundary_capabilities(boundary, None);
+        assert_eq!(boundary_rows.len(), 2);
+        assert_eq!(
+            boundary_rows[0].source,
+            "boundary:arn:aws:iam::123456789012:policy/Boundary"
+        );
+        assert!(boundary_rows
+            .iter()
+            .all(|capability| capability.kind == CapabilityKind::Ceiling));
+    }
+
+    #[test]
+    fn test_service_control_policies_restrict_capabilities() {
+        let scp = |path: &str, statements: serde_json::Value| {
+            let mut scp = deny_test_policy(statements).remove(0);
+            scp.origin = PolicyOrigin::ServiceControlPolicy {
+                path: path.to_string(),
+            };
+            scp
+        };
+        let mut policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": ["s3:GetObject", "ec2:RunInstances", "iam:CreateUser"], "Resource": "*" }
+        ]));
+        policies.push(scp(
+            "root.json",
+            serde_json::json!([
+                { "Effect": "Allow", "Action": "*", "Resource": "*" },
+                { "Effect": "Deny", "Action": "iam:*", "Resource": "*" }
+            ]),
+        ));
+        policies.push(scp(
+            "workloads.json",
+            serde_json::json!([{ "Effect": "Allow", "Action": ["s3:*", "iam:*"], "Resource": "*" }]),
+        ));
+
+        let capabilities = extract_capabilities_from_policies(policies, None);
+        let restricted_by = |action: &str| {
+            capabilities
+                .iter()
+                .find(|capability| capability.action == action)
+                .map(|capability| (capability.status, capability.restricted_by.as_str()))
+                .unwrap()
+        };
+
+        assert_eq!(
+            restricted_by("s3:GetObject"),
+            (CapabilityStatus::Allowed, "")
+        );
+        assert_eq!(
+            restricted_by("ec2:RunInstances"),
+            (CapabilityStatus::Denied, "scp:workloads.json")
+        );
+        assert_eq!(
+            restricted_by("iam:CreateUser"),
+            (CapabilityStatus::Denied, "scp:root.json")
+        );
+    }
+
+    #[test]
+    fn test_resource_control_policies_only_deny() {
+        let mut policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": ["s3:GetObject", "s3:DeleteBucket"], "Resource": "*" }
+        ]));
+        let mut rcp = deny_test_policy(serde_json::json!([
+            { "Effect": "Deny", "Action": "s3:DeleteBucket", "Resource": "*" }
+        ]))
+        .remove(0);
+        rcp.origin = PolicyOrigin::ResourceControlPolicy {
+            path: "root.json".to_string(),
+        };
+        policies.push(rcp);
+
+        let capabilities = extract_capabilities_from_policies(policies, None);
+
+        assert_eq!(
+            status_of(&capabilities, "s3:GetObject"),
+            CapabilityStatus::Allowed
+        );
+        assert_eq!(
+            status_of(&capabilities, "s3:DeleteBucket"),
+            CapabilityStatus::Denied
+        );
+    }
+
+    #[test]
+    fn test_expand_actions_with_catalog() {
+        let catalog: ActionCatalog = serde_json::from_value(serde_json::json!({
+            "version": "test",
+            "services": { "s3": ["DeleteBucket", "GetObject", "PutObject"] }
+        }))
+        .unwrap();
+        let policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": ["s3:*", "s3:GetObject", "ec2:RunInstances"], "Resource": "*" },
+            { "Effect": "Deny", "Action": "s3:DeleteBucket", "Resource": "*" }
+        ]));
+
+        let capabilities = extract_capabilities_from_policies(policies, Some(&catalog));
+
+        let actions: Vec<&str> = capabilities
+            .iter()
+            .map(|capability| capability.action.as_str())
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                "ec2:RunInstances",
+                "s3:DeleteBucket",
+                "s3:GetObject",
+                "s3:PutObject"
+            ]
+        );
+        // Once expanded, the deny applies to exactly one concrete action.
+        assert_eq!(
+            status_of(&capabilities, "s3:DeleteBucket"),
+            CapabilityStatus::Denied
+        );
+        assert_eq!(
+            status_of(&capabilities, "s3:GetObject"),
+            CapabilityStatus::Allowed
+        );
+    }
+
+    #[test]
+    fn test_expand_actions_keeps_uncatalogued_services() {
+        let catalog: ActionCatalog = serde_json::from_value(serde_json::json!({
+            "version": "test",
+            "services": { "s3": ["GetObject"] }
+        }))
+        .unwrap();
+        let policies = deny_test_policy(serde_json::json!([
+            { "Effect": "Allow", "Action": "*", "Resource": "*" },
+            { "Effect": "Allow", "NotAction": "s3:*", "Resource": "*" }
+        ]));
+
+        let capabilities = extract_capabilities_from_policies(policies, Some(&catalog));
+
+        let actions: Vec<&str> = capabilities
+            .iter()
+            .map(|capability| capability.action.as_str())
+            .collect();
+        assert_eq!(actions, vec!["*", "NotAction(s3:*)", "s3:GetObject"]);
     }
 }
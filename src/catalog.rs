@@ -0,0 +1,140 @@
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::wildcard::PatternSet;
+
+/// The catalog compiled into the binary. Refresh it by replacing `data/actions.json`, or point
+/// `--action-catalog` at a newer file without rebuilding.
+const EMBEDDED_CATALOG: &str = include_str!("../data/actions.json");
+
+/// A versioned list of the actions each AWS service supports, used to expand wildcard actions
+/// such as `s3:Get*` into the concrete actions they grant.
+#[derive(Debug, Deserialize)]
+pub struct ActionCatalog {
+    pub version: String,
+    /// Service prefix (e.g. `s3`) to action names without the prefix (e.g. `GetObject`).
+    pub services: BTreeMap<String, Vec<String>>,
+}
+
+impl ActionCatalog {
+    /// Returns the catalog embedded in the binary.
+    pub fn embedded() -> Self {
+        serde_json::from_str(EMBEDDED_CATALOG).expect("embedded action catalog is valid JSON")
+    }
+
+    /// Loads a catalog from a JSON file with the same layout as `data/actions.json`.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read action catalog {}: {}", path.display(), e))?;
+        let catalog = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid action catalog {}: {}", path.display(), e))?;
+        Ok(catalog)
+    }
+
+    /// Loads the catalog from `path` if given, or falls back to the embedded one.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        match path {
+            Some(path) => ActionCatalog::from_file(path),
+            None => Ok(ActionCatalog::embedded()),
+        }
+    }
+
+    /// Iterates over every action in the catalog as `service:Action`.
+    pub fn actions(&self) -> impl Iterator<Item = String> + '_ {
+        self.services.iter().flat_map(|(service, actions)| {
+            actions
+                .iter()
+                .map(move |action| format!("{}:{}", service, action))
+        })
+    }
+
+    /// Expands a set of action patterns (matched case-insensitively) into the concrete
+    /// actions of the catalog it contains, sorted and without duplicates.
+    pub fn expand(&self, actions: &PatternSet) -> Vec<String> {
+        let lowercase = actions.to_lowercase();
+        let mut expanded: Vec<String> = self
+            .actions()
+            .filter(|action| lowercase.contains(&action.to_lowercase()))
+            .collect();
+        expanded.sort();
+        expanded.dedup();
+        expanded
+    }
+
+    /// Returns true if every action in the set belongs to a service of the catalog, so that
+    /// expanding it loses nothing. Patterns with a wildcard service (`*`, `s*:Get*`) and
+    /// `NotAction` sets also match the actions of services the catalog does not list.
+    pub fn knows_services_of(&self, actions: &PatternSet) -> bool {
+        match actions {
+            PatternSet::Include(patterns) => patterns.iter().all(|pattern| {
+                pattern.split_once(':').is_some_and(|(service, _)| {
+                    !service.contains(['*', '?'])
+                        && self.services.contains_key(&service.to_lowercase())
+                })
+            }),
+            PatternSet::Exclude(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_catalog() -> ActionCatalog {
+        serde_json::from_value(serde_json::json!({
+            "version": "test",
+            "services": {
+                "s3": ["GetObject", "GetObjectAcl", "ListBucket", "PutObject"],
+                "sqs": ["SendMessage"]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_embedded_catalog_loads() {
+        let catalog = ActionCatalog::embedded();
+
+        assert!(!catalog.version.is_empty());
+        let expanded = catalog.expand(&PatternSet::Include(vec!["iam:PassRole".into()]));
+        assert_eq!(expanded, vec!["iam:PassRole"]);
+    }
+
+    #[test]
+    fn test_expand_wildcards() {
+        let catalog = test_catalog();
+        let expand = |pattern: &str| catalog.expand(&PatternSet::Include(vec![pattern.into()]));
+
+        assert_eq!(expand("s3:Get*"), vec!["s3:GetObject", "s3:GetObjectAcl"]);
+        assert_eq!(expand("s3:*").len(), 4);
+        assert_eq!(expand("*").len(), 5);
+        assert_eq!(expand("S3:getobject"), vec!["s3:GetObject"]);
+        assert!(expand("ec2:*").is_empty());
+    }
+
+    #[test]
+    fn test_expand_not_action() {
+        let catalog = test_catalog();
+
+        let expanded = catalog.expand(&PatternSet::Exclude(vec!["s3:*".into()]));
+
+        assert_eq!(expanded, vec!["sqs:SendMessage"]);
+    }
+
+    #[test]
+    fn test_knows_services_of() {
+        let catalog = test_catalog();
+        let knows =
+            |pattern: &str| catalog.knows_services_of(&PatternSet::Include(vec![pattern.into()]));
+
+        assert!(knows("s3:*"));
+        assert!(knows("SQS:Send*"));
+        assert!(!knows("*"));
+        assert!(!knows("s*:GetObject"));
+        assert!(!knows("rds:DeleteDBInstance"));
+        assert!(!catalog.knows_services_of(&PatternSet::Exclude(vec!["s3:*".into()])));
+    }
+}
@@ -1,19 +1,130 @@
+use crate::aws::cache::PolicyCache;
+use crate::aws::client::ClientOptions;
+use crate::aws::identity::Identity;
+use crate::compare::CompareMode;
+use crate::input::{select_identity, PolicyInput};
 use crate::output::format::OutputFormat;
+use crate::resource::ResourcePolicyArg;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
 #[structopt(
     name = "IAM Capable",
-    about = "A tool to compare and fetch IAM Role capabilities"
+    about = "A tool to compare and fetch IAM Role, User and Group capabilities"
 )]
 pub enum IamCapable {
-    #[structopt(name = "compare", about = "Compares two IAM Roles")]
+    #[structopt(
+        name = "compare",
+        about = "Compares two or more IAM Roles, Users or Groups"
+    )]
     Compare {
-        #[structopt(long, help = "Name of the first IAM Role", required = true)]
-        role1: String,
+        #[structopt(
+            long,
+            help = "Name or ARN of the first IAM Role, or 'user:NAME' / 'group:NAME'",
+            parse(try_from_str)
+        )]
+        role1: Option<Identity>,
+
+        #[structopt(
+            long,
+            help = "Read the first role's policies from this JSON file instead of AWS (repeatable)",
+            parse(from_os_str),
+            number_of_values = 1
+        )]
+        role1_policy_file: Vec<PathBuf>,
 
-        #[structopt(long, help = "Name of the second IAM Role", required = true)]
-        role2: String,
+        #[structopt(
+            long,
+            help = "Read the first role's policies from the JSON files in this directory instead of AWS (repeatable)",
+            parse(from_os_str),
+            number_of_values = 1
+        )]
+        role1_policy_dir: Vec<PathBuf>,
+
+        #[structopt(
+            long,
+            help = "AWS profile to fetch the first role with, overriding --profile",
+            requires = "role1"
+        )]
+        role1_profile: Option<String>,
+
+        #[structopt(
+            long,
+            help = "Name or ARN of the second IAM Role, or 'user:NAME' / 'group:NAME'",
+            parse(try_from_str)
+        )]
+        role2: Option<Identity>,
+
+        #[structopt(
+            long,
+            help = "Read the second role's policies from this JSON file instead of AWS (repeatable)",
+            parse(from_os_str),
+            number_of_values = 1
+        )]
+        role2_policy_file: Vec<PathBuf>,
+
+        #[structopt(
+            long,
+            help = "Read the second role's policies from the JSON files in this directory instead of AWS (repeatable)",
+            parse(from_os_str),
+            number_of_values = 1
+        )]
+        role2_policy_dir: Vec<PathBuf>,
+
+        #[structopt(
+            long,
+            help = "AWS profile to fetch the second role with, overriding --profile",
+            requires = "role2"
+        )]
+        role2_profile: Option<String>,
+
+        #[structopt(
+            long = "role",
+            help = "An IAM Role name or ARN, 'user:NAME', 'group:NAME', or 'file:PATH' / 'dir:PATH' for local policies, to compare (repeatable, after --role1 and --role2)",
+            number_of_values = 1
+        )]
+        roles: Vec<PolicyInput>,
+
+        #[structopt(
+            long,
+            help = "Treat actions and resources as wildcard patterns and report which role's grant is broader"
+        )]
+        semantic: bool,
+
+        #[structopt(
+            long,
+            help = "Compare who may assume each role (their trust policies) instead of what the roles can do",
+            conflicts_with_all = &["semantic", "assert-subset", "expand-actions"]
+        )]
+        trust: bool,
+
+        #[structopt(flatten)]
+        aws: AwsArgs,
+
+        #[structopt(flatten)]
+        organization: OrganizationArgs,
+
+        #[structopt(
+            long,
+            help = "Restrict capabilities to what the role's permissions boundary allows"
+        )]
+        apply_boundary: bool,
+
+        #[structopt(
+            long,
+            help = "Rows to report: 'difference' (capabilities not every role has), 'only-in-first', 'only-in-second', 'intersection', 'union' or 'full' (every capability, with per-role columns); defaults to 'difference'",
+            parse(try_from_str),
+            conflicts_with_all = &["semantic", "assert-subset"]
+        )]
+        mode: Option<CompareMode>,
+
+        #[structopt(
+            long,
+            help = "Report capabilities the first role does not grant but another role does, and exit with status 1 if there are any",
+            conflicts_with = "semantic"
+        )]
+        assert_subset: bool,
 
         #[structopt(
             long,
@@ -23,12 +134,122 @@ pub enum IamCapable {
             parse(try_from_str)
         )]
         output_format: OutputFormat,
+        #[structopt(
+            long,
+            help = "Expand wildcard actions (e.g. 's3:Get*') into the concrete actions they grant"
+        )]
+        expand_actions: bool,
+
+        #[structopt(
+            long,
+            help = "Action catalog JSON file to expand actions with, instead of the embedded one",
+            parse(from_os_str)
+        )]
+        action_catalog: Option<PathBuf>,
     },
 
-    #[structopt(name = "fetch", about = "Fetches capabilities for a single IAM Role")]
+    #[structopt(
+        name = "fetch",
+        about = "Fetches capabilities for a single IAM Role, User or Group"
+    )]
     Fetch {
-        #[structopt(long, help = "Name of the IAM Role", required = true)]
-        role: String,
+        #[structopt(flatten)]
+        identity: IdentityArgs,
+
+        #[structopt(flatten)]
+        aws: AwsArgs,
+
+        #[structopt(flatten)]
+        organization: OrganizationArgs,
+
+        #[structopt(
+            long,
+            help = "Restrict capabilities to what the role's permissions boundary allows"
+        )]
+        apply_boundary: bool,
+
+        #[structopt(
+            long,
+            help = "Report who may assume the role (its trust policy) instead of what it can do, flagging wildcard principals and missing sts:ExternalId or subject conditions",
+            conflicts_with_all = &["user", "group", "expand-actions"]
+        )]
+        trust: bool,
+
+        #[structopt(
+            long,
+            help = "Output format, either 'json' or 'csv'",
+            default_value = "csv",
+            parse(try_from_str)
+        )]
+        output_format: OutputFormat,
+        #[structopt(
+            long,
+            help = "Expand wildcard actions (e.g. 's3:Get*') into the concrete actions they grant"
+        )]
+        expand_actions: bool,
+
+        #[structopt(
+            long,
+            help = "Action catalog JSON file to expand actions with, instead of the embedded one",
+            parse(from_os_str)
+        )]
+        action_catalog: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "snapshot",
+        about = "Saves an IAM Role's resolved policies to a file for later comparison"
+    )]
+    Snapshot {
+        #[structopt(
+            long,
+            help = "Name or ARN of the IAM Role",
+            required = true,
+            parse(try_from_str)
+        )]
+        role: Identity,
+
+        #[structopt(flatten)]
+        aws: AwsArgs,
+
+        #[structopt(
+            long,
+            help = "File to write the snapshot to; read it back with --policy-file",
+            parse(from_os_str)
+        )]
+        out: PathBuf,
+    },
+
+    #[structopt(
+        name = "simulate",
+        about = "Evaluates whether an IAM Role, User or Group can perform an action on a resource"
+    )]
+    Simulate {
+        #[structopt(flatten)]
+        identity: IdentityArgs,
+
+        #[structopt(flatten)]
+        aws: AwsArgs,
+
+        #[structopt(flatten)]
+        organization: OrganizationArgs,
+
+        #[structopt(
+            long,
+            help = "Action to evaluate, e.g. 's3:GetObject'",
+            required = true
+        )]
+        action: String,
+
+        #[structopt(long, help = "ARN of the resource to evaluate", required = true)]
+        resource: String,
+
+        #[structopt(
+            long,
+            help = "JSON file of condition keys and values for the request, e.g. {\"aws:SourceIp\": \"203.0.113.10\"}",
+            parse(from_os_str)
+        )]
+        context: Option<PathBuf>,
 
         #[structopt(
             long,
@@ -38,8 +259,352 @@ pub enum IamCapable {
         )]
         output_format: OutputFormat,
     },
+
+    #[structopt(
+        name = "access",
+        about = "Evaluates an IAM Role's access to resources by combining its policies with their resource-based policies"
+    )]
+    Access {
+        #[structopt(
+            long,
+            help = "Name or ARN of the IAM Role; an ARN tells cross-account resources apart and matches account principals",
+            parse(try_from_str)
+        )]
+        role: Identity,
+
+        #[structopt(flatten)]
+        files: PolicyFileArgs,
+
+        #[structopt(flatten)]
+        aws: AwsArgs,
+
+        #[structopt(flatten)]
+        organization: OrganizationArgs,
+
+        #[structopt(
+            long,
+            help = "Resource-based policy JSON file, as ARN=PATH unless the file names its resource, or ARN@ACCOUNT=PATH to give the account owning an S3 bucket (repeatable)",
+            required = true,
+            number_of_values = 1,
+            parse(try_from_str)
+        )]
+        resource_policy: Vec<ResourcePolicyArg>,
+
+        #[structopt(
+            long,
+            help = "Action to evaluate, e.g. 'kms:Decrypt' (repeatable)",
+            required = true,
+            number_of_values = 1
+        )]
+        action: Vec<String>,
+
+        #[structopt(
+            long,
+            help = "ARN of a resource to evaluate, e.g. an object in a bucket (repeatable) [default: the resource of each resource policy]",
+            number_of_values = 1
+        )]
+        resource: Vec<String>,
+
+        #[structopt(
+            long,
+            help = "JSON file of condition keys and values for the requests",
+            parse(from_os_str)
+        )]
+        context: Option<PathBuf>,
+
+        #[structopt(
+            long,
+            help = "Output format, either 'json' or 'csv'",
+            default_value = "csv",
+            parse(try_from_str)
+        )]
+        output_format: OutputFormat,
+    },
+
+    #[structopt(
+        name = "escalation",
+        about = "Detects privilege escalation paths open to an IAM Role, User or Group"
+    )]
+    Escalation {
+        #[structopt(flatten)]
+        identity: IdentityArgs,
+
+        #[structopt(flatten)]
+        aws: AwsArgs,
+
+        #[structopt(flatten)]
+        organization: OrganizationArgs,
+
+        #[structopt(
+            long,
+            help = "Escalation rules JSON file to check, instead of the embedded data/escalation-rules.json",
+            parse(from_os_str)
+        )]
+        rules: Option<PathBuf>,
+
+        #[structopt(
+            long,
+            help = "Output format, either 'json' or 'csv'",
+            default_value = "csv",
+            parse(try_from_str)
+        )]
+        output_format: OutputFormat,
+    },
+
+    #[structopt(
+        name = "lint",
+        about = "Checks the policies of an IAM Role, User or Group for common mistakes"
+    )]
+    Lint {
+        #[structopt(flatten)]
+        identity: IdentityArgs,
+
+        #[structopt(flatten)]
+        aws: AwsArgs,
+
+        #[structopt(
+            long,
+            help = "Action catalog JSON file to check actions against, instead of the embedded one",
+            parse(from_os_str)
+        )]
+        action_catalog: Option<PathBuf>,
+
+        #[structopt(
+            long,
+            help = "Output format, either 'json' or 'csv'",
+            default_value = "csv",
+            parse(try_from_str)
+        )]
+        output_format: OutputFormat,
+    },
+
+    #[structopt(
+        name = "generate",
+        about = "Generates a least-privilege policy for an IAM Role from the calls it made, as recorded by CloudTrail"
+    )]
+    Generate {
+        #[structopt(
+            long,
+            help = "Name or ARN of the IAM Role; an ARN only matches calls made in its account",
+            required = true,
+            parse(try_from_str)
+        )]
+        role: Identity,
+
+        #[structopt(
+            long,
+            help = "Directory of CloudTrail log files (.json.gz as delivered to S3, or .json), searched recursively",
+            parse(from_os_str)
+        )]
+        cloudtrail_dir: PathBuf,
+
+        #[structopt(
+            long,
+            help = "Collapse resources sharing a parent path into parent/*, and actions sharing a verb into service:Verb*"
+        )]
+        collapse_wildcards: bool,
+
+        #[structopt(
+            long,
+            help = "File to write the policy document to, instead of standard output",
+            parse(from_os_str)
+        )]
+        out: Option<PathBuf>,
+
+        #[structopt(
+            long,
+            help = "Action catalog JSON file to check the generated actions against, instead of the embedded one",
+            parse(from_os_str)
+        )]
+        action_catalog: Option<PathBuf>,
+    },
+
+    #[structopt(
+        name = "cache",
+        about = "Manages the cache of managed policy documents"
+    )]
+    Cache(CacheCommand),
+}
+
+#[derive(StructOpt)]
+pub enum CacheCommand {
+    #[structopt(name = "clear", about = "Removes every cached policy document")]
+    Clear {
+        #[structopt(
+            long,
+            help = "Cache directory to clear [default: $IAM_CAPABLE_CACHE_DIR or the user's cache directory]",
+            parse(from_os_str)
+        )]
+        cache_dir: Option<PathBuf>,
+    },
+}
+
+// The argument structs below are flattened into subcommands, so they carry plain comments:
+// structopt would show a doc comment as the about text of every subcommand using them.
+
+// Selects the role, user or group to read policies for, from AWS or from local policy files.
+#[derive(StructOpt)]
+pub struct IdentityArgs {
+    #[structopt(
+        long,
+        help = "Name or ARN of the IAM Role; a user or group ARN selects that identity instead",
+        required_unless_one = &["user", "group", "policy-file", "policy-dir"],
+        parse(try_from_str)
+    )]
+    pub role: Option<Identity>,
+
+    #[structopt(
+        long,
+        help = "Name of an IAM User, including the policies of its groups",
+        conflicts_with_all = &["role", "group"]
+    )]
+    pub user: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Name of an IAM Group",
+        conflicts_with_all = &["role", "user"]
+    )]
+    pub group: Option<String>,
+
+    #[structopt(flatten)]
+    pub files: PolicyFileArgs,
+}
+
+impl IdentityArgs {
+    /// Returns where to read the selected identity's policies from.
+    pub fn into_input(self) -> Result<PolicyInput, Box<dyn std::error::Error + Send + Sync>> {
+        let identity = select_identity(self.role, self.user, self.group);
+        self.files.into_input(identity)
+    }
+}
+
+// Local policy files to read an identity's policies from instead of AWS.
+#[derive(StructOpt)]
+pub struct PolicyFileArgs {
+    #[structopt(
+        long,
+        help = "Read the role's policies from this JSON file instead of AWS (repeatable)",
+        parse(from_os_str),
+        number_of_values = 1
+    )]
+    pub policy_file: Vec<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Read the role's policies from the JSON files in this directory instead of AWS (repeatable)",
+        parse(from_os_str),
+        number_of_values = 1
+    )]
+    pub policy_dir: Vec<PathBuf>,
+}
+
+impl PolicyFileArgs {
+    /// Returns where to read the policies of `identity` from: the files if any were given,
+    /// AWS otherwise.
+    pub fn into_input(
+        self,
+        identity: Option<Identity>,
+    ) -> Result<PolicyInput, Box<dyn std::error::Error + Send + Sync>> {
+        PolicyInput::from_args(identity, self.policy_file, self.policy_dir)
+    }
+}
+
+// AWS Organizations control policies read from local files, one per level of the OU chain.
+#[derive(StructOpt)]
+pub struct OrganizationArgs {
+    #[structopt(
+        long,
+        help = "Service control policy JSON file capping what the role can do; repeat for each level of the OU chain",
+        parse(from_os_str),
+        number_of_values = 1
+    )]
+    pub scp_file: Vec<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Resource control policy JSON file whose Deny statements cap what can be done to the account's resources; repeat for each level of the OU chain",
+        parse(from_os_str),
+        number_of_values = 1
+    )]
+    pub rcp_file: Vec<PathBuf>,
 }
 
 pub fn fetch_cli_args() -> IamCapable {
     IamCapable::from_args()
 }
+
+// Options for connecting to AWS, shared by every command that fetches live identities.
+#[derive(StructOpt)]
+pub struct AwsArgs {
+    #[structopt(long, help = "AWS shared config profile to use")]
+    pub profile: Option<String>,
+
+    #[structopt(long, help = "AWS region, overriding the environment and the profile")]
+    pub region: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Send every AWS request to this endpoint, e.g. a LocalStack or Moto server"
+    )]
+    pub endpoint_url: Option<String>,
+
+    #[structopt(long, help = "ARN of a role to assume before fetching anything")]
+    pub assume_role_arn: Option<String>,
+
+    #[structopt(
+        long,
+        help = "External ID required by the trust policy of the assumed roles"
+    )]
+    pub external_id: Option<String>,
+
+    #[structopt(long, help = "Session name for assumed roles [default: iam-capable]")]
+    pub session_name: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Name of a read-only role to assume in accounts other than the caller's, for identities given as ARNs"
+    )]
+    pub cross_account_role: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Most IAM requests to have in flight at once; lower it if IAM keeps throttling",
+        default_value = "4"
+    )]
+    pub concurrency: usize,
+
+    #[structopt(
+        long,
+        help = "Download every managed policy document, bypassing the cache"
+    )]
+    pub no_cache: bool,
+
+    #[structopt(
+        long,
+        help = "Directory to cache managed policy documents in [default: $IAM_CAPABLE_CACHE_DIR or the user's cache directory]",
+        parse(from_os_str),
+        conflicts_with = "no-cache"
+    )]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl From<AwsArgs> for ClientOptions {
+    fn from(args: AwsArgs) -> Self {
+        ClientOptions {
+            profile: args.profile,
+            region: args.region,
+            endpoint_url: args.endpoint_url,
+            assume_role_arn: args.assume_role_arn,
+            external_id: args.external_id,
+            session_name: args.session_name,
+            cross_account_role: args.cross_account_role,
+            concurrency: args.concurrency,
+            cache_dir: if args.no_cache {
+                None
+            } else {
+                args.cache_dir.or_else(PolicyCache::default_dir)
+            },
+        }
+    }
+}
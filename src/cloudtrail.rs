@@ -0,0 +1,431 @@
+use flate2::read::GzDecoder;
+use serde_derive::Deserialize;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::aws::identity::Identity;
+use crate::catalog::ActionCatalog;
+use crate::wildcard::PatternSet;
+
+/// CloudTrail event names that differ from the IAM action authorizing the call, as
+/// `(service, event name, action name)`.
+const RENAMED_EVENTS: &[(&str, &str, &str)] = &[
+    ("s3", "CompleteMultipartUpload", "PutObject"),
+    ("s3", "CopyObject", "PutObject"),
+    ("s3", "CreateMultipartUpload", "PutObject"),
+    ("s3", "DeleteObjects", "DeleteObject"),
+    ("s3", "HeadBucket", "ListBucket"),
+    ("s3", "HeadObject", "GetObject"),
+    ("s3", "ListBuckets", "ListAllMyBuckets"),
+    ("s3", "ListObjects", "ListBucket"),
+    ("s3", "ListObjectsV2", "ListBucket"),
+    ("s3", "ListObjectVersions", "ListBucketVersions"),
+    ("s3", "SelectObjectContent", "GetObject"),
+    ("s3", "UploadPart", "PutObject"),
+    ("s3", "UploadPartCopy", "PutObject"),
+    ("lambda", "Invoke", "InvokeFunction"),
+];
+
+/// Names the digest files of log file validation and the directory CloudTrail delivers them to,
+/// next to `CloudTrail` in `AWSLogs/<account>`, e.g.
+/// `CloudTrail-Digest/<region>/.../<account>_CloudTrail-Digest_<region>_..._.json.gz`.
+const DIGEST_MARKER: &str = "CloudTrail-Digest";
+
+/// A CloudTrail log file, as delivered to S3.
+#[derive(Debug, Deserialize)]
+struct LogFile {
+    #[serde(rename = "Records")]
+    records: Vec<CloudTrailEvent>,
+}
+
+/// An API call recorded by CloudTrail. Only the fields needed to tell who made the call and
+/// what it touched are read.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudTrailEvent {
+    pub event_source: String,
+    pub event_name: String,
+    #[serde(default)]
+    pub user_identity: UserIdentity,
+    #[serde(default)]
+    pub error_code: Option<String>,
+    #[serde(default)]
+    pub resources: Vec<EventResource>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserIdentity {
+    #[serde(rename = "type", default)]
+    pub identity_type: String,
+    #[serde(default)]
+    pub session_context: Option<SessionContext>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionContext {
+    #[serde(default)]
+    pub session_issuer: Option<SessionIssuer>,
+}
+
+/// The role whose session made an `AssumedRole` call.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionIssuer {
+    #[serde(default)]
+    pub user_name: String,
+    #[serde(default)]
+    pub account_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventResource {
+    #[serde(rename = "ARN", default)]
+    pub arn: String,
+    #[serde(rename = "type", default)]
+    pub resource_type: String,
+}
+
+/// An action the role performed on a resource, `*` when CloudTrail does not record one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiCall {
+    pub action: String,
+    pub resource: String,
+}
+
+impl CloudTrailEvent {
+    /// Returns true if the call was made with a session of `role`, in its account when the role
+    /// was given as an ARN.
+    pub fn is_made_by(&self, role: &Identity) -> bool {
+        let Some(issuer) = self
+            .user_identity
+            .session_context
+            .as_ref()
+            .and_then(|context| context.session_issuer.as_ref())
+        else {
+            return false;
+        };
+        self.user_identity.identity_type == "AssumedRole"
+            && issuer.user_name == role.name
+            && role
+                .account_id
+                .as_ref()
+                .is_none_or(|account_id| *account_id == issuer.account_id)
+    }
+
+    /// Returns true if the call was refused for lack of permissions, so the role was never
+    /// allowed to make it.
+    pub fn is_access_denied(&self) -> bool {
+        self.error_code.as_deref().is_some_and(|code| {
+            code.contains("AccessDenied") || code.contains("UnauthorizedOperation")
+        })
+    }
+
+    /// Returns the IAM action authorizing the call, e.g. `s3:GetObject` for `GetObject` on
+    /// `s3.amazonaws.com`.
+    pub fn action(&self) -> String {
+        let service = self
+            .event_source
+            .strip_suffix(".amazonaws.com")
+            .unwrap_or(&self.event_source);
+        // CloudWatch metrics are served from monitoring.amazonaws.com.
+        let service = if service == "monitoring" {
+            "cloudwatch"
+        } else {
+            service
+        };
+        // Lambda event names carry the API version, e.g. `UpdateFunctionCode20150331v2`.
+        let name = match service {
+            "lambda" => self
+                .event_name
+                .split(|c: char| c.is_ascii_digit())
+                .next()
+                .unwrap_or_default(),
+            _ => self.event_name.as_str(),
+        };
+        let name = RENAMED_EVENTS
+            .iter()
+            .find(|(renamed_service, event, _)| *renamed_service == service && *event == name)
+            .map_or(name, |(_, _, action)| action);
+        format!("{}:{}", service, name)
+    }
+
+    /// Returns the calls the event records, one per resource it touched.
+    pub fn calls(&self) -> Vec<ApiCall> {
+        let action = self.action();
+        // Object-level S3 events also list the bucket; the object is what the action applies to.
+        let objects: Vec<&EventResource> = self
+            .resources
+            .iter()
+            .filter(|resource| resource.resource_type == "AWS::S3::Object")
+            .collect();
+        let resources: Vec<&EventResource> = if objects.is_empty() {
+            self.resources.iter().collect()
+        } else {
+            objects
+        };
+
+        let mut resources: Vec<String> = resources
+            .iter()
+            .filter(|resource| !resource.arn.is_empty())
+            .map(|resource| resource.arn.clone())
+            .collect();
+        if resources.is_empty() {
+            resources.push("*".to_string());
+        }
+        resources
+            .into_iter()
+            .map(|resource| ApiCall {
+                action: action.clone(),
+                resource,
+            })
+            .collect()
+    }
+}
+
+/// Returns the actions of the calls that belong to a service of the catalog but are not in it,
+/// sorted and without duplicates. These are usually event names that differ from the action
+/// authorizing them and are missing from `RENAMED_EVENTS`. Actions of services the catalog does
+/// not list cannot be checked and are left out.
+pub fn actions_missing_from(calls: &[ApiCall], catalog: &ActionCatalog) -> Vec<String> {
+    let mut missing: Vec<String> = calls
+        .iter()
+        .map(|call| call.action.as_str())
+        .filter(|action| {
+            let actions = PatternSet::Include(vec![action.to_string()]);
+            catalog.knows_services_of(&actions) && catalog.expand(&actions).is_empty()
+        })
+        .map(str::to_string)
+        .collect();
+    missing.sort();
+    missing.dedup();
+    missing
+}
+
+/// Reads the events of every CloudTrail log file under `dir`, including its subdirectories
+/// (the `AWSLogs/<account>/CloudTrail/<region>/<yyyy>/<mm>/<dd>` layout of a trail's bucket).
+/// Gzipped files (`.json.gz`), as CloudTrail delivers them, and plain `.json` files are read;
+/// other files, and the `CloudTrail-Digest` files and directories of log file validation, are
+/// skipped.
+///
+/// # Arguments
+///
+/// * `dir` - The directory the log files were copied to.
+///
+/// # Returns
+///
+/// A Result containing the events of all files, in file name order, or an error if a file
+/// cannot be read or is not a CloudTrail log file.
+pub fn load_events(
+    dir: &Path,
+) -> Result<Vec<CloudTrailEvent>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut files = Vec::new();
+    collect_log_files(dir, &mut files)?;
+    files.sort();
+
+    let mut events = Vec::new();
+    for path in files {
+        let mut contents = String::new();
+        let mut file = File::open(&path)
+            .map_err(|e| format!("Failed to read CloudTrail log {}: {}", path.display(), e))?;
+        let read = if path.to_string_lossy().ends_with(".gz") {
+            GzDecoder::new(file).read_to_string(&mut contents)
+        } else {
+            file.read_to_string(&mut contents)
+        };
+        read.map_err(|e| format!("Failed to read CloudTrail log {}: {}", path.display(), e))?;
+
+        let log: LogFile = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid CloudTrail log {}: {}", path.display(), e))?;
+        events.extend(log.records);
+    }
+    Ok(events)
+}
+
+fn collect_log_files(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        format!(
+            "Failed to read CloudTrail directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+    for entry in entries {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if path.is_dir() {
+            if name != DIGEST_MARKER {
+                collect_log_files(&path, files)?;
+            }
+        } else if (name.ends_with(".json.gz") || name.ends_with(".json"))
+            && !name.contains(DIGEST_MARKER)
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use serde_json::json;
+    use std::io::Write;
+
+    fn event(value: serde_json::Value) -> CloudTrailEvent {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn assumed_role(role: &str, account_id: &str) -> serde_json::Value {
+        json!({
+            "type": "AssumedRole",
+            "sessionContext": {
+                "sessionIssuer": { "type": "Role", "userName": role, "accountId": account_id }
+            }
+        })
+    }
+
+    #[test]
+    fn test_event_actions_and_resources() {
+        let get = event(json!({
+            "eventSource": "s3.amazonaws.com",
+            "eventName": "HeadObject",
+            "userIdentity": assumed_role("app", "123456789012"),
+            "resources": [
+                { "type": "AWS::S3::Object", "ARN": "arn:aws:s3:::bucket/key" },
+                { "type": "AWS::S3::Bucket", "ARN": "arn:aws:s3:::bucket" }
+            ]
+        }));
+        assert_eq!(
+            get.calls(),
+            vec![ApiCall {
+                action: "s3:GetObject".to_string(),
+                resource: "arn:aws:s3:::bucket/key".to_string()
+            }]
+        );
+
+        let update = event(json!({
+            "eventSource": "lambda.amazonaws.com",
+            "eventName": "UpdateFunctionCode20150331v2"
+        }));
+        assert_eq!(update.calls()[0].action, "lambda:UpdateFunctionCode");
+        assert_eq!(update.calls()[0].resource, "*");
+
+        let metrics = event(json!({
+            "eventSource": "monitoring.amazonaws.com",
+            "eventName": "PutMetricData"
+        }));
+        assert_eq!(metrics.action(), "cloudwatch:PutMetricData");
+
+        let s3_action = |name: &str| {
+            event(json!({ "eventSource": "s3.amazonaws.com", "eventName": name })).action()
+        };
+        assert_eq!(s3_action("CreateMultipartUpload"), "s3:PutObject");
+        assert_eq!(s3_action("UploadPart"), "s3:PutObject");
+        assert_eq!(s3_action("CompleteMultipartUpload"), "s3:PutObject");
+        assert_eq!(s3_action("CopyObject"), "s3:PutObject");
+        assert_eq!(s3_action("ListBuckets"), "s3:ListAllMyBuckets");
+        assert_eq!(s3_action("DeleteObjects"), "s3:DeleteObject");
+        assert_eq!(s3_action("HeadBucket"), "s3:ListBucket");
+    }
+
+    #[test]
+    fn test_actions_missing_from_catalog() {
+        let call = |action: &str| ApiCall {
+            action: action.to_string(),
+            resource: "*".to_string(),
+        };
+        let calls = vec![
+            call("s3:GetObject"),
+            call("s3:GetBucketPolicyStatusAndMore"),
+            call("s3:GetBucketPolicyStatusAndMore"),
+            call("unlisted:DoSomething"),
+        ];
+
+        let missing = actions_missing_from(&calls, &ActionCatalog::embedded());
+
+        assert!(missing.contains(&"s3:GetBucketPolicyStatusAndMore".to_string()));
+        assert!(!missing.contains(&"s3:GetObject".to_string()));
+        assert!(!missing.iter().any(|action| action.starts_with("unlisted:")));
+        assert_eq!(
+            missing
+                .iter()
+                .filter(|action| *action == "s3:GetBucketPolicyStatusAndMore")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_event_identity_and_errors() {
+        let call = event(json!({
+            "eventSource": "sqs.amazonaws.com",
+            "eventName": "SendMessage",
+            "userIdentity": assumed_role("app", "123456789012"),
+            "errorCode": "AccessDenied"
+        }));
+
+        assert!(call.is_made_by(&Identity::role("app")));
+        assert!(call.is_made_by(&"arn:aws:iam::123456789012:role/app".parse().unwrap()));
+        assert!(!call.is_made_by(&"arn:aws:iam::210987654321:role/app".parse().unwrap()));
+        assert!(!call.is_made_by(&Identity::role("worker")));
+        assert!(call.is_access_denied());
+    }
+
+    #[test]
+    fn test_load_gzipped_events() {
+        let dir =
+            std::env::temp_dir().join(format!("iam-capable-cloudtrail-{}", std::process::id()));
+        let day = dir.join("AWSLogs/123456789012/CloudTrail/us-east-1/2026/10/01");
+        fs::create_dir_all(&day).unwrap();
+        let log = json!({
+            "Records": [{ "eventSource": "sts.amazonaws.com", "eventName": "GetCallerIdentity" }]
+        });
+        let mut encoder = GzEncoder::new(
+            File::create(day.join("123456789012_CloudTrail_us-east-1.json.gz")).unwrap(),
+            Compression::default(),
+        );
+        encoder.write_all(log.to_string().as_bytes()).unwrap();
+        encoder.finish().unwrap();
+        fs::write(day.join("digest.txt"), "not a log").unwrap();
+
+        // Digest files are gzipped JSON too, but are not logs.
+        let digest_day = dir.join("AWSLogs/123456789012/CloudTrail-Digest/us-east-1/2026/10/01");
+        fs::create_dir_all(&digest_day).unwrap();
+        let digest = json!({
+            "awsAccountId": "123456789012",
+            "digestStartTime": "2026-10-01T00:00:00Z",
+            "logFiles": []
+        });
+        for path in [
+            digest_day.join("123456789012_CloudTrail-Digest_us-east-1.json.gz"),
+            // A digest copied out of its directory is still recognised by its name.
+            day.join("123456789012_CloudTrail-Digest_us-east-1.json.gz"),
+        ] {
+            let mut encoder = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+            encoder.write_all(digest.to_string().as_bytes()).unwrap();
+            encoder.finish().unwrap();
+        }
+
+        let events = load_events(&dir);
+        // Any other JSON file is not a log.
+        fs::write(day.join("notes.json"), r#"{ "owner": "security" }"#).unwrap();
+        let stray = load_events(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let events = events.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action(), "sts:GetCallerIdentity");
+        assert!(stray.unwrap_err().to_string().contains("notes.json"));
+    }
+}
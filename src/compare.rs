@@ -1,75 +1,384 @@
-use std::collections::HashMap;
 use std::collections::HashSet;
-use std::iter::FromIterator;
+use std::str::FromStr;
 
 use crate::aws::iam::Policy;
 use crate::capability::extract_capabilities_from_policies;
-use crate::capability::{CapabilityComparisonRow, CapabilityRow};
+use crate::capability::{
+    CapabilityComparisonRow, CapabilityKind, CapabilityRow, CapabilityStatus, Relation,
+    SemanticComparisonRow,
+};
+use crate::catalog::ActionCatalog;
+use crate::trust::{TrustComparisonRow, TrustFinding, TrustRow};
 
-/// Represents an AWS capability, consisting of a resource and an action.
+/// Which capabilities `compare_policies` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompareMode {
+    /// Capabilities that not every role has; for two roles, the symmetric difference.
+    #[default]
+    Difference,
+    /// Capabilities that only the first role has.
+    OnlyInFirst,
+    /// Capabilities that only the second role has.
+    OnlyInSecond,
+    /// Capabilities that every role has, without per-role columns.
+    Intersection,
+    /// Capabilities that any role has, without per-role columns.
+    Union,
+    /// Every capability of any role, with per-role columns.
+    Full,
+}
 
-/// Compares two sets of policies and outputs a table displaying their differences.
-pub fn compare_policies(
-    policies1: Vec<Policy>,
-    policies2: Vec<Policy>,
-) -> Vec<CapabilityComparisonRow> {
-    let mut capabilities1 = HashMap::<CapabilityRow, bool>::new();
-    let mut capabilities2 = HashMap::<CapabilityRow, bool>::new();
+impl CompareMode {
+    /// Returns true if a capability held by the roles flagged in `has_capability` is reported.
+    fn includes(&self, has_capability: &[bool]) -> bool {
+        let only = |index: usize| {
+            has_capability
+                .iter()
+                .enumerate()
+                .all(|(i, has)| *has == (i == index))
+        };
+        match self {
+            CompareMode::Difference => has_capability.contains(&false),
+            CompareMode::OnlyInFirst => only(0),
+            CompareMode::OnlyInSecond => only(1),
+            CompareMode::Intersection => !has_capability.contains(&false),
+            CompareMode::Union | CompareMode::Full => true,
+        }
+    }
 
-    let caps_from_policies1 = extract_capabilities_from_policies(policies1);
-    for cap in caps_from_policies1 {
-        capabilities1.insert(cap, true);
+    /// Returns true if the rows carry a column per role. The set modes leave them out, since
+    /// every value would be the same.
+    pub fn shows_roles(&self) -> bool {
+        !matches!(self, CompareMode::Intersection | CompareMode::Union)
     }
+}
+
+impl FromStr for CompareMode {
+    type Err = String;
 
-    let caps_from_policies2 = extract_capabilities_from_policies(policies2);
-    for cap in caps_from_policies2 {
-        capabilities2.insert(cap, true);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "difference" => Ok(CompareMode::Difference),
+            "only-in-first" => Ok(CompareMode::OnlyInFirst),
+            "only-in-second" => Ok(CompareMode::OnlyInSecond),
+            "intersection" => Ok(CompareMode::Intersection),
+            "union" => Ok(CompareMode::Union),
+            "full" => Ok(CompareMode::Full),
+            _ => Err(format!("Invalid compare mode: {}", s)),
+        }
+    }
+}
+
+/// Compares the policies of several roles and outputs a table of the capabilities selected by
+/// `mode`, with one column per role unless the mode leaves them out.
+///
+/// Capabilities that are fully denied by an explicit deny are not counted as granted. When a
+/// `catalog` is given, wildcard actions are expanded before comparing.
+pub fn compare_policies(
+    roles: Vec<(String, Vec<Policy>)>,
+    mode: CompareMode,
+    catalog: Option<&ActionCatalog>,
+) -> Vec<CapabilityComparisonRow> {
+    let mut names = Vec::new();
+    let mut capabilities = Vec::<HashSet<CapabilityRow>>::new();
+
+    for (name, policies) in roles {
+        names.push(name);
+        capabilities.push(
+            extract_capabilities_from_policies(policies, catalog)
+                .into_iter()
+                .filter(|cap| cap.status != CapabilityStatus::Denied)
+                .collect(),
+        );
     }
 
-    let all_keys_set: HashSet<&CapabilityRow> =
-        HashSet::from_iter(capabilities1.keys().chain(capabilities2.keys()));
+    let all_keys_set: HashSet<&CapabilityRow> = capabilities.iter().flatten().collect();
     let mut all_keys: Vec<&CapabilityRow> = all_keys_set.into_iter().collect();
     all_keys.sort_by(|a, b| {
-        let res = a.resource.cmp(&b.resource);
-        if res == std::cmp::Ordering::Equal {
-            a.action.cmp(&b.action)
-        } else {
-            res
-        }
+        (a.resource.cmp(&b.resource))
+            .then(a.action.cmp(&b.action))
+            .then(a.condition.cmp(&b.condition))
     });
 
     let capability_rows: Vec<CapabilityComparisonRow> = all_keys
         .into_iter()
         .filter_map(|key| {
-            let has_capability1 = capabilities1.get(key).unwrap_or(&false);
-            let has_capability2 = capabilities2.get(key).unwrap_or(&false);
-
-            if !(*has_capability1 && *has_capability2) {
-                Some(CapabilityComparisonRow {
-                    resource: key.resource.clone(),
-                    action: key.action.clone(),
-                    has_capability1: *has_capability1,
-                    has_capability2: *has_capability2,
+            let has_capability: Vec<bool> = capabilities
+                .iter()
+                .map(|role_capabilities| {
+                    role_capabilities.contains(key)
+                        || catalog.is_some_and(|catalog| {
+                            covered_by_unexpanded(role_capabilities, key, catalog)
+                        })
                 })
-            } else {
-                None
+                .collect();
+
+            if !mode.includes(&has_capability) {
+                return None;
             }
+            let (roles, has_capability) = if mode.shows_roles() {
+                (names.clone(), has_capability)
+            } else {
+                (Vec::new(), Vec::new())
+            };
+            Some(CapabilityComparisonRow {
+                resource: key.resource.clone(),
+                action: key.action.clone(),
+                condition: key.condition.clone(),
+                roles,
+                has_capability,
+            })
         })
         .collect();
 
     capability_rows
 }
 
+/// Returns true if one of the capabilities the catalog could not fully expand (e.g. `*` or
+/// `NotAction`, kept as they are) grants `key`. Without this, `*` would not count as granting
+/// the actions of services missing from the catalog.
+fn covered_by_unexpanded(
+    capabilities: &HashSet<CapabilityRow>,
+    key: &CapabilityRow,
+    catalog: &ActionCatalog,
+) -> bool {
+    capabilities.iter().any(|capability| {
+        !catalog.knows_services_of(&capability.action_set()) && grant_covers(capability, key)
+    })
+}
+
+/// Compares the roles and selects the capabilities granted by some role that no grant of the
+/// first role covers, i.e. the reasons the other roles are not subsets of the first. Grants
+/// narrower than one of the first role's (e.g. `s3:GetObject` on a bucket against `s3:*` on
+/// `*`) are not violations.
+pub fn subset_violations(
+    roles: Vec<(String, Vec<Policy>)>,
+    catalog: Option<&ActionCatalog>,
+) -> Vec<CapabilityComparisonRow> {
+    let baseline = match roles.first() {
+        Some((_, policies)) => effective_capabilities(policies.clone(), catalog),
+        None => return Vec::new(),
+    };
+
+    compare_policies(roles, CompareMode::Full, catalog)
+        .into_iter()
+        .filter(|row| row.has_capability.first() == Some(&false))
+        .filter(|row| {
+            let granted = CapabilityRow {
+                resource: row.resource.clone(),
+                action: row.action.clone(),
+                condition: row.condition.clone(),
+                kind: CapabilityKind::Grant,
+                status: CapabilityStatus::Allowed,
+                source: String::new(),
+                restricted_by: String::new(),
+            };
+            !baseline
+                .iter()
+                .any(|approved| grant_covers(approved, &granted))
+        })
+        .collect()
+}
+
+/// Compares the trust policies of several roles and outputs a table of the principals selected
+/// by `mode`, with one column per role unless the mode leaves them out. A role without a trust
+/// policy trusts no one.
+pub fn compare_trust_policies(
+    roles: Vec<(String, Vec<TrustRow>)>,
+    mode: CompareMode,
+) -> Vec<TrustComparisonRow> {
+    let (names, trusts): (Vec<String>, Vec<HashSet<TrustRow>>) = roles
+        .into_iter()
+        .map(|(name, rows)| (name, rows.into_iter().collect()))
+        .unzip();
+
+    let all_keys_set: HashSet<&TrustRow> = trusts.iter().flatten().collect();
+    let mut all_keys: Vec<&TrustRow> = all_keys_set.into_iter().collect();
+    all_keys.sort_by(|a, b| {
+        (a.principal_type.cmp(&b.principal_type))
+            .then(a.principal.cmp(&b.principal))
+            .then(a.action.cmp(&b.action))
+            .then(a.condition.cmp(&b.condition))
+    });
+
+    all_keys
+        .into_iter()
+        .filter_map(|key| {
+            let trusted: Vec<bool> = trusts.iter().map(|rows| rows.contains(key)).collect();
+
+            if !mode.includes(&trusted) {
+                return None;
+            }
+            // Findings can differ between roles trusting the same principal, e.g. an
+            // sts:ExternalId is only needed by roles in other accounts than the principal.
+            let findings: Vec<Vec<TrustFinding>> = trusts
+                .iter()
+                .map(|rows| {
+                    rows.get(key)
+                        .map(|row| row.findings.clone())
+                        .unwrap_or_default()
+                })
+                .collect();
+            let (roles, trusted, findings) = if mode.shows_roles() {
+                (names.clone(), trusted, findings)
+            } else {
+                let mut any: Vec<TrustFinding> = findings.into_iter().flatten().collect();
+                any.sort();
+                any.dedup();
+                (Vec::new(), Vec::new(), vec![any])
+            };
+            Some(TrustComparisonRow {
+                principal_type: key.principal_type.clone(),
+                principal: key.principal.clone(),
+                action: key.action.clone(),
+                condition: key.condition.clone(),
+                roles,
+                trusted,
+                findings,
+            })
+        })
+        .collect()
+}
+
+/// Compares two sets of policies treating actions and resources as wildcard patterns, so that
+/// e.g. `arn:aws:s3:::bucket/*` is recognised as covering `arn:aws:s3:::bucket/logs/*` instead
+/// of being reported as an unrelated difference.
+///
+/// A broader grant is only reported as such when the other role has nothing covering it.
+pub fn compare_policies_semantically(
+    policies1: Vec<Policy>,
+    policies2: Vec<Policy>,
+    catalog: Option<&ActionCatalog>,
+) -> Vec<SemanticComparisonRow> {
+    let capabilities1 = effective_capabilities(policies1, catalog);
+    let capabilities2 = effective_capabilities(policies2, catalog);
+
+    let covered1: Vec<bool> = capabilities1
+        .iter()
+        .map(|c1| capabilities2.iter().any(|c2| grant_covers(c2, c1)))
+        .collect();
+    let covered2: Vec<bool> = capabilities2
+        .iter()
+        .map(|c2| capabilities1.iter().any(|c1| grant_covers(c1, c2)))
+        .collect();
+
+    let mut related1 = vec![false; capabilities1.len()];
+    let mut related2 = vec![false; capabilities2.len()];
+    let mut rows = Vec::new();
+
+    for (i, c1) in capabilities1.iter().enumerate() {
+        for (j, c2) in capabilities2.iter().enumerate() {
+            let c1_covers = grant_covers(c1, c2);
+            let c2_covers = grant_covers(c2, c1);
+
+            let relation = match (c1_covers, c2_covers) {
+                (true, true) => Some(Relation::Equivalent),
+                (true, false) if !covered1[i] => Some(Relation::Role1Broader),
+                (false, true) if !covered2[j] => Some(Relation::Role2Broader),
+                _ => None,
+            };
+
+            if let Some(relation) = relation {
+                let narrower = if relation == Relation::Role1Broader {
+                    c2
+                } else {
+                    c1
+                };
+                rows.push(SemanticComparisonRow {
+                    resource1: c1.resource.clone(),
+                    action1: c1.action.clone(),
+                    resource2: c2.resource.clone(),
+                    action2: c2.action.clone(),
+                    condition: narrower.condition.clone(),
+                    relation,
+                });
+            }
+            if c1_covers || c2_covers {
+                related1[i] = true;
+                related2[j] = true;
+            }
+        }
+    }
+
+    for (c1, _) in capabilities1.iter().zip(related1).filter(|(_, r)| !r) {
+        rows.push(SemanticComparisonRow {
+            resource1: c1.resource.clone(),
+            action1: c1.action.clone(),
+            resource2: String::new(),
+            action2: String::new(),
+            condition: c1.condition.clone(),
+            relation: Relation::OnlyInRole1,
+        });
+    }
+    for (c2, _) in capabilities2.iter().zip(related2).filter(|(_, r)| !r) {
+        rows.push(SemanticComparisonRow {
+            resource1: String::new(),
+            action1: String::new(),
+            resource2: c2.resource.clone(),
+            action2: c2.action.clone(),
+            condition: c2.condition.clone(),
+            relation: Relation::OnlyInRole2,
+        });
+    }
+
+    rows.sort_by(|a, b| {
+        (a.resource1.as_str().max(&a.resource2))
+            .cmp(b.resource1.as_str().max(&b.resource2))
+            .then(
+                a.action1
+                    .as_str()
+                    .max(&a.action2)
+                    .cmp(b.action1.as_str().max(&b.action2)),
+            )
+            .then(a.relation.cmp(&b.relation))
+    });
+
+    rows
+}
+
+/// The capabilities that are not fully denied, without duplicates.
+fn effective_capabilities(
+    policies: Vec<Policy>,
+    catalog: Option<&ActionCatalog>,
+) -> Vec<CapabilityRow> {
+    let mut seen = HashSet::new();
+
+    extract_capabilities_from_policies(policies, catalog)
+        .into_iter()
+        .filter(|capability| capability.status != CapabilityStatus::Denied)
+        .filter(|capability| {
+            seen.insert((
+                capability.resource.clone(),
+                capability.action.clone(),
+                capability.condition.clone(),
+            ))
+        })
+        .collect()
+}
+
+/// Returns true if the `broad` grant allows everything the `narrow` one does. A conditional
+/// grant only covers grants with the same condition.
+fn grant_covers(broad: &CapabilityRow, narrow: &CapabilityRow) -> bool {
+    (broad.condition.is_empty() || broad.condition == narrow.condition)
+        && broad
+            .action_set()
+            .to_lowercase()
+            .covers(&narrow.action_set().to_lowercase())
+        && broad.resource_set().covers(&narrow.resource_set())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::aws::iam::{Effect, PolicyStatement};
+    use crate::aws::iam::{Effect, PolicyOrigin, PolicyStatement};
+    use crate::output::format::OutputSerializable;
 
     fn make_policy_statement(actions: Vec<&str>, resources: Vec<&str>) -> PolicyStatement {
         PolicyStatement {
             action: actions.into_iter().map(String::from).collect(),
             resource: resources.into_iter().map(String::from).collect(),
             effect: Effect::Allow,
+            ..Default::default()
         }
     }
 
@@ -77,9 +386,17 @@ mod tests {
         Policy {
             statements,
             version: "2012-10-17".to_string(),
+            origin: PolicyOrigin::default(),
         }
     }
 
+    fn named(policies1: Vec<Policy>, policies2: Vec<Policy>) -> Vec<(String, Vec<Policy>)> {
+        vec![
+            ("role1".to_string(), policies1),
+            ("role2".to_string(), policies2),
+        ]
+    }
+
     #[test]
     fn test_compare_policies() {
         let policies1 = vec![make_policy(vec![make_policy_statement(
@@ -92,18 +409,373 @@ mod tests {
             vec!["arn:aws:s3:::mybucket/*"],
         )])];
 
-        let capability_rows = compare_policies(policies1, policies2);
+        let capability_rows =
+            compare_policies(named(policies1, policies2), CompareMode::default(), None);
 
         assert_eq!(capability_rows.len(), 2);
 
         assert_eq!(capability_rows[0].resource, "arn:aws:s3:::mybucket");
         assert_eq!(capability_rows[0].action, "s3:ListBucket");
-        assert_eq!(capability_rows[0].has_capability1, true);
-        assert_eq!(capability_rows[0].has_capability2, false);
+        assert_eq!(capability_rows[0].has_capability, vec![true, false]);
 
         assert_eq!(capability_rows[1].resource, "arn:aws:s3:::mybucket/*");
         assert_eq!(capability_rows[1].action, "s3:GetObject");
-        assert_eq!(capability_rows[1].has_capability1, false);
-        assert_eq!(capability_rows[1].has_capability2, true);
+        assert_eq!(capability_rows[1].has_capability, vec![false, true]);
+        assert_eq!(capability_rows[1].roles, vec!["role1", "role2"]);
+    }
+
+    #[test]
+    fn test_comparison_json_keeps_roles_apart_from_fields() {
+        let roles = vec![
+            (
+                "action".to_string(),
+                vec![make_policy(vec![make_policy_statement(
+                    vec!["s3:GetObject"],
+                    vec!["*"],
+                )])],
+            ),
+            ("resource".to_string(), Vec::new()),
+        ];
+
+        let capability_rows = compare_policies(roles, CompareMode::default(), None);
+
+        assert_eq!(
+            capability_rows[0].to_json_value(),
+            serde_json::json!({
+                "resource": "*",
+                "action": "s3:GetObject",
+                "condition": "",
+                "roles": { "action": true, "resource": false }
+            })
+        );
+    }
+
+    #[test]
+    fn test_compare_policies_with_expanded_actions() {
+        let catalog: ActionCatalog = serde_json::from_value(serde_json::json!({
+            "version": "test",
+            "services": { "s3": ["GetObject", "PutObject"] }
+        }))
+        .unwrap();
+        let policies1 = vec![make_policy(vec![make_policy_statement(
+            vec!["s3:*"],
+            vec!["*"],
+        )])];
+        let policies2 = vec![make_policy(vec![make_policy_statement(
+            vec!["s3:GetObject"],
+            vec!["*"],
+        )])];
+
+        let capability_rows = compare_policies(
+            named(policies1, policies2),
+            CompareMode::default(),
+            Some(&catalog),
+        );
+
+        assert_eq!(capability_rows.len(), 1);
+        assert_eq!(capability_rows[0].action, "s3:PutObject");
+        assert_eq!(capability_rows[0].has_capability, vec![true, false]);
+    }
+
+    #[test]
+    fn test_compare_policies_with_expanded_actions_keeps_uncatalogued_services() {
+        let catalog: ActionCatalog = serde_json::from_value(serde_json::json!({
+            "version": "test",
+            "services": { "s3": ["GetObject"] }
+        }))
+        .unwrap();
+        let admin = vec![make_policy(vec![make_policy_statement(
+            vec!["*"],
+            vec!["*"],
+        )])];
+        let deleter = vec![make_policy(vec![make_policy_statement(
+            vec!["rds:DeleteDBInstance"],
+            vec!["*"],
+        )])];
+
+        let capability_rows = compare_policies(
+            named(admin, deleter),
+            CompareMode::OnlyInSecond,
+            Some(&catalog),
+        );
+
+        assert!(capability_rows.is_empty());
+    }
+
+    #[test]
+    fn test_compare_policies_across_many_roles() {
+        let role =
+            |actions: Vec<&str>| vec![make_policy(vec![make_policy_statement(actions, vec!["*"])])];
+        let roles = vec![
+            (
+                "dev".to_string(),
+                role(vec!["s3:GetObject", "s3:PutObject"]),
+            ),
+            (
+                "staging".to_string(),
+                role(vec!["s3:GetObject", "s3:PutObject"]),
+            ),
+            ("prod".to_string(), role(vec!["s3:GetObject"])),
+        ];
+
+        let capability_rows = compare_policies(roles, CompareMode::Difference, None);
+
+        assert_eq!(capability_rows.len(), 1);
+        assert_eq!(capability_rows[0].action, "s3:PutObject");
+        assert_eq!(capability_rows[0].has_capability, vec![true, true, false]);
+        assert_eq!(
+            capability_rows[0].csv_record(),
+            vec!["*", "s3:PutObject", "", "true", "true", "false"]
+        );
+        assert_eq!(
+            capability_rows[0].to_json_value()["roles"]["prod"],
+            serde_json::json!(false)
+        );
+        assert_eq!(
+            CapabilityComparisonRow::header(&capability_rows[0].roles),
+            vec!["Resource", "Action", "Condition", "dev", "staging", "prod"]
+        );
+    }
+
+    #[test]
+    fn test_compare_policies_modes() {
+        let role =
+            |actions: Vec<&str>| vec![make_policy(vec![make_policy_statement(actions, vec!["*"])])];
+        let actions = |mode: CompareMode| {
+            let roles = vec![
+                (
+                    "role1".to_string(),
+                    role(vec!["s3:GetObject", "s3:ListBucket"]),
+                ),
+                (
+                    "role2".to_string(),
+                    role(vec!["s3:GetObject", "s3:PutObject"]),
+                ),
+            ];
+            compare_policies(roles, mode, None)
+                .into_iter()
+                .map(|row| (row.action, row.has_capability))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            actions(CompareMode::OnlyInFirst),
+            vec![("s3:ListBucket".to_string(), vec![true, false])]
+        );
+        assert_eq!(
+            actions(CompareMode::OnlyInSecond),
+            vec![("s3:PutObject".to_string(), vec![false, true])]
+        );
+        assert_eq!(
+            actions(CompareMode::Intersection),
+            vec![("s3:GetObject".to_string(), vec![])]
+        );
+        assert_eq!(actions(CompareMode::Union).len(), 3);
+        assert_eq!(actions(CompareMode::Full)[0].1, vec![true, true]);
+        assert_eq!("only-in-second".parse(), Ok(CompareMode::OnlyInSecond));
+    }
+
+    #[test]
+    fn test_subset_violations() {
+        let role =
+            |actions: Vec<&str>| vec![make_policy(vec![make_policy_statement(actions, vec!["*"])])];
+        let roles = vec![
+            (
+                "approved".to_string(),
+                role(vec!["s3:GetObject", "s3:ListBucket"]),
+            ),
+            (
+                "deployed".to_string(),
+                role(vec!["s3:GetObject", "s3:DeleteObject"]),
+            ),
+        ];
+
+        let violations = subset_violations(roles, None);
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].action, "s3:DeleteObject");
+    }
+
+    #[test]
+    fn test_subset_violations_allow_narrower_grants() {
+        let roles = vec![
+            (
+                "approved".to_string(),
+                vec![make_policy(vec![make_policy_statement(
+                    vec!["s3:*"],
+                    vec!["*"],
+                )])],
+            ),
+            (
+                "deployed".to_string(),
+                vec![make_policy(vec![
+                    make_policy_statement(vec!["s3:GetObject"], vec!["arn:aws:s3:::b/*"]),
+                    make_policy_statement(vec!["sqs:SendMessage"], vec!["*"]),
+                ])],
+            ),
+        ];
+
+        let violations = subset_violations(roles, None);
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].action, "sqs:SendMessage");
+    }
+
+    #[test]
+    fn test_compare_trust_policies() {
+        let trust = |principal: &str, findings: Vec<TrustFinding>| TrustRow {
+            principal_type: "AWS".to_string(),
+            principal: principal.to_string(),
+            action: "sts:AssumeRole".to_string(),
+            condition: String::new(),
+            findings,
+        };
+        let roles = vec![
+            (
+                "staging".to_string(),
+                vec![
+                    trust("111111111111", vec![TrustFinding::MissingExternalId]),
+                    trust("222222222222", vec![TrustFinding::MissingExternalId]),
+                ],
+            ),
+            // prod lives in 111111111111, so its own account needs no external ID.
+            ("prod".to_string(), vec![trust("111111111111", Vec::new())]),
+        ];
+
+        let rows = compare_trust_policies(roles.clone(), CompareMode::Difference);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].principal, "222222222222");
+        assert_eq!(rows[0].trusted, vec![true, false]);
+        assert_eq!(
+            rows[0].csv_record(),
+            vec![
+                "AWS",
+                "222222222222",
+                "sts:AssumeRole",
+                "",
+                "true",
+                "missing-external-id",
+                "false",
+                ""
+            ]
+        );
+
+        let rows = compare_trust_policies(roles.clone(), CompareMode::Full);
+        assert_eq!(rows[0].principal, "111111111111");
+        assert_eq!(
+            rows[0].to_json_value()["roles"],
+            serde_json::json!({
+                "staging": { "trusted": true, "findings": ["missing-external-id"] },
+                "prod": { "trusted": true, "findings": [] }
+            })
+        );
+
+        let rows = compare_trust_policies(roles, CompareMode::Intersection);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].principal, "111111111111");
+        assert!(rows[0].roles.is_empty());
+        assert_eq!(
+            rows[0].csv_record(),
+            vec![
+                "AWS",
+                "111111111111",
+                "sts:AssumeRole",
+                "",
+                "missing-external-id"
+            ]
+        );
+        assert_eq!(
+            TrustComparisonRow::header(&[]).last().map(String::as_str),
+            Some("Findings")
+        );
+    }
+
+    fn semantic_rows(
+        statements1: Vec<PolicyStatement>,
+        statements2: Vec<PolicyStatement>,
+    ) -> Vec<(String, String, String, String, Relation)> {
+        compare_policies_semantically(
+            vec![make_policy(statements1)],
+            vec![make_policy(statements2)],
+            None,
+        )
+        .into_iter()
+        .map(|row| {
+            (
+                row.resource1,
+                row.action1,
+                row.resource2,
+                row.action2,
+                row.relation,
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_compare_policies_semantically_detects_broader_resources() {
+        let rows = semantic_rows(
+            vec![make_policy_statement(
+                vec!["s3:GetObject"],
+                vec!["arn:aws:s3:::bucket/*"],
+            )],
+            vec![make_policy_statement(
+                vec!["s3:getobject"],
+                vec!["arn:aws:s3:::bucket/logs/*"],
+            )],
+        );
+
+        assert_eq!(
+            rows,
+            vec![(
+                "arn:aws:s3:::bucket/*".to_string(),
+                "s3:GetObject".to_string(),
+                "arn:aws:s3:::bucket/logs/*".to_string(),
+                "s3:getobject".to_string(),
+                Relation::Role1Broader
+            )]
+        );
+    }
+
+    #[test]
+    fn test_compare_policies_semantically_classifies_rows() {
+        let rows = semantic_rows(
+            vec![
+                make_policy_statement(vec!["s3:ListBucket"], vec!["arn:aws:s3:::bucket"]),
+                make_policy_statement(vec!["sqs:SendMessage"], vec!["*"]),
+            ],
+            vec![
+                make_policy_statement(vec!["s3:List*"], vec!["arn:aws:s3:::*"]),
+                make_policy_statement(vec!["sqs:SendMessage"], vec!["*"]),
+                make_policy_statement(vec!["ec2:RunInstances"], vec!["*"]),
+            ],
+        );
+
+        let relations: Vec<Relation> = rows.iter().map(|row| row.4).collect();
+        assert_eq!(
+            relations,
+            vec![
+                Relation::OnlyInRole2,
+                Relation::Equivalent,
+                Relation::Role2Broader
+            ]
+        );
+        assert_eq!(rows[2].1, "s3:ListBucket");
+        assert_eq!(rows[2].3, "s3:List*");
+    }
+
+    #[test]
+    fn test_compare_policies_semantically_ignores_covered_narrower_grants() {
+        // Role2 also grants s3:* on *, so role1 is not broader than role2's narrower grant.
+        let rows = semantic_rows(
+            vec![make_policy_statement(vec!["s3:*"], vec!["*"])],
+            vec![
+                make_policy_statement(vec!["s3:*"], vec!["*"]),
+                make_policy_statement(vec!["s3:GetObject"], vec!["arn:aws:s3:::bucket/*"]),
+            ],
+        );
+
+        let relations: Vec<Relation> = rows.iter().map(|row| row.4).collect();
+        assert_eq!(relations, vec![Relation::Equivalent]);
     }
 }
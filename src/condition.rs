@@ -0,0 +1,446 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::aws::iam::{scalar_or_array, Condition};
+use crate::wildcard;
+
+/// The condition keys of a request (e.g. `aws:SourceIp`, `aws:PrincipalTag/team`) and their
+/// values. Keys are matched case-insensitively, as IAM does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    keys: BTreeMap<String, Vec<String>>,
+}
+
+impl RequestContext {
+    /// Builds a context from a JSON object mapping condition keys to a value or list of values.
+    pub fn from_value(value: Value) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let object = match value {
+            Value::Object(object) => object,
+            _ => return Err("Request context must be a JSON object of condition keys".into()),
+        };
+
+        let mut keys = BTreeMap::new();
+        for (key, values) in object {
+            let values = scalar_or_array(values)
+                .map_err(|e| format!("Invalid value for context key {}: {}", key, e))?;
+            keys.insert(key.to_lowercase(), values);
+        }
+
+        Ok(RequestContext { keys })
+    }
+
+    /// Loads a context from a JSON file, e.g. `{"aws:SourceIp": "203.0.113.10"}`.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read request context {}: {}", path.display(), e))?;
+        let value = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid request context {}: {}", path.display(), e))?;
+        RequestContext::from_value(value)
+    }
+
+    /// Returns the values of a condition key, or `None` if the request does not carry it.
+    pub fn get(&self, key: &str) -> Option<&Vec<String>> {
+        self.keys.get(&key.to_lowercase())
+    }
+}
+
+/// How an operator treats multi-valued keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetQualifier {
+    /// No qualifier: at least one request value must satisfy the operator.
+    Single,
+    /// `ForAnyValue:`: at least one request value must satisfy the operator, so a missing key
+    /// fails even negated operators.
+    AnyValue,
+    /// `ForAllValues:`: every request value must satisfy the operator.
+    AllValues,
+}
+
+/// Evaluates a statement's `Condition` block against a request context.
+///
+/// Every operator must be satisfied, and within an operator every key. A key is satisfied if a
+/// request value matches any of the policy values (or, for negated operators such as
+/// `StringNotEquals`, none of them). Unknown operators are never satisfied.
+pub fn evaluate_condition(condition: &Condition, context: &RequestContext) -> bool {
+    condition.iter().all(|(operator, keys)| {
+        keys.iter()
+            .all(|(key, values)| evaluate_operator(operator, key, values, context))
+    })
+}
+
+fn evaluate_operator(
+    operator: &str,
+    key: &str,
+    policy_values: &[String],
+    context: &RequestContext,
+) -> bool {
+    let (qualifier, operator) = if let Some(rest) = operator.strip_prefix("ForAnyValue:") {
+        (SetQualifier::AnyValue, rest)
+    } else if let Some(rest) = operator.strip_prefix("ForAllValues:") {
+        (SetQualifier::AllValues, rest)
+    } else {
+        (SetQualifier::Single, operator)
+    };
+    let (operator, if_exists) = match operator.strip_suffix("IfExists") {
+        Some(rest) => (rest, true),
+        None => (operator, false),
+    };
+
+    let request_values = context.get(key);
+
+    if operator == "Null" {
+        let is_absent = request_values.is_none();
+        return policy_values
+            .iter()
+            .any(|value| value.eq_ignore_ascii_case(&is_absent.to_string()));
+    }
+
+    let negated = is_negated(operator);
+    let request_values = match request_values {
+        Some(values) => values,
+        // A missing key satisfies `...IfExists`, unqualified negated operators and
+        // `ForAllValues:` (which holds vacuously), and fails everything else, including
+        // `ForAnyValue:` with a negated operator (there is no value to differ).
+        None => {
+            return if_exists
+                || (negated && qualifier == SetQualifier::Single)
+                || qualifier == SetQualifier::AllValues
+        }
+    };
+
+    let policy_values: Vec<String> = policy_values
+        .iter()
+        .map(|value| substitute_policy_variables(value, context))
+        .collect();
+
+    let satisfies = |request_value: &String| {
+        let mut matched = policy_values
+            .iter()
+            .map(|policy_value| compare_values(operator, request_value, policy_value));
+        if negated {
+            matched.all(|m| m == Some(false))
+        } else {
+            matched.any(|m| m == Some(true))
+        }
+    };
+
+    match qualifier {
+        SetQualifier::Single | SetQualifier::AnyValue => request_values.iter().any(satisfies),
+        SetQualifier::AllValues => request_values.iter().all(satisfies),
+    }
+}
+
+/// Returns true for the negated operators, e.g. `StringNotEquals` or `NotIpAddress`.
+fn is_negated(operator: &str) -> bool {
+    matches!(
+        operator,
+        "StringNotEquals"
+            | "StringNotEqualsIgnoreCase"
+            | "StringNotLike"
+            | "NumericNotEquals"
+            | "DateNotEquals"
+            | "NotIpAddress"
+            | "ArnNotEquals"
+            | "ArnNotLike"
+    )
+}
+
+/// Compares a request value with a policy value using the positive form of `operator` (e.g.
+/// `StringEquals` for `StringNotEquals`). Returns `None` for unknown operators or values that
+/// cannot be parsed, which never satisfy a condition.
+fn compare_values(operator: &str, request_value: &str, policy_value: &str) -> Option<bool> {
+    match operator {
+        "StringEquals" | "StringNotEquals" => Some(request_value == policy_value),
+        "StringEqualsIgnoreCase" | "StringNotEqualsIgnoreCase" => {
+            Some(request_value.eq_ignore_ascii_case(policy_value))
+        }
+        "StringLike" | "StringNotLike" => Some(wildcard::matches(policy_value, request_value)),
+        "ArnEquals" | "ArnNotEquals" | "ArnLike" | "ArnNotLike" => {
+            Some(wildcard::matches(policy_value, request_value))
+        }
+        "Bool" => Some(request_value.eq_ignore_ascii_case(policy_value)),
+        "BinaryEquals" => Some(request_value == policy_value),
+        "NumericEquals" | "NumericNotEquals" => {
+            compare_numbers(request_value, policy_value, |o| o.is_eq())
+        }
+        "NumericLessThan" => compare_numbers(request_value, policy_value, |o| o.is_lt()),
+        "NumericLessThanEquals" => compare_numbers(request_value, policy_value, |o| o.is_le()),
+        "NumericGreaterThan" => compare_numbers(request_value, policy_value, |o| o.is_gt()),
+        "NumericGreaterThanEquals" => compare_numbers(request_value, policy_value, |o| o.is_ge()),
+        "DateEquals" | "DateNotEquals" => compare_dates(request_value, policy_value, |o| o.is_eq()),
+        "DateLessThan" => compare_dates(request_value, policy_value, |o| o.is_lt()),
+        "DateLessThanEquals" => compare_dates(request_value, policy_value, |o| o.is_le()),
+        "DateGreaterThan" => compare_dates(request_value, policy_value, |o| o.is_gt()),
+        "DateGreaterThanEquals" => compare_dates(request_value, policy_value, |o| o.is_ge()),
+        "IpAddress" | "NotIpAddress" => ip_in_cidr(request_value, policy_value),
+        _ => None,
+    }
+}
+
+fn compare_numbers(
+    request_value: &str,
+    policy_value: &str,
+    accept: impl Fn(std::cmp::Ordering) -> bool,
+) -> Option<bool> {
+    let request_value: f64 = request_value.parse().ok()?;
+    let policy_value: f64 = policy_value.parse().ok()?;
+    request_value.partial_cmp(&policy_value).map(accept)
+}
+
+fn compare_dates(
+    request_value: &str,
+    policy_value: &str,
+    accept: impl Fn(std::cmp::Ordering) -> bool,
+) -> Option<bool> {
+    let request_value = parse_date(request_value)?;
+    let policy_value = parse_date(policy_value)?;
+    Some(accept(request_value.cmp(&policy_value)))
+}
+
+/// Parses an ISO 8601 date (`2026-01-01T00:00:00Z` or `2026-01-01`) or epoch seconds.
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0).map(|date| date.and_utc());
+    }
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+}
+
+/// Returns true if `ip` is within `cidr` (or equal to it, when no prefix length is given).
+fn ip_in_cidr(ip: &str, cidr: &str) -> Option<bool> {
+    let ip: IpAddr = ip.parse().ok()?;
+    let (network, prefix) = match cidr.split_once('/') {
+        Some((network, prefix)) => (network.parse::<IpAddr>().ok()?, prefix.parse::<u32>().ok()?),
+        None => {
+            let network = cidr.parse::<IpAddr>().ok()?;
+            let prefix = if network.is_ipv4() { 32 } else { 128 };
+            (network, prefix)
+        }
+    };
+
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            (u32::from(ip) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return Some(false),
+    };
+    if prefix > bits {
+        return None;
+    }
+
+    let mask = if prefix == 0 {
+        0
+    } else {
+        (u128::MAX << (bits - prefix)) & (u128::MAX >> (128 - bits))
+    };
+    Some(ip & mask == network & mask)
+}
+
+/// Replaces policy variables such as `${aws:username}` with the first value of that key in the
+/// request context. Variables the context does not define are left untouched.
+fn substitute_policy_variables(value: &str, context: &RequestContext) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let key = &rest[start + 2..start + end];
+        result.push_str(&rest[..start]);
+        match context.get(key).and_then(|values| values.first()) {
+            Some(replacement) => result.push_str(replacement),
+            None => result.push_str(&rest[start..start + end + 1]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn condition(value: Value) -> Condition {
+        let statement: crate::aws::iam::PolicyStatement = serde_json::from_value(json!({
+            "Effect": "Allow",
+            "Action": "*",
+            "Resource": "*",
+            "Condition": value
+        }))
+        .unwrap();
+        statement.condition.unwrap()
+    }
+
+    fn context(value: Value) -> RequestContext {
+        RequestContext::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_string_and_arn_operators() {
+        let condition = condition(json!({
+            "StringEquals": { "aws:RequestedRegion": ["eu-west-1", "eu-central-1"] },
+            "StringLike": { "aws:PrincipalTag/team": "platform-*" },
+            "ArnLike": { "aws:SourceArn": "arn:aws:sns:*:123456789012:*" }
+        }));
+
+        let matching = context(json!({
+            "aws:RequestedRegion": "eu-west-1",
+            "AWS:PrincipalTag/team": "platform-infra",
+            "aws:SourceArn": "arn:aws:sns:eu-west-1:123456789012:alerts"
+        }));
+        assert!(evaluate_condition(&condition, &matching));
+
+        let wrong_region = context(json!({
+            "aws:RequestedRegion": "us-east-1",
+            "aws:PrincipalTag/team": "platform-infra",
+            "aws:SourceArn": "arn:aws:sns:eu-west-1:123456789012:alerts"
+        }));
+        assert!(!evaluate_condition(&condition, &wrong_region));
+    }
+
+    #[test]
+    fn test_missing_keys() {
+        let context = RequestContext::default();
+
+        assert!(!evaluate_condition(
+            &condition(json!({ "StringEquals": { "aws:SourceVpc": "vpc-1" } })),
+            &context
+        ));
+        assert!(evaluate_condition(
+            &condition(json!({ "StringNotEquals": { "aws:SourceVpc": "vpc-1" } })),
+            &context
+        ));
+        assert!(evaluate_condition(
+            &condition(json!({ "StringEqualsIfExists": { "aws:SourceVpc": "vpc-1" } })),
+            &context
+        ));
+        assert!(evaluate_condition(
+            &condition(json!({ "Null": { "aws:SourceVpc": "true" } })),
+            &context
+        ));
+        assert!(!evaluate_condition(
+            &condition(json!({ "Null": { "aws:SourceVpc": false } })),
+            &context
+        ));
+    }
+
+    #[test]
+    fn test_missing_keys_with_set_qualifiers() {
+        let context = RequestContext::default();
+
+        assert!(!evaluate_condition(
+            &condition(json!({ "ForAnyValue:StringNotEquals": { "aws:TagKeys": "owner" } })),
+            &context
+        ));
+        assert!(!evaluate_condition(
+            &condition(json!({ "ForAnyValue:StringEquals": { "aws:TagKeys": "owner" } })),
+            &context
+        ));
+        assert!(evaluate_condition(
+            &condition(json!({ "ForAllValues:StringNotLike": { "aws:TagKeys": "owner" } })),
+            &context
+        ));
+        // Only the listed operators are negated; an unknown name containing "Not" is not.
+        assert!(!evaluate_condition(
+            &condition(json!({ "StringNotAnOperator": { "aws:TagKeys": "owner" } })),
+            &context
+        ));
+    }
+
+    #[test]
+    fn test_ip_address() {
+        let condition =
+            condition(json!({ "IpAddress": { "aws:SourceIp": ["10.0.0.0/8", "2001:db8::/32"] } }));
+
+        assert!(evaluate_condition(
+            &condition,
+            &context(json!({ "aws:SourceIp": "10.20.30.40" }))
+        ));
+        assert!(evaluate_condition(
+            &condition,
+            &context(json!({ "aws:SourceIp": "2001:db8::1" }))
+        ));
+        assert!(!evaluate_condition(
+            &condition,
+            &context(json!({ "aws:SourceIp": "203.0.113.10" }))
+        ));
+        assert!(evaluate_condition(
+            &super::tests::condition(json!({ "NotIpAddress": { "aws:SourceIp": "10.0.0.0/8" } })),
+            &context(json!({ "aws:SourceIp": "203.0.113.10" }))
+        ));
+    }
+
+    #[test]
+    fn test_date_bool_and_numeric() {
+        let condition = condition(json!({
+            "DateGreaterThan": { "aws:CurrentTime": "2026-01-01T00:00:00Z" },
+            "Bool": { "aws:MultiFactorAuthPresent": true },
+            "NumericLessThan": { "aws:MultiFactorAuthAge": 3600 }
+        }));
+
+        assert!(evaluate_condition(
+            &condition,
+            &context(json!({
+                "aws:CurrentTime": "2026-10-18T12:00:00Z",
+                "aws:MultiFactorAuthPresent": "true",
+                "aws:MultiFactorAuthAge": 60
+            }))
+        ));
+        assert!(!evaluate_condition(
+            &condition,
+            &context(json!({
+                "aws:CurrentTime": "2025-12-31",
+                "aws:MultiFactorAuthPresent": true,
+                "aws:MultiFactorAuthAge": 60
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_set_qualifiers() {
+        let any =
+            condition(json!({ "ForAnyValue:StringEquals": { "aws:TagKeys": ["env", "team"] } }));
+        let all =
+            condition(json!({ "ForAllValues:StringEquals": { "aws:TagKeys": ["env", "team"] } }));
+
+        let tags = context(json!({ "aws:TagKeys": ["env", "owner"] }));
+        assert!(evaluate_condition(&any, &tags));
+        assert!(!evaluate_condition(&all, &tags));
+
+        let allowed_tags = context(json!({ "aws:TagKeys": ["team"] }));
+        assert!(evaluate_condition(&all, &allowed_tags));
+
+        // ForAllValues holds vacuously when the key is missing, ForAnyValue does not.
+        assert!(evaluate_condition(&all, &RequestContext::default()));
+        assert!(!evaluate_condition(&any, &RequestContext::default()));
+    }
+
+    #[test]
+    fn test_policy_variables() {
+        let condition =
+            condition(json!({ "StringLike": { "s3:prefix": "home/${aws:username}/*" } }));
+
+        assert!(evaluate_condition(
+            &condition,
+            &context(json!({ "aws:username": "alice", "s3:prefix": "home/alice/docs" }))
+        ));
+        assert!(!evaluate_condition(
+            &condition,
+            &context(json!({ "aws:username": "alice", "s3:prefix": "home/bob/docs" }))
+        ));
+    }
+}
@@ -0,0 +1,369 @@
+use serde_derive::Deserialize;
+use serde_json::json;
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+use crate::aws::iam::Policy;
+use crate::capability::{extract_capabilities_from_policies, CapabilityRow, CapabilityStatus};
+use crate::condition::RequestContext;
+use crate::evaluate::{ceilings_allow, evaluate, Decision, Request};
+use crate::output::format::OutputSerializable;
+use crate::severity::Severity;
+use crate::wildcard::PatternSet;
+
+/// The rules compiled into the binary. Add patterns by editing `data/escalation-rules.json`, or
+/// point `--rules` at another file without rebuilding.
+const EMBEDDED_RULES: &str = include_str!("../data/escalation-rules.json");
+
+/// A versioned set of privilege escalation patterns.
+#[derive(Debug, Deserialize)]
+pub struct EscalationRules {
+    pub version: String,
+    pub rules: Vec<EscalationRule>,
+}
+
+/// A known way to gain more privileges than granted, which needs every one of its
+/// requirements to be granted.
+#[derive(Debug, Deserialize)]
+pub struct EscalationRule {
+    pub id: String,
+    pub severity: Severity,
+    pub description: String,
+    pub requires: Vec<Requirement>,
+}
+
+/// An action the identity must be granted, e.g. `iam:PassRole`, optionally on a resource its
+/// grant must cover, e.g. `*`.
+#[derive(Debug, Deserialize)]
+pub struct Requirement {
+    pub action: String,
+    #[serde(default)]
+    pub resource: Option<String>,
+}
+
+impl Requirement {
+    /// Returns true if the capability grants the required action (on the required resource).
+    fn is_met_by(&self, capability: &CapabilityRow) -> bool {
+        capability
+            .action_set()
+            .to_lowercase()
+            .contains(&self.action.to_lowercase())
+            && self.resource.as_ref().is_none_or(|resource| {
+                capability
+                    .resource_set()
+                    .covers(&PatternSet::Include(vec![resource.clone()]))
+            })
+    }
+
+    /// Returns true if the policies allow the required action through the grant: on the
+    /// required resource, or on the resource the grant names when no resource is required.
+    /// Denies, the permissions boundary and control policies all apply. A conditional grant
+    /// only needs to escape denies and ceilings, since it is allowed once its condition holds.
+    fn is_allowed_through(&self, capability: &CapabilityRow, policies: &[Policy]) -> bool {
+        let resource = match &self.resource {
+            Some(resource) => resource.clone(),
+            None if capability.resource.starts_with("NotResource(") => "*".to_string(),
+            None => capability.resource.clone(),
+        };
+        let request = Request {
+            action: self.action.clone(),
+            resource,
+            context: RequestContext::default(),
+        };
+        match evaluate(policies, &request).decision {
+            Decision::Allowed => true,
+            Decision::ImplicitDeny => {
+                !capability.condition.is_empty() && ceilings_allow(policies, &request)
+            }
+            Decision::ExplicitDeny => false,
+        }
+    }
+}
+
+impl EscalationRules {
+    /// Returns the rules embedded in the binary.
+    pub fn embedded() -> Self {
+        serde_json::from_str(EMBEDDED_RULES).expect("embedded escalation rules are valid JSON")
+    }
+
+    /// Loads rules from a JSON file with the same layout as `data/escalation-rules.json`.
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read escalation rules {}: {}", path.display(), e))?;
+        let rules: EscalationRules = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid escalation rules {}: {}", path.display(), e))?;
+        if let Some(rule) = rules.rules.iter().find(|rule| rule.requires.is_empty()) {
+            return Err(format!(
+                "Invalid escalation rules {}: rule {} requires nothing",
+                path.display(),
+                rule.id
+            )
+            .into());
+        }
+        Ok(rules)
+    }
+
+    /// Loads the rules from `path` if given, or falls back to the embedded ones.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        match path {
+            Some(path) => EscalationRules::from_file(path),
+            None => Ok(EscalationRules::embedded()),
+        }
+    }
+}
+
+/// Represents a row in the escalation report: one grant enabling one requirement of a rule
+/// whose requirements are all met.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EscalationRow {
+    pub rule: String,
+    pub severity: Severity,
+    pub description: String,
+    /// The required action, e.g. `iam:PassRole`.
+    pub requirement: String,
+    /// The granted action and resource, as written in the policy, e.g. `iam:*` on `*`.
+    pub action: String,
+    pub resource: String,
+    pub condition: String,
+    /// The policy the grant comes from, e.g. `managed:<arn>` or `inline:<name>`.
+    pub source: String,
+}
+
+impl OutputSerializable for EscalationRow {
+    fn csv_header() -> Vec<&'static str> {
+        vec![
+            "Rule",
+            "Severity",
+            "Description",
+            "Requirement",
+            "Action",
+            "Resource",
+            "Condition",
+            "Source",
+        ]
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.rule.clone(),
+            self.severity.to_string(),
+            self.description.clone(),
+            self.requirement.clone(),
+            self.action.clone(),
+            self.resource.clone(),
+            self.condition.clone(),
+            self.source.clone(),
+        ]
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        json!({
+            "rule": self.rule,
+            "severity": self.severity.to_string(),
+            "description": self.description,
+            "requirement": self.requirement,
+            "action": self.action,
+            "resource": self.resource,
+            "condition": self.condition,
+            "source": self.source
+        })
+    }
+}
+
+/// Finds the escalation paths open to an identity: the rules whose every requirement is met by
+/// one of its grants. Each requirement is evaluated against all of the policies, so a grant
+/// such as `iam:*` does not count for `iam:PassRole` when a deny, the permissions boundary or a
+/// control policy blocks it. Conditional grants count, with their condition reported.
+///
+/// # Arguments
+///
+/// * `policies` - The identity's policies, including its permissions boundary and control
+///   policies.
+/// * `rules` - The escalation rules to check.
+///
+/// # Returns
+///
+/// A Vec of EscalationRow listing, for each open path, the grants enabling each requirement,
+/// most severe paths first.
+pub fn find_escalation_paths(policies: &[Policy], rules: &EscalationRules) -> Vec<EscalationRow> {
+    let capabilities = extract_capabilities_from_policies(policies.to_vec(), None);
+    let capabilities: Vec<&CapabilityRow> = capabilities
+        .iter()
+        .filter(|capability| capability.status != CapabilityStatus::Denied)
+        .collect();
+
+    let mut rules: Vec<&EscalationRule> = rules.rules.iter().collect();
+    // Stable, so rules of the same severity keep the order of the rules file.
+    rules.sort_by_key(|rule| Reverse(rule.severity));
+
+    let mut rows = Vec::new();
+    for rule in rules {
+        let enabled_by: Vec<(&Requirement, Vec<&CapabilityRow>)> = rule
+            .requires
+            .iter()
+            .map(|requirement| {
+                let grants = capabilities
+                    .iter()
+                    .copied()
+                    .filter(|capability| {
+                        requirement.is_met_by(capability)
+                            && requirement.is_allowed_through(capability, policies)
+                    })
+                    .collect();
+                (requirement, grants)
+            })
+            .collect();
+        if enabled_by.iter().any(|(_, grants)| grants.is_empty()) {
+            continue;
+        }
+
+        for (requirement, grants) in enabled_by {
+            for capability in grants {
+                rows.push(EscalationRow {
+                    rule: rule.id.clone(),
+                    severity: rule.severity,
+                    description: rule.description.clone(),
+                    requirement: requirement.action.clone(),
+                    action: capability.action.clone(),
+                    resource: capability.resource.clone(),
+                    condition: capability.condition.clone(),
+                    source: capability.source.clone(),
+                });
+            }
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::iam::PolicyOrigin;
+
+    fn policies(statements: serde_json::Value) -> Vec<Policy> {
+        vec![Policy::inline("deploy", statements)]
+    }
+
+    fn rule_ids(rows: &[EscalationRow]) -> Vec<&str> {
+        let mut ids: Vec<&str> = rows.iter().map(|row| row.rule.as_str()).collect();
+        ids.dedup();
+        ids
+    }
+
+    #[test]
+    fn test_embedded_rules_load() {
+        let rules = EscalationRules::embedded();
+
+        assert!(!rules.version.is_empty());
+        assert!(rules.rules.iter().all(|rule| !rule.requires.is_empty()));
+        assert!(rules
+            .rules
+            .iter()
+            .any(|rule| rule.id == "passrole-lambda-invoke"));
+    }
+
+    #[test]
+    fn test_find_passrole_lambda_path() {
+        let rules = EscalationRules::embedded();
+        let policies = policies(json!([
+            { "Effect": "Allow", "Action": "iam:PassRole", "Resource": "arn:aws:iam::123456789012:role/worker" },
+            { "Effect": "Allow", "Action": "lambda:*", "Resource": "*" }
+        ]));
+
+        let rows = find_escalation_paths(&policies, &rules);
+
+        assert_eq!(
+            rule_ids(&rows),
+            vec![
+                "passrole-lambda-invoke",
+                "passrole-lambda-event-source",
+                "update-lambda-code"
+            ]
+        );
+        assert_eq!(rows[0].requirement, "iam:PassRole");
+        assert_eq!(rows[0].resource, "arn:aws:iam::123456789012:role/worker");
+        assert_eq!(rows[0].source, "inline:deploy");
+        assert_eq!(rows[1].requirement, "lambda:CreateFunction");
+        assert_eq!(rows[1].action, "lambda:*");
+    }
+
+    #[test]
+    fn test_resource_requirements_and_denies() {
+        let rules = EscalationRules::embedded();
+
+        let scoped = policies(json!([
+            { "Effect": "Allow", "Action": "sts:AssumeRole", "Resource": "arn:aws:iam::123456789012:role/reader" }
+        ]));
+        assert!(find_escalation_paths(&scoped, &rules).is_empty());
+
+        let denied = policies(json!([
+            { "Effect": "Allow", "Action": ["sts:AssumeRole", "iam:AttachRolePolicy"], "Resource": "*" },
+            { "Effect": "Deny", "Action": "iam:*", "Resource": "*" }
+        ]));
+        let rows = find_escalation_paths(&denied, &rules);
+        assert_eq!(rule_ids(&rows), vec!["assume-any-role"]);
+    }
+
+    #[test]
+    fn test_partial_denies_and_ceilings_remove_paths() {
+        let rules = EscalationRules::embedded();
+        let grants = json!([
+            { "Effect": "Allow", "Action": ["iam:*", "ec2:RunInstances"], "Resource": "*" }
+        ]);
+        let open = find_escalation_paths(&policies(grants.clone()), &rules);
+        assert!(rule_ids(&open).contains(&"passrole-ec2"));
+
+        // iam:* is only partly denied, but what is denied is exactly iam:PassRole.
+        let mut denied = policies(grants.clone());
+        denied[0].statements.extend(
+            Policy::from_statements(
+                PolicyOrigin::Unknown,
+                json!([{ "Effect": "Deny", "Action": "iam:PassRole", "Resource": "*" }]),
+            )
+            .statements,
+        );
+        let rows = find_escalation_paths(&denied, &rules);
+        assert!(!rows.is_empty());
+        assert!(rows.iter().all(|row| row.requirement != "iam:PassRole"));
+
+        // A permissions boundary without iam:PassRole closes the same paths.
+        let mut bounded = policies(grants);
+        bounded.push(Policy::from_statements(
+            PolicyOrigin::PermissionsBoundary {
+                arn: "arn:aws:iam::123456789012:policy/boundary".to_string(),
+                version_id: None,
+            },
+            json!([{ "Effect": "Allow", "NotAction": "iam:PassRole", "Resource": "*" }]),
+        ));
+        let rows = find_escalation_paths(&bounded, &rules);
+        assert!(!rule_ids(&rows).contains(&"passrole-ec2"));
+
+        // A conditional grant still opens a path, reported with its condition.
+        let conditional = policies(json!([
+            { "Effect": "Allow", "Action": ["iam:PassRole", "ec2:RunInstances"], "Resource": "*",
+              "Condition": { "StringEquals": { "aws:RequestedRegion": "us-east-1" } } }
+        ]));
+        let rows = find_escalation_paths(&conditional, &rules);
+        assert_eq!(rule_ids(&rows), vec!["passrole-ec2"]);
+        assert!(rows[0].condition.contains("aws:RequestedRegion"));
+    }
+
+    #[test]
+    fn test_rules_from_file_are_validated() {
+        let path = std::env::temp_dir().join(format!(
+            "iam-capable-escalation-rules-{}.json",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            r#"{ "version": "custom", "rules": [{ "id": "empty", "severity": "low", "description": "", "requires": [] }] }"#,
+        )
+        .unwrap();
+
+        let error = EscalationRules::from_file(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert!(error.to_string().contains("rule empty requires nothing"));
+    }
+}
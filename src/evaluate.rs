@@ -0,0 +1,466 @@
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::aws::iam::{Effect, Policy, PolicyStatement};
+use crate::condition::{evaluate_condition, RequestContext};
+use crate::output::format::OutputSerializable;
+use crate::wildcard::PatternSet;
+
+/// A request to evaluate against a set of policies: can the principal perform `action` on
+/// `resource`, given the condition keys in `context`?
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub action: String,
+    pub resource: String,
+    pub context: RequestContext,
+}
+
+#[cfg(test)]
+impl Request {
+    /// A request with no condition keys, for tests.
+    pub fn without_context(action: &str, resource: &str) -> Request {
+        Request {
+            action: action.to_string(),
+            resource: resource.to_string(),
+            context: RequestContext::default(),
+        }
+    }
+}
+
+/// The outcome of evaluating a request, following AWS's policy evaluation logic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    /// A `Deny` statement matches the request, which overrides any `Allow`.
+    ExplicitDeny,
+    /// No statement allows the request.
+    ImplicitDeny,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Allowed => write!(f, "allowed"),
+            Decision::ExplicitDeny => write!(f, "explicit-deny"),
+            Decision::ImplicitDeny => write!(f, "implicit-deny"),
+        }
+    }
+}
+
+/// A statement that determined a decision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchedStatement {
+    /// The policy the statement belongs to, e.g. `managed:<arn>` or `inline:<name>`.
+    pub source: String,
+    /// The position of the statement in its policy, starting at 0.
+    pub index: usize,
+    pub sid: Option<String>,
+    pub effect: Effect,
+}
+
+/// The decision for a request together with the statements that determined it. An implicit
+/// deny has no determining statements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    pub decision: Decision,
+    pub statements: Vec<MatchedStatement>,
+}
+
+/// Evaluates a request against identity policies and the ceilings among them: the role's
+/// permissions boundary and service and resource control policies.
+///
+/// An explicit deny in any policy wins; otherwise the request is allowed if any identity
+/// statement allows it and every permissions boundary or service control policy level allows it
+/// too, and implicitly denied if not. Resource control policies only deny, so their `Allow`
+/// statements are ignored.
+/// `Condition` blocks are evaluated against the request context.
+pub fn evaluate(policies: &[Policy], request: &Request) -> Evaluation {
+    let mut allows = Vec::new();
+    let mut ceiling_allows = BTreeMap::<String, Vec<MatchedStatement>>::new();
+    let mut denies = Vec::new();
+
+    for policy in policies {
+        let is_ceiling = policy.origin.is_ceiling();
+        let requires_allow = policy.origin.requires_allow();
+        let source = policy.origin.to_string();
+        if requires_allow {
+            // A ceiling that allows nothing still has to be satisfied.
+            ceiling_allows.entry(source.clone()).or_default();
+        }
+        for (index, statement) in policy.statements.iter().enumerate() {
+            if !statement_matches(statement, request) {
+                continue;
+            }
+            let matched = MatchedStatement {
+                source: source.clone(),
+                index,
+                sid: statement.sid.clone(),
+                effect: statement.effect,
+            };
+            match statement.effect {
+                Effect::Allow if requires_allow => ceiling_allows
+                    .entry(source.clone())
+                    .or_default()
+                    .push(matched),
+                Effect::Allow if is_ceiling => {}
+                Effect::Allow => allows.push(matched),
+                Effect::Deny => denies.push(matched),
+            }
+        }
+    }
+
+    let ceilings_allow = ceiling_allows.values().all(|matched| !matched.is_empty());
+
+    if !denies.is_empty() {
+        Evaluation {
+            decision: Decision::ExplicitDeny,
+            statements: denies,
+        }
+    } else if !allows.is_empty() && ceilings_allow {
+        Evaluation {
+            decision: Decision::Allowed,
+            statements: allows
+                .into_iter()
+                .chain(ceiling_allows.into_values().flatten())
+                .collect(),
+        }
+    } else {
+        Evaluation {
+            decision: Decision::ImplicitDeny,
+            statements: Vec::new(),
+        }
+    }
+}
+
+/// Returns true if every ceiling among the policies that must allow requests (the permissions
+/// boundary, each level of service control policies) has a statement allowing the request.
+/// Denies are not considered.
+pub fn ceilings_allow(policies: &[Policy], request: &Request) -> bool {
+    let mut ceiling_allows = BTreeMap::<String, bool>::new();
+
+    for policy in policies
+        .iter()
+        .filter(|policy| policy.origin.requires_allow())
+    {
+        let allows = policy.statements.iter().any(|statement| {
+            statement.effect == Effect::Allow && statement_matches(statement, request)
+        });
+        *ceiling_allows.entry(policy.origin.to_string()).or_default() |= allows;
+    }
+
+    ceiling_allows.values().all(|allows| *allows)
+}
+
+/// Returns true if the statement applies to the request. Actions match case-insensitively,
+/// resources case-sensitively.
+pub fn statement_matches(statement: &PolicyStatement, request: &Request) -> bool {
+    let actions = PatternSet::from_element(&statement.action, &statement.not_action).to_lowercase();
+    let resources = PatternSet::from_element(&statement.resource, &statement.not_resource);
+
+    actions.contains(&request.action.to_lowercase())
+        && resources.contains(&request.resource)
+        && statement
+            .condition
+            .as_ref()
+            .is_none_or(|condition| evaluate_condition(condition, &request.context))
+}
+
+/// Represents a row in the simulation output, one per determining statement.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SimulationRow {
+    pub action: String,
+    pub resource: String,
+    pub decision: Decision,
+    pub source: String,
+    /// The statement's `Sid`, or its index in the policy when it has none.
+    pub statement: String,
+}
+
+impl SimulationRow {
+    /// Flattens an evaluation into output rows; an implicit deny yields a single row without
+    /// a statement.
+    pub fn from_evaluation(request: &Request, evaluation: &Evaluation) -> Vec<SimulationRow> {
+        let row = |source: String, statement: String| SimulationRow {
+            action: request.action.clone(),
+            resource: request.resource.clone(),
+            decision: evaluation.decision,
+            source,
+            statement,
+        };
+
+        if evaluation.statements.is_empty() {
+            return vec![row(String::new(), String::new())];
+        }
+
+        evaluation
+            .statements
+            .iter()
+            .map(|matched| {
+                let statement = matched
+                    .sid
+                    .clone()
+                    .unwrap_or_else(|| format!("#{}", matched.index));
+                row(matched.source.clone(), statement)
+            })
+            .collect()
+    }
+}
+
+impl OutputSerializable for SimulationRow {
+    fn csv_header() -> Vec<&'static str> {
+        vec!["Action", "Resource", "Decision", "Source", "Statement"]
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.action.clone(),
+            self.resource.clone(),
+            self.decision.to_string(),
+            self.source.clone(),
+            self.statement.clone(),
+        ]
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        json!({
+            "action": self.action,
+            "resource": self.resource,
+            "decision": self.decision.to_string(),
+            "source": self.source,
+            "statement": self.statement
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::iam::PolicyOrigin;
+
+    #[test]
+    fn test_evaluate_allow_and_implicit_deny() {
+        let policies = vec![Policy::inline(
+            "read",
+            json!([{ "Sid": "Read", "Effect": "Allow", "Action": "s3:Get*", "Resource": "arn:aws:s3:::bucket/*" }]),
+        )];
+
+        let allowed = evaluate(
+            &policies,
+            &Request::without_context("S3:GetObject", "arn:aws:s3:::bucket/key"),
+        );
+        assert_eq!(allowed.decision, Decision::Allowed);
+        assert_eq!(allowed.statements[0].sid.as_deref(), Some("Read"));
+        assert_eq!(allowed.statements[0].source, "inline:read");
+
+        let other_bucket = evaluate(
+            &policies,
+            &Request::without_context("s3:GetObject", "arn:aws:s3:::other/key"),
+        );
+        assert_eq!(other_bucket.decision, Decision::ImplicitDeny);
+        assert!(other_bucket.statements.is_empty());
+    }
+
+    #[test]
+    fn test_evaluate_explicit_deny_wins() {
+        let policies = vec![
+            Policy::inline(
+                "admin",
+                json!([{ "Effect": "Allow", "Action": "*", "Resource": "*" }]),
+            ),
+            Policy::inline(
+                "guardrail",
+                json!([
+                    { "Effect": "Allow", "Action": "iam:GetRole", "Resource": "*" },
+                    { "Effect": "Deny", "Action": "iam:*", "Resource": "*" }
+                ]),
+            ),
+        ];
+
+        let evaluation = evaluate(
+            &policies,
+            &Request::without_context("iam:GetRole", "arn:aws:iam::1:role/x"),
+        );
+
+        assert_eq!(evaluation.decision, Decision::ExplicitDeny);
+        assert_eq!(
+            evaluation.statements,
+            vec![MatchedStatement {
+                source: "inline:guardrail".to_string(),
+                index: 1,
+                sid: None,
+                effect: Effect::Deny,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_evaluate_not_action_and_not_resource() {
+        let policies = vec![Policy::inline(
+            "not",
+            json!([
+                { "Effect": "Allow", "NotAction": "iam:*", "Resource": "*" },
+                { "Effect": "Deny", "Action": "s3:*", "NotResource": "arn:aws:s3:::public/*" }
+            ]),
+        )];
+
+        let decide = |action: &str, resource: &str| {
+            evaluate(&policies, &Request::without_context(action, resource)).decision
+        };
+
+        assert_eq!(decide("ec2:RunInstances", "*"), Decision::Allowed);
+        assert_eq!(decide("iam:PassRole", "*"), Decision::ImplicitDeny);
+        assert_eq!(
+            decide("s3:GetObject", "arn:aws:s3:::public/a"),
+            Decision::Allowed
+        );
+        assert_eq!(
+            decide("s3:GetObject", "arn:aws:s3:::private/a"),
+            Decision::ExplicitDeny
+        );
+    }
+
+    #[test]
+    fn test_evaluate_conditions_against_context() {
+        let policies = vec![Policy::inline(
+            "vpc-only",
+            json!([
+                { "Effect": "Allow", "Action": "s3:*", "Resource": "*" },
+                {
+                    "Sid": "DenyOutsideVpc",
+                    "Effect": "Deny",
+                    "Action": "s3:*",
+                    "Resource": "*",
+                    "Condition": { "StringNotEquals": { "aws:SourceVpc": "vpc-1" } }
+                }
+            ]),
+        )];
+        let mut in_vpc = Request::without_context("s3:GetObject", "arn:aws:s3:::bucket/key");
+        in_vpc.context = RequestContext::from_value(json!({ "aws:SourceVpc": "vpc-1" })).unwrap();
+        let outside_vpc = Request::without_context("s3:GetObject", "arn:aws:s3:::bucket/key");
+
+        assert_eq!(evaluate(&policies, &in_vpc).decision, Decision::Allowed);
+        let evaluation = evaluate(&policies, &outside_vpc);
+        assert_eq!(evaluation.decision, Decision::ExplicitDeny);
+        assert_eq!(
+            evaluation.statements[0].sid.as_deref(),
+            Some("DenyOutsideVpc")
+        );
+    }
+
+    #[test]
+    fn test_evaluate_permissions_boundary() {
+        let mut boundary = Policy::inline(
+            "unused",
+            json!([{ "Effect": "Allow", "Action": "s3:*", "Resource": "*" }]),
+        );
+        boundary.origin = PolicyOrigin::PermissionsBoundary {
+            arn: "arn:aws:iam::1:policy/Boundary".to_string(),
+            version_id: None,
+        };
+        let policies = vec![
+            Policy::inline(
+                "admin",
+                json!([{ "Effect": "Allow", "Action": "*", "Resource": "*" }]),
+            ),
+            boundary,
+        ];
+
+        let allowed = evaluate(&policies, &Request::without_context("s3:GetObject", "*"));
+        assert_eq!(allowed.decision, Decision::Allowed);
+        assert_eq!(allowed.statements.len(), 2);
+        assert_eq!(
+            allowed.statements[1].source,
+            "boundary:arn:aws:iam::1:policy/Boundary"
+        );
+
+        let outside = evaluate(&policies, &Request::without_context("iam:CreateUser", "*"));
+        assert_eq!(outside.decision, Decision::ImplicitDeny);
+    }
+
+    #[test]
+    fn test_evaluate_service_control_policies() {
+        let scp = |path: &str, statements: serde_json::Value| {
+            let mut scp = Policy::inline("unused", statements);
+            scp.origin = PolicyOrigin::ServiceControlPolicy {
+                path: path.to_string(),
+            };
+            scp
+        };
+        let policies = vec![
+            Policy::inline(
+                "admin",
+                json!([{ "Effect": "Allow", "Action": "*", "Resource": "*" }]),
+            ),
+            scp(
+                "root.json",
+                json!([
+                    { "Effect": "Allow", "Action": "*", "Resource": "*" },
+                    { "Effect": "Deny", "Action": "organizations:*", "Resource": "*" }
+                ]),
+            ),
+            scp(
+                "workloads.json",
+                json!([{ "Effect": "Allow", "Action": ["s3:*", "organizations:*"], "Resource": "*" }]),
+            ),
+        ];
+        let decide =
+            |action: &str| evaluate(&policies, &Request::without_context(action, "*")).decision;
+
+        assert_eq!(decide("s3:GetObject"), Decision::Allowed);
+        // Every level of the OU chain must allow the action.
+        assert_eq!(decide("ec2:RunInstances"), Decision::ImplicitDeny);
+        assert_eq!(decide("organizations:ListAccounts"), Decision::ExplicitDeny);
+    }
+
+    #[test]
+    fn test_evaluate_resource_control_policies_only_deny() {
+        let mut rcp = Policy::inline(
+            "unused",
+            json!([{ "Effect": "Deny", "Action": "s3:DeleteBucket", "Resource": "*" }]),
+        );
+        rcp.origin = PolicyOrigin::ResourceControlPolicy {
+            path: "root.json".to_string(),
+        };
+        let policies = vec![
+            Policy::inline(
+                "admin",
+                json!([{ "Effect": "Allow", "Action": "*", "Resource": "*" }]),
+            ),
+            rcp,
+        ];
+        let decide =
+            |action: &str| evaluate(&policies, &Request::without_context(action, "*")).decision;
+
+        // Without an Allow of its own, the RCP still lets everything it does not deny through.
+        assert_eq!(decide("s3:GetObject"), Decision::Allowed);
+        assert_eq!(decide("s3:DeleteBucket"), Decision::ExplicitDeny);
+        assert!(ceilings_allow(
+            &policies,
+            &Request::without_context("s3:GetObject", "*")
+        ));
+    }
+
+    #[test]
+    fn test_simulation_rows() {
+        let request = Request::without_context("s3:GetObject", "arn:aws:s3:::bucket/key");
+        let implicit = Evaluation {
+            decision: Decision::ImplicitDeny,
+            statements: Vec::new(),
+        };
+
+        let rows = SimulationRow::from_evaluation(&request, &implicit);
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0].csv_record(),
+            vec![
+                "s3:GetObject",
+                "arn:aws:s3:::bucket/key",
+                "implicit-deny",
+                "",
+                ""
+            ]
+        );
+    }
+}
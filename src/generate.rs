@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::aws::iam::{Effect, Policy, PolicyOrigin, PolicyStatement};
+use crate::cloudtrail::ApiCall;
+
+/// Builds the smallest policy allowing every call: one statement per service and set of
+/// actions, listing the resources those actions were called on.
+///
+/// With `collapse_wildcards`, resources sharing a parent path (e.g. two objects under
+/// `bucket/logs/`) collapse to `parent/*`, and actions sharing a verb (e.g. `s3:GetObject` and
+/// `s3:GetObjectTagging`) to `service:Verb*`. This keeps the policy short, at the cost of
+/// granting calls the role has not made yet.
+///
+/// # Arguments
+///
+/// * `calls` - The calls the role made, as read from CloudTrail.
+/// * `collapse_wildcards` - Whether to collapse resources and actions into wildcards.
+///
+/// # Returns
+///
+/// A policy document with a statement per service and action set, sorted by service.
+pub fn generate_policy(calls: &[ApiCall], collapse_wildcards: bool) -> Policy {
+    // Service to resource to the actions called on it.
+    let mut services: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> = BTreeMap::new();
+    for call in calls {
+        let service = call
+            .action
+            .split_once(':')
+            .map_or(call.action.as_str(), |(service, _)| service);
+        services
+            .entry(service.to_string())
+            .or_default()
+            .entry(call.resource.clone())
+            .or_default()
+            .insert(call.action.clone());
+    }
+
+    let mut statements = Vec::new();
+    for (service, resources) in services {
+        let resources = if collapse_wildcards {
+            collapse_resources(resources)
+        } else {
+            resources
+        };
+
+        // Resources called with the same actions share a statement.
+        let mut grouped: BTreeMap<BTreeSet<String>, Vec<String>> = BTreeMap::new();
+        for (resource, actions) in resources {
+            let actions = if collapse_wildcards {
+                collapse_actions(&service, actions)
+            } else {
+                actions
+            };
+            grouped.entry(actions).or_default().push(resource);
+        }
+
+        statements.extend(
+            grouped
+                .into_iter()
+                .map(|(actions, resources)| PolicyStatement {
+                    effect: Effect::Allow,
+                    action: actions.into_iter().collect(),
+                    resource: resources,
+                    ..Default::default()
+                }),
+        );
+    }
+
+    Policy {
+        version: "2012-10-17".to_string(),
+        statements,
+        origin: PolicyOrigin::Unknown,
+    }
+}
+
+/// Replaces resources sharing a parent path with `parent/*`, merging their actions.
+fn collapse_resources(
+    resources: BTreeMap<String, BTreeSet<String>>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut siblings: BTreeMap<String, usize> = BTreeMap::new();
+    for resource in resources.keys() {
+        if let Some(parent) = parent_path(resource) {
+            *siblings.entry(parent.to_string()).or_default() += 1;
+        }
+    }
+
+    let mut collapsed: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (resource, actions) in resources {
+        let resource = match parent_path(&resource) {
+            Some(parent) if siblings[parent] > 1 => format!("{}/*", parent),
+            _ => resource,
+        };
+        collapsed.entry(resource).or_default().extend(actions);
+    }
+    collapsed
+}
+
+/// Returns the part of an ARN before its last `/`, if its resource part has one.
+fn parent_path(resource: &str) -> Option<&str> {
+    let resource_part = resource.splitn(6, ':').nth(5)?;
+    let (parent, _) = resource_part.rsplit_once('/')?;
+    Some(&resource[..resource.len() - resource_part.len() + parent.len()])
+}
+
+/// Replaces actions sharing a verb, the first word of their name, with `service:Verb*`.
+fn collapse_actions(service: &str, actions: BTreeSet<String>) -> BTreeSet<String> {
+    let mut verbs: BTreeMap<String, usize> = BTreeMap::new();
+    for action in &actions {
+        *verbs.entry(verb(action).to_string()).or_default() += 1;
+    }
+
+    actions
+        .into_iter()
+        .map(|action| {
+            let verb = verb(&action);
+            if verbs[verb] > 1 {
+                format!("{}:{}*", service, verb)
+            } else {
+                action
+            }
+        })
+        .collect()
+}
+
+/// Returns the leading capitalised word of an action's name, e.g. `Get` for `s3:GetObject`.
+fn verb(action: &str) -> &str {
+    let name = action.split_once(':').map_or(action, |(_, name)| name);
+    let end = name
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| c.is_ascii_uppercase())
+        .map_or(name.len(), |(index, _)| index);
+    &name[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calls(calls: &[(&str, &str)]) -> Vec<ApiCall> {
+        calls
+            .iter()
+            .map(|(action, resource)| ApiCall {
+                action: action.to_string(),
+                resource: resource.to_string(),
+            })
+            .collect()
+    }
+
+    fn statements(policy: &Policy) -> Vec<(Vec<&str>, Vec<&str>)> {
+        policy
+            .statements
+            .iter()
+            .map(|statement| {
+                (
+                    statement.action.iter().map(String::as_str).collect(),
+                    statement.resource.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn observed() -> Vec<ApiCall> {
+        calls(&[
+            ("s3:GetObject", "arn:aws:s3:::bucket/logs/a.log"),
+            ("s3:GetObject", "arn:aws:s3:::bucket/logs/b.log"),
+            ("s3:GetObjectTagging", "arn:aws:s3:::bucket/logs/b.log"),
+            ("s3:GetObject", "arn:aws:s3:::bucket/logs/a.log"),
+            ("s3:ListBucket", "arn:aws:s3:::bucket"),
+            ("sts:GetCallerIdentity", "*"),
+        ])
+    }
+
+    #[test]
+    fn test_generate_groups_by_service_and_resource() {
+        let policy = generate_policy(&observed(), false);
+
+        assert_eq!(policy.version, "2012-10-17");
+        assert_eq!(
+            statements(&policy),
+            vec![
+                (vec!["s3:GetObject"], vec!["arn:aws:s3:::bucket/logs/a.log"]),
+                (
+                    vec!["s3:GetObject", "s3:GetObjectTagging"],
+                    vec!["arn:aws:s3:::bucket/logs/b.log"]
+                ),
+                (vec!["s3:ListBucket"], vec!["arn:aws:s3:::bucket"]),
+                (vec!["sts:GetCallerIdentity"], vec!["*"]),
+            ]
+        );
+        assert!(policy
+            .statements
+            .iter()
+            .all(|statement| statement.effect == Effect::Allow));
+    }
+
+    #[test]
+    fn test_generate_collapses_wildcards() {
+        let policy = generate_policy(&observed(), true);
+
+        assert_eq!(
+            statements(&policy),
+            vec![
+                (vec!["s3:Get*"], vec!["arn:aws:s3:::bucket/logs/*"]),
+                (vec!["s3:ListBucket"], vec!["arn:aws:s3:::bucket"]),
+                (vec!["sts:GetCallerIdentity"], vec!["*"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generated_policy_serializes_as_document() {
+        let policy = generate_policy(
+            &calls(&[("sqs:SendMessage", "arn:aws:sqs:us-east-1:123456789012:jobs")]),
+            false,
+        );
+
+        assert_eq!(
+            serde_json::to_value(&policy).unwrap(),
+            serde_json::json!({
+                "Version": "2012-10-17",
+                "Statement": [{
+                    "Effect": "Allow",
+                    "Action": ["sqs:SendMessage"],
+                    "Resource": ["arn:aws:sqs:us-east-1:123456789012:jobs"]
+                }]
+            })
+        );
+    }
+}
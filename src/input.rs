@@ -0,0 +1,274 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::aws::client::AwsClient;
+use crate::aws::iam::{IdentityPolicies, Policy};
+use crate::aws::identity::Identity;
+use crate::local::FilePolicySource;
+use crate::source::PolicySource;
+
+/// Where the policies of one role, user or group come from: the IAM API, or local policy files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyInput {
+    Live(Identity),
+    /// Local files and directories. `identity` selects an identity from authorization details
+    /// dumps.
+    Local {
+        paths: Vec<PathBuf>,
+        identity: Option<Identity>,
+    },
+}
+
+impl PolicyInput {
+    /// Builds the input from command line arguments, preferring local files when any are given.
+    pub fn from_args(
+        identity: Option<Identity>,
+        policy_files: Vec<PathBuf>,
+        policy_dirs: Vec<PathBuf>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        PolicyInput::from_optional_args(identity, policy_files, policy_dirs)
+            .ok_or_else(|| "either a role, user or group or policy files are required".into())
+    }
+
+    /// Like `from_args`, but returns `None` when no argument was given.
+    pub fn from_optional_args(
+        identity: Option<Identity>,
+        policy_files: Vec<PathBuf>,
+        policy_dirs: Vec<PathBuf>,
+    ) -> Option<Self> {
+        let paths: Vec<PathBuf> = policy_files.into_iter().chain(policy_dirs).collect();
+        match (identity, paths.is_empty()) {
+            (identity, false) => Some(PolicyInput::Local { paths, identity }),
+            (Some(identity), true) => Some(PolicyInput::Live(identity)),
+            (None, true) => None,
+        }
+    }
+
+    /// A name for the input in output, e.g. as a column header: the identity (a role's bare name,
+    /// `user:NAME` or `group:NAME`, prefixed with `ACCOUNT:` when given as an ARN), or the policy
+    /// paths for local files of an unnamed role.
+    pub fn label(&self) -> String {
+        match self {
+            PolicyInput::Live(identity)
+            | PolicyInput::Local {
+                identity: Some(identity),
+                ..
+            } => match &identity.account_id {
+                Some(account_id) => format!("{}:{}", account_id, identity),
+                None => identity.to_string(),
+            },
+            PolicyInput::Local {
+                paths,
+                identity: None,
+            } => paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+
+    /// Returns true if loading the input needs AWS credentials.
+    pub fn is_live(&self) -> bool {
+        matches!(self, PolicyInput::Live(_))
+    }
+
+    /// The identity to read from the input's source, if one was named.
+    pub fn identity(&self) -> Option<&Identity> {
+        match self {
+            PolicyInput::Live(identity) => Some(identity),
+            PolicyInput::Local { identity, .. } => identity.as_ref(),
+        }
+    }
+
+    /// Returns the source to read the input's policies from.
+    ///
+    /// # Arguments
+    ///
+    /// * `live_source` - The source to read live identities from, e.g. IAM; only needed if the
+    ///   input is live.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the PolicySource, or an Error if the input is live and there is no
+    /// live source.
+    pub fn source(
+        &self,
+        live_source: Option<&Arc<dyn PolicySource>>,
+    ) -> Result<Arc<dyn PolicySource>, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            PolicyInput::Live(_) => live_source
+                .cloned()
+                .ok_or_else(|| "an AWS client is required to fetch live identities".into()),
+            PolicyInput::Local { paths, .. } => Ok(Arc::new(FilePolicySource::new(paths.clone()))),
+        }
+    }
+
+    /// Loads the input's policies and permissions boundary from its source.
+    ///
+    /// # Arguments
+    ///
+    /// * `live_source` - The source to read live identities from; only needed if the input is
+    ///   live.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the IdentityPolicies, or an Error.
+    pub async fn load(
+        &self,
+        live_source: Option<&Arc<dyn PolicySource>>,
+    ) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+        self.source(live_source)?.load(self.identity()).await
+    }
+
+    /// Loads the trust policy of the input's role from its source, if it has one.
+    ///
+    /// # Arguments
+    ///
+    /// * `live_source` - The source to read live identities from; only needed if the input is
+    ///   live.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the trust policy, or `None` for users, groups and local files
+    /// without one, or an Error.
+    pub async fn load_trust_policy(
+        &self,
+        live_source: Option<&Arc<dyn PolicySource>>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        self.source(live_source)?
+            .trust_policy(self.identity())
+            .await
+    }
+
+    /// The account of the input's identity: from its ARN if it was given as one, otherwise the
+    /// caller's account for live identities. `None` for local files without an ARN.
+    pub fn account_id<'a>(&'a self, aws_client: Option<&'a AwsClient>) -> Option<&'a str> {
+        self.identity()
+            .and_then(|identity| identity.account_id.as_deref())
+            .or_else(|| {
+                aws_client
+                    .filter(|_| self.is_live())
+                    .map(|client| client.account_id.as_str())
+            })
+    }
+}
+
+/// Parses a `--role` value: a role name, `user:NAME`, `group:NAME`, an IAM ARN, or
+/// `file:PATH` / `dir:PATH` for local policies.
+impl FromStr for PolicyInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = s.strip_prefix("file:").or_else(|| s.strip_prefix("dir:"));
+        match path {
+            Some("") => Err(format!("Missing path in {}", s)),
+            Some(path) => Ok(PolicyInput::Local {
+                paths: vec![PathBuf::from(path)],
+                identity: None,
+            }),
+            None if s.is_empty() => Err("Role name cannot be empty".to_string()),
+            None => s.parse().map(PolicyInput::Live),
+        }
+    }
+}
+
+/// Picks the identity named by the `--role`, `--user` or `--group` arguments of a command; at
+/// most one of them is given.
+pub fn select_identity(
+    role: Option<Identity>,
+    user: Option<String>,
+    group: Option<String>,
+) -> Option<Identity> {
+    role.or_else(|| user.map(Identity::user))
+        .or_else(|| group.map(Identity::group))
+}
+
+/// Labels the inputs, numbering repeated labels (`app`, `app#2`) so that each names a distinct
+/// output column.
+pub fn unique_labels(inputs: &[PolicyInput]) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    for input in inputs {
+        let label = input.label();
+        let mut candidate = label.clone();
+        let mut n = 1;
+        while labels.contains(&candidate) {
+            n += 1;
+            candidate = format!("{}#{}", label, n);
+        }
+        labels.push(candidate);
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_args_prefers_local_files() {
+        let local = PolicyInput::from_args(
+            Some(Identity::role("app")),
+            vec![PathBuf::from("details.json")],
+            vec![PathBuf::from("policies")],
+        )
+        .unwrap();
+
+        assert_eq!(
+            local,
+            PolicyInput::Local {
+                paths: vec![PathBuf::from("details.json"), PathBuf::from("policies")],
+                identity: Some(Identity::role("app")),
+            }
+        );
+        assert!(!local.is_live());
+
+        let live =
+            PolicyInput::from_args(Some(Identity::role("app")), Vec::new(), Vec::new()).unwrap();
+        assert!(live.is_live());
+        assert!(PolicyInput::from_args(None, Vec::new(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_parse_role_values() {
+        assert_eq!(
+            "app".parse::<PolicyInput>(),
+            Ok(PolicyInput::Live(Identity::role("app")))
+        );
+        assert_eq!(
+            "arn:aws:iam::123456789012:user/alice".parse::<PolicyInput>(),
+            Ok(PolicyInput::Live(Identity {
+                account_id: Some("123456789012".to_string()),
+                ..Identity::user("alice")
+            }))
+        );
+        assert_eq!(
+            "dir:./policies".parse::<PolicyInput>(),
+            Ok(PolicyInput::Local {
+                paths: vec![PathBuf::from("./policies")],
+                identity: None,
+            })
+        );
+        assert!("file:".parse::<PolicyInput>().is_err());
+    }
+
+    #[test]
+    fn test_unique_labels() {
+        let inputs: Vec<PolicyInput> = [
+            "app",
+            "file:app.json",
+            "app",
+            "user:app",
+            "arn:aws:iam::123456789012:role/app",
+        ]
+        .iter()
+        .map(|value| value.parse().unwrap())
+        .collect();
+
+        assert_eq!(
+            unique_labels(&inputs),
+            vec!["app", "app.json", "app#2", "user:app", "123456789012:app"]
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use serde_json::json;
+
+use crate::aws::iam::{Effect, Policy, PolicyStatement};
+use crate::catalog::ActionCatalog;
+use crate::output::format::OutputSerializable;
+use crate::severity::Severity;
+use crate::wildcard::PatternSet;
+
+/// The policy language version every policy should use; `2008-10-17` lacks policy variables.
+const CURRENT_VERSION: &str = "2012-10-17";
+
+/// The checks `lint` runs. Their IDs are stable, so findings can be filtered or suppressed by
+/// ID across releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintCheck {
+    /// `Allow` of `Action: "*"` on `Resource: "*"`, i.e. full administrator access.
+    FullAccess,
+    /// `Allow` of `iam:PassRole` on every role.
+    PassRoleOnAnyRole,
+    /// A statement whose grant (or deny) another statement already covers.
+    RedundantStatement,
+    /// An action of a catalogued service that the action catalog does not know, usually a typo,
+    /// or an action not of the form `service:Action`.
+    UnknownAction,
+    /// A resource that is neither `*` nor a well-formed ARN.
+    MalformedArn,
+    /// A policy using the `2008-10-17` language version, or none at all.
+    DeprecatedVersion,
+    /// `Allow` with `NotAction`, which grants every action but the listed ones, including
+    /// those of services added later.
+    AllowWithNotAction,
+    /// An action of a service the action catalog does not list. The catalog only covers some
+    /// services, so this is informational rather than a likely typo.
+    ServiceNotInCatalog,
+}
+
+impl LintCheck {
+    pub fn id(&self) -> &'static str {
+        match self {
+            LintCheck::FullAccess => "L001",
+            LintCheck::PassRoleOnAnyRole => "L002",
+            LintCheck::RedundantStatement => "L003",
+            LintCheck::UnknownAction => "L004",
+            LintCheck::MalformedArn => "L005",
+            LintCheck::DeprecatedVersion => "L006",
+            LintCheck::AllowWithNotAction => "L007",
+            LintCheck::ServiceNotInCatalog => "L008",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintCheck::FullAccess => Severity::Critical,
+            LintCheck::PassRoleOnAnyRole => Severity::High,
+            LintCheck::RedundantStatement => Severity::Low,
+            LintCheck::UnknownAction => Severity::Medium,
+            LintCheck::MalformedArn => Severity::Medium,
+            LintCheck::DeprecatedVersion => Severity::Low,
+            LintCheck::AllowWithNotAction => Severity::Medium,
+            LintCheck::ServiceNotInCatalog => Severity::Low,
+        }
+    }
+}
+
+/// Represents a row in the lint output: one problem in one policy statement, or in the policy
+/// as a whole.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LintFinding {
+    pub check: LintCheck,
+    /// The policy, named like capability sources, e.g. `managed:<arn>` or `inline:<name>`.
+    pub policy: String,
+    /// The position of the statement in its policy, starting at 0; `None` for findings about
+    /// the whole policy.
+    pub statement: Option<usize>,
+    pub sid: Option<String>,
+    pub message: String,
+}
+
+impl OutputSerializable for LintFinding {
+    fn csv_header() -> Vec<&'static str> {
+        vec!["Id", "Severity", "Policy", "Statement", "Sid", "Message"]
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.check.id().to_string(),
+            self.check.severity().to_string(),
+            self.policy.clone(),
+            self.statement
+                .map(|index| index.to_string())
+                .unwrap_or_default(),
+            self.sid.clone().unwrap_or_default(),
+            self.message.clone(),
+        ]
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        json!({
+            "id": self.check.id(),
+            "severity": self.check.severity().to_string(),
+            "policy": self.policy,
+            "statement": self.statement,
+            "sid": self.sid,
+            "message": self.message
+        })
+    }
+}
+
+/// Runs every check over the policies.
+///
+/// Redundancy is checked across all of the policies that grant permissions, and within each
+/// ceiling (permissions boundary, service or resource control policy) on its own, since a
+/// ceiling's statements only combine with each other.
+///
+/// # Arguments
+///
+/// * `policies` - The policies to check.
+/// * `catalog` - The action catalog to look actions and services up in.
+///
+/// # Returns
+///
+/// A Vec of LintFinding, in policy and statement order.
+pub fn lint_policies(policies: &[Policy], catalog: &ActionCatalog) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+
+    for (policy_index, policy) in policies.iter().enumerate() {
+        let source = policy.origin.to_string();
+        let finding = |check: LintCheck, statement: Option<usize>, message: String| {
+            let sid = statement.and_then(|index| policy.statements[index].sid.clone());
+            LintFinding {
+                check,
+                policy: source.clone(),
+                statement,
+                sid,
+                message,
+            }
+        };
+
+        if policy.version != CURRENT_VERSION {
+            let version = match policy.version.as_str() {
+                "" => "no version".to_string(),
+                version => format!("version {}", version),
+            };
+            findings.push(finding(
+                LintCheck::DeprecatedVersion,
+                None,
+                format!(
+                    "Policy uses {}; use {} to support policy variables",
+                    version, CURRENT_VERSION
+                ),
+            ));
+        }
+
+        for (index, statement) in policy.statements.iter().enumerate() {
+            for (check, message) in check_statement(statement, catalog) {
+                findings.push(finding(check, Some(index), message));
+            }
+            if let Some(covering) = find_covering_statement(policies, policy_index, index) {
+                findings.push(finding(
+                    LintCheck::RedundantStatement,
+                    Some(index),
+                    format!("Statement is already covered by {}", covering),
+                ));
+            }
+        }
+    }
+
+    findings
+}
+
+/// Runs the checks that look at a single statement.
+fn check_statement(
+    statement: &PolicyStatement,
+    catalog: &ActionCatalog,
+) -> Vec<(LintCheck, String)> {
+    let mut findings = Vec::new();
+    let is_allow = statement.effect == Effect::Allow;
+    let star = PatternSet::Include(vec!["*".to_string()]);
+    let resources = PatternSet::from_element(&statement.resource, &statement.not_resource);
+
+    let full_access = is_allow
+        && statement.action.iter().any(|action| action == "*")
+        && statement.resource.iter().any(|resource| resource == "*");
+    if full_access {
+        findings.push((
+            LintCheck::FullAccess,
+            "Allows every action on every resource".to_string(),
+        ));
+    }
+
+    let passes_any_role = is_allow
+        && PatternSet::from_element(&statement.action, &statement.not_action)
+            .to_lowercase()
+            .contains("iam:passrole")
+        && resources.covers(&star);
+    if passes_any_role && !full_access {
+        findings.push((
+            LintCheck::PassRoleOnAnyRole,
+            "Allows iam:PassRole on every role; scope it to the roles that are meant to be passed"
+                .to_string(),
+        ));
+    }
+
+    for action in statement.action.iter().chain(&statement.not_action) {
+        findings.extend(unknown_action(action, catalog));
+    }
+
+    for resource in statement.resource.iter().chain(&statement.not_resource) {
+        if !is_well_formed_resource(resource) {
+            findings.push((
+                LintCheck::MalformedArn,
+                format!("Resource {} is not a valid ARN", resource),
+            ));
+        }
+    }
+
+    if is_allow && !statement.not_action.is_empty() {
+        findings.push((
+            LintCheck::AllowWithNotAction,
+            "Allows every action except those listed in NotAction, including actions of services added later"
+                .to_string(),
+        ));
+    }
+
+    findings
+}
+
+/// Describes what is wrong with an action, if the catalog does not know it: an unknown action
+/// of a catalogued service, or a service the catalog does not list at all.
+fn unknown_action(action: &str, catalog: &ActionCatalog) -> Option<(LintCheck, String)> {
+    if action == "*" {
+        return None;
+    }
+    let Some((service, _)) = action.split_once(':') else {
+        return Some((
+            LintCheck::UnknownAction,
+            format!("Action {} is not of the form service:Action", action),
+        ));
+    };
+    if !catalog.services.contains_key(&service.to_lowercase()) {
+        return Some((
+            LintCheck::ServiceNotInCatalog,
+            format!(
+                "Service {} of action {} is not in action catalog {}, so its actions are not checked",
+                service, action, catalog.version
+            ),
+        ));
+    }
+    if catalog
+        .expand(&PatternSet::Include(vec![action.to_string()]))
+        .is_empty()
+    {
+        return Some((
+            LintCheck::UnknownAction,
+            format!(
+                "Action {} matches no action in action catalog {}",
+                action, catalog.version
+            ),
+        ));
+    }
+    None
+}
+
+/// Returns true if a resource is `*` or an ARN with a partition, service and resource part
+/// (`arn:partition:service:region:account:resource`). Wildcards are allowed anywhere.
+fn is_well_formed_resource(resource: &str) -> bool {
+    if resource == "*" {
+        return true;
+    }
+    let parts: Vec<&str> = resource.splitn(6, ':').collect();
+    parts.len() == 6
+        && parts[0] == "arn"
+        && !parts[1].is_empty()
+        && !parts[2].is_empty()
+        && !parts[5].is_empty()
+}
+
+/// Finds a statement covering the statement at `index` of `policies[policy_index]`: one with the
+/// same effect whose actions and resources include all of its own, with no condition or the
+/// same one. Of two identical statements, only the later one is reported as redundant.
+fn find_covering_statement(
+    policies: &[Policy],
+    policy_index: usize,
+    index: usize,
+) -> Option<String> {
+    let policy = &policies[policy_index];
+    let statement = &policy.statements[index];
+    // Statements with principals belong to resource policies, which this does not compare.
+    if statement.principal.is_some() || statement.not_principal.is_some() {
+        return None;
+    }
+
+    let candidates = policies
+        .iter()
+        .enumerate()
+        .filter(|(other_index, other)| {
+            if policy.origin.is_ceiling() {
+                *other_index == policy_index
+            } else {
+                !other.origin.is_ceiling()
+            }
+        })
+        .flat_map(|(other_index, other)| {
+            other
+                .statements
+                .iter()
+                .enumerate()
+                .map(move |(other_statement, candidate)| {
+                    ((other_index, other_statement), other, candidate)
+                })
+        });
+
+    for (position, other, candidate) in candidates {
+        if position == (policy_index, index) || !covers(candidate, statement) {
+            continue;
+        }
+        if covers(statement, candidate) && position > (policy_index, index) {
+            // Identical statements: the later one is the redundant one.
+            continue;
+        }
+        let name = candidate
+            .sid
+            .clone()
+            .unwrap_or_else(|| format!("#{}", position.1));
+        return Some(if position.0 == policy_index {
+            format!("statement {}", name)
+        } else {
+            format!("statement {} of {}", name, other.origin)
+        });
+    }
+    None
+}
+
+/// Returns true if `broad` applies to everything `narrow` does, with the same effect.
+fn covers(broad: &PolicyStatement, narrow: &PolicyStatement) -> bool {
+    broad.effect == narrow.effect
+        && (broad.condition.is_none() || broad.condition == narrow.condition)
+        && PatternSet::from_element(&broad.action, &broad.not_action)
+            .to_lowercase()
+            .covers(&PatternSet::from_element(&narrow.action, &narrow.not_action).to_lowercase())
+        && PatternSet::from_element(&broad.resource, &broad.not_resource).covers(
+            &PatternSet::from_element(&narrow.resource, &narrow.not_resource),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(findings: &[LintFinding]) -> Vec<(&str, Option<usize>)> {
+        findings
+            .iter()
+            .map(|finding| (finding.check.id(), finding.statement))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_statement_checks() {
+        let policies = vec![Policy::inline(
+            "app",
+            json!([
+                { "Effect": "Allow", "Action": "*", "Resource": "*" },
+                { "Sid": "Pass", "Effect": "Allow", "Action": "iam:Pass*", "Resource": "*", "Condition": { "Bool": { "aws:SecureTransport": "true" } } },
+                { "Effect": "Allow", "Action": ["s3:GetObjekt", "rds:DescribeDBInstances", "s3"], "Resource": "arn:aws:s3:::bucket/*", "Condition": { "Bool": { "aws:SecureTransport": "true" } } },
+                { "Effect": "Allow", "NotAction": "iam:*", "Resource": "bucket", "Condition": { "Bool": { "aws:SecureTransport": "true" } } }
+            ]),
+        )];
+
+        let findings = lint_policies(&policies, &ActionCatalog::embedded());
+
+        assert_eq!(
+            ids(&findings),
+            vec![
+                ("L001", Some(0)),
+                ("L002", Some(1)),
+                ("L003", Some(1)),
+                ("L004", Some(2)),
+                ("L008", Some(2)),
+                ("L004", Some(2)),
+                ("L003", Some(2)),
+                ("L005", Some(3)),
+                ("L007", Some(3)),
+                ("L003", Some(3)),
+            ]
+        );
+        assert_eq!(findings[1].sid.as_deref(), Some("Pass"));
+        assert_eq!(
+            findings[2].message,
+            "Statement is already covered by statement #0"
+        );
+        assert!(findings[3]
+            .message
+            .contains("s3:GetObjekt matches no action"));
+        assert!(findings[4].message.contains("Service rds"));
+        assert_eq!(findings[4].check.severity(), Severity::Low);
+        assert!(findings[5].message.contains("not of the form"));
+    }
+
+    #[test]
+    fn test_lint_redundancy_and_version() {
+        let read =
+            json!({ "Effect": "Allow", "Action": "s3:Get*", "Resource": "arn:aws:s3:::bucket/*" });
+        let mut old = Policy::inline("old", json!([read.clone()]));
+        old.version = "2008-10-17".to_string();
+        let policies = vec![
+            old,
+            Policy::inline(
+                "copy",
+                json!([
+                    read,
+                    { "Effect": "Allow", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/logs/*" },
+                    { "Effect": "Deny", "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/logs/*" }
+                ]),
+            ),
+        ];
+
+        let findings = lint_policies(&policies, &ActionCatalog::embedded());
+
+        assert_eq!(
+            ids(&findings),
+            vec![("L006", None), ("L003", Some(0)), ("L003", Some(1))]
+        );
+        assert_eq!(findings[0].policy, "inline:old");
+        assert_eq!(
+            findings[1].message,
+            "Statement is already covered by statement #0 of inline:old"
+        );
+        assert_eq!(
+            findings[2].message,
+            "Statement is already covered by statement #0 of inline:old"
+        );
+        assert_eq!(
+            findings[1].csv_record(),
+            vec![
+                "L003",
+                "low",
+                "inline:copy",
+                "0",
+                "",
+                "Statement is already covered by statement #0 of inline:old"
+            ]
+        );
+    }
+}
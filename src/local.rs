@@ -0,0 +1,806 @@
+use async_trait::async_trait;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::aws::iam::{IdentityPolicies, Policy, PolicyOrigin};
+use crate::aws::identity::{Identity, IdentityKind};
+use crate::aws::policy::decode_policy_document;
+use crate::resource::{ResourcePolicy, ResourcePolicyArg};
+use crate::snapshot::Snapshot;
+use crate::source::PolicySource;
+
+/// Loads policies from local JSON files and directories instead of AWS.
+///
+/// Each file may hold a single policy document, an array of policy documents, a snapshot written
+/// by `snapshot`, or the output of `aws iam get-account-authorization-details`. Directories are read non-recursively, taking
+/// every `.json` file in name order.
+///
+/// # Arguments
+///
+/// * `paths` - The files and directories to read.
+/// * `identity` - The role, user or group to select from authorization details dumps, or the
+///   role to check snapshots against. May be omitted when a dump contains a single role.
+///
+/// # Returns
+///
+/// A Result containing the IdentityPolicies read, or an Error. Only dumps and snapshots carry a
+/// permissions boundary, and at most one may be read.
+pub fn load_local_policies(
+    paths: &[PathBuf],
+    identity: Option<&Identity>,
+) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+    let mut loaded_policies = IdentityPolicies::default();
+
+    for path in expand_paths(paths)? {
+        let value = read_json_file(&path)?;
+        let loaded = parse_policy_file(value, &path.display().to_string(), identity)
+            .map_err(|e| format!("Failed to load policies from {}: {}", path.display(), e))?;
+        loaded_policies.policies.extend(loaded.policies);
+        if let Some(boundary) = loaded.permissions_boundary {
+            if loaded_policies.permissions_boundary.is_some() {
+                return Err(format!(
+                    "{} holds a second permissions boundary for the identity",
+                    path.display()
+                )
+                .into());
+            }
+            loaded_policies.permissions_boundary = Some(boundary);
+        }
+    }
+
+    Ok(loaded_policies)
+}
+
+/// Reads the trust policy of a role from the local files: the role's `AssumeRolePolicyDocument`
+/// in an authorization details dump, the trust policy of a snapshot, or a plain trust policy
+/// document, whose statements all name a `Principal`.
+///
+/// # Arguments
+///
+/// * `paths` - The files and directories to read.
+/// * `identity` - The role to select from the dumps; may be omitted when they contain a single
+///   role.
+///
+/// # Returns
+///
+/// A Result containing the first trust policy found, if any, or an Error.
+pub fn load_local_trust_policy(
+    paths: &[PathBuf],
+    identity: Option<&Identity>,
+) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    if identity.is_some_and(|identity| identity.kind != IdentityKind::Role) {
+        return Ok(None);
+    }
+    let name = identity.map(|identity| identity.name.as_str());
+
+    for path in expand_paths(paths)? {
+        let trust_policy = parse_trust_policy(&read_json_file(&path)?, name)
+            .map_err(|e| format!("Failed to load policies from {}: {}", path.display(), e))?;
+        if trust_policy.is_some() {
+            return Ok(trust_policy);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Extracts the trust policy of the named (or only) role from an authorization details dump or
+/// a snapshot, or returns the document itself if it is a trust policy. Other layouts hold no
+/// trust policy.
+fn parse_trust_policy(
+    value: &Value,
+    role_name: Option<&str>,
+) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    if Snapshot::is_snapshot(value) {
+        let snapshot = Snapshot::from_value(value.clone())?;
+        return Ok(snapshot
+            .trust_policy
+            .filter(|_| role_name.is_none_or(|role_name| snapshot.role == role_name)));
+    }
+    if value.get("RoleDetailList").is_none() {
+        return Ok(plain_trust_policy(value));
+    }
+    let role = find_detail_entry(value, IdentityKind::Role, role_name)?;
+    match &role["AssumeRolePolicyDocument"] {
+        Value::Null => Ok(None),
+        document => parse_document(document).map(Some),
+    }
+}
+
+/// Returns `value` as a trust policy if it is a policy document whose statements all name a
+/// `Principal` or `NotPrincipal`, which identity policies never do.
+fn plain_trust_policy(value: &Value) -> Option<Policy> {
+    let policy: Policy = serde_json::from_value(value.clone()).ok()?;
+    let is_trust_policy = !policy.statements.is_empty()
+        && policy
+            .statements
+            .iter()
+            .all(|stmt| stmt.principal.is_some() || stmt.not_principal.is_some());
+    is_trust_policy.then_some(policy)
+}
+
+/// Reads policies from local files through `load_local_policies`.
+pub struct FilePolicySource {
+    paths: Vec<PathBuf>,
+}
+
+impl FilePolicySource {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        FilePolicySource { paths }
+    }
+}
+
+#[async_trait]
+impl PolicySource for FilePolicySource {
+    async fn identity_policies(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(load_local_policies(&self.paths, identity)?.policies)
+    }
+
+    async fn permissions_boundary(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(load_local_policies(&self.paths, identity)?.permissions_boundary)
+    }
+
+    async fn trust_policy(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        load_local_trust_policy(&self.paths, identity)
+    }
+
+    /// Reads the files once for both the policies and the boundary.
+    async fn load(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+        load_local_policies(&self.paths, identity)
+    }
+}
+
+/// Loads service control policies, one file per level of the organizational unit chain.
+///
+/// Each file may hold a single policy document or an array of the policy documents attached at
+/// that level; a request has to be allowed by at least one policy of every level.
+///
+/// # Arguments
+///
+/// * `paths` - The SCP files, e.g. from the organization root down to the account's OU.
+///
+/// # Returns
+///
+/// A Result containing a Vec of Policy, or an Error.
+pub fn load_service_control_policies(
+    paths: &[PathBuf],
+) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    load_organization_policies(paths, |path| PolicyOrigin::ServiceControlPolicy { path })
+}
+
+/// Loads resource control policies, one file per level of the organizational unit chain, like
+/// `load_service_control_policies`.
+pub fn load_resource_control_policies(
+    paths: &[PathBuf],
+) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    load_organization_policies(paths, |path| PolicyOrigin::ResourceControlPolicy { path })
+}
+
+fn load_organization_policies(
+    paths: &[PathBuf],
+    origin: fn(String) -> PolicyOrigin,
+) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut policies = Vec::new();
+
+    for path in paths {
+        let loaded = load_local_policies(std::slice::from_ref(path), None)?;
+        policies.extend(loaded.policies.into_iter().map(|mut policy| {
+            policy.origin = origin(path.display().to_string());
+            policy
+        }));
+    }
+
+    Ok(policies)
+}
+
+/// Loads resource-based policies from local JSON files, such as those written by
+/// `aws s3api get-bucket-policy`, `aws kms get-key-policy`, `aws sqs get-queue-attributes`,
+/// `aws sns get-topic-attributes` or `aws lambda get-policy`.
+///
+/// Each file may hold a bare policy document, the AWS CLI output (whose `Policy` is a JSON
+/// string), or one or an array of `{"ResourceArn": ..., "Policy": ...}` objects. Files that do
+/// not name their resource (all but SNS topics and the last form) need the ARN in the argument.
+///
+/// # Arguments
+///
+/// * `args` - The `--resource-policy` arguments.
+///
+/// # Returns
+///
+/// A Result containing a Vec of ResourcePolicy, or an Error.
+pub fn load_resource_policies(
+    args: &[ResourcePolicyArg],
+) -> Result<Vec<ResourcePolicy>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut policies = Vec::new();
+
+    for arg in args {
+        let value = read_json_file(&arg.path)?;
+        let entries = match &value {
+            Value::Array(entries) => entries.iter().collect(),
+            entry => vec![entry],
+        };
+        for entry in entries {
+            let mut policy = parse_resource_policy(entry, arg.arn.as_deref())
+                .map_err(|e| format!("Invalid resource policy in {}: {}", arg.path.display(), e))?;
+            policy.owner_account_id = arg.account_id.clone();
+            policies.push(policy);
+        }
+    }
+
+    Ok(policies)
+}
+
+fn parse_resource_policy(
+    entry: &Value,
+    arn: Option<&str>,
+) -> Result<ResourcePolicy, Box<dyn std::error::Error + Send + Sync>> {
+    // SQS and SNS return their policy among the resource's attributes.
+    let entry = entry.get("Attributes").unwrap_or(entry);
+    let arn = arn
+        .or_else(|| entry["ResourceArn"].as_str())
+        .or_else(|| entry["TopicArn"].as_str())
+        .or_else(|| entry["QueueArn"].as_str())
+        .ok_or("the file does not name its resource; pass it as ARN=PATH")?
+        .to_string();
+
+    let mut policy = match entry.get("Policy") {
+        // The AWS CLI prints resource policies as JSON strings, which are not URL-encoded.
+        Some(Value::String(document)) => serde_json::from_str(document)?,
+        Some(document) => parse_document(document)?,
+        None => parse_document(entry)?,
+    };
+    policy.origin = PolicyOrigin::Resource { arn: arn.clone() };
+
+    Ok(ResourcePolicy {
+        arn,
+        owner_account_id: None,
+        policy,
+    })
+}
+
+fn read_json_file(path: &Path) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read policy file {}: {}", path.display(), e))?;
+    let value = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid JSON in policy file {}: {}", path.display(), e))?;
+    Ok(value)
+}
+
+/// Replaces directories with the `.json` files they contain.
+fn expand_paths(
+    paths: &[PathBuf],
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)
+                .map_err(|e| format!("Failed to read policy directory {}: {}", path.display(), e))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|entry| entry.is_file() && has_json_extension(entry))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    Ok(files)
+}
+
+fn has_json_extension(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+/// Works out which of the supported layouts `value` uses and extracts its policies.
+fn parse_policy_file(
+    value: Value,
+    path: &str,
+    identity: Option<&Identity>,
+) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+    let policies = match value {
+        ref value if Snapshot::is_snapshot(value) => {
+            return parse_snapshot(value.clone(), identity)
+        }
+        Value::Object(ref object)
+            if ["RoleDetailList", "UserDetailList", "GroupDetailList"]
+                .iter()
+                .any(|list| object.contains_key(*list)) =>
+        {
+            return parse_authorization_details(&value, identity);
+        }
+        Value::Object(_) => {
+            let mut policy: Policy = serde_json::from_value(value)?;
+            policy.origin = PolicyOrigin::File {
+                path: path.to_string(),
+            };
+            vec![policy]
+        }
+        Value::Array(documents) => documents
+            .into_iter()
+            .enumerate()
+            .map(|(index, document)| {
+                let mut policy: Policy = serde_json::from_value(document)?;
+                policy.origin = PolicyOrigin::File {
+                    path: format!("{}#{}", path, index),
+                };
+                Ok(policy)
+            })
+            .collect::<Result<_, serde_json::Error>>()?,
+        _ => return Err("expected a policy document, an array of policy documents or an authorization details dump".into()),
+    };
+
+    Ok(IdentityPolicies {
+        policies,
+        permissions_boundary: None,
+    })
+}
+
+/// Restores the policies of a snapshot, checking that it is of the expected role and, when the
+/// role was given as an ARN, account.
+fn parse_snapshot(
+    value: Value,
+    identity: Option<&Identity>,
+) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+    let snapshot = Snapshot::from_value(value)?;
+    if let Some(identity) = identity {
+        if identity.kind != IdentityKind::Role || snapshot.role != identity.name {
+            return Err(format!(
+                "the snapshot is of role {}, not {}",
+                snapshot.role, identity
+            )
+            .into());
+        }
+        if let Some(account_id) = &identity.account_id {
+            if &snapshot.account_id != account_id {
+                return Err(format!(
+                    "the snapshot is of account {}, not {}",
+                    snapshot.account_id, account_id
+                )
+                .into());
+            }
+        }
+    }
+    Ok(snapshot.into_identity_policies())
+}
+
+/// The keys of the output of `aws iam get-account-authorization-details` that hold the entries
+/// of one kind of identity: the list of entries, the name of an entry, and its inline policies.
+fn detail_keys(kind: IdentityKind) -> (&'static str, &'static str, &'static str) {
+    match kind {
+        IdentityKind::Role => ("RoleDetailList", "RoleName", "RolePolicyList"),
+        IdentityKind::User => ("UserDetailList", "UserName", "UserPolicyList"),
+        IdentityKind::Group => ("GroupDetailList", "GroupName", "GroupPolicyList"),
+    }
+}
+
+/// Extracts an identity's inline and attached managed policies and its permissions boundary
+/// from the output of `aws iam get-account-authorization-details`. A user's policies include
+/// those of the groups listed in its `GroupList`.
+fn parse_authorization_details(
+    details: &Value,
+    identity: Option<&Identity>,
+) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+    let kind = identity.map_or(IdentityKind::Role, |identity| identity.kind);
+    let name = identity.map(|identity| identity.name.as_str());
+    let entry = find_detail_entry(details, kind, name)?;
+
+    let mut policies = detail_entry_policies(details, kind, entry)?;
+
+    if kind == IdentityKind::User {
+        for group_name in entry["GroupList"].as_array().into_iter().flatten() {
+            let group_name = group_name.as_str().ok_or("group name is not a string")?;
+            let group = find_detail_entry(details, IdentityKind::Group, Some(group_name))?;
+            for mut policy in detail_entry_policies(details, IdentityKind::Group, group)? {
+                policy.origin = policy.origin.inherited_from(group_name);
+                policies.push(policy);
+            }
+        }
+    }
+
+    let permissions_boundary = match entry["PermissionsBoundary"]["PermissionsBoundaryArn"].as_str()
+    {
+        Some(arn) => {
+            let mut policy = managed_policy(details, arn)?;
+            policy.origin = policy.origin.into_permissions_boundary();
+            Some(policy)
+        }
+        None => None,
+    };
+
+    Ok(IdentityPolicies {
+        policies,
+        permissions_boundary,
+    })
+}
+
+/// Finds the entry of the named identity of the given kind in the dump. Without a name, the dump
+/// must hold exactly one identity of that kind.
+fn find_detail_entry<'a>(
+    details: &'a Value,
+    kind: IdentityKind,
+    name: Option<&str>,
+) -> Result<&'a Value, Box<dyn std::error::Error + Send + Sync>> {
+    let (list_key, name_key, _) = detail_keys(kind);
+    let entries = match &details[list_key] {
+        Value::Null => &[][..],
+        list => list
+            .as_array()
+            .ok_or_else(|| format!("{} is not an array", list_key))?,
+    };
+
+    match name {
+        Some(name) => entries
+            .iter()
+            .find(|entry| entry[name_key] == name)
+            .ok_or_else(|| format!("{} {} is not in the authorization details", kind, name).into()),
+        None if entries.len() == 1 => Ok(&entries[0]),
+        None if entries.is_empty() => {
+            Err(format!("the authorization details contain no {kind}s").into())
+        }
+        None => Err(format!(
+            "the authorization details contain several {kind}s; select one with --{kind}"
+        )
+        .into()),
+    }
+}
+
+/// Reads the attached managed policies and the inline policies of a dump entry.
+fn detail_entry_policies(
+    details: &Value,
+    kind: IdentityKind,
+    entry: &Value,
+) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+    let (_, _, inline_key) = detail_keys(kind);
+    let mut policies = Vec::new();
+
+    for attached in entry["AttachedManagedPolicies"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let arn = attached["PolicyArn"]
+            .as_str()
+            .ok_or("attached policy without a PolicyArn")?;
+        policies.push(managed_policy(details, arn)?);
+    }
+
+    for inline in entry[inline_key].as_array().into_iter().flatten() {
+        let name = inline["PolicyName"]
+            .as_str()
+            .ok_or("inline policy without a PolicyName")?;
+        let mut policy = parse_document(&inline["PolicyDocument"])?;
+        policy.origin = PolicyOrigin::Inline {
+            name: name.to_string(),
+        };
+        policies.push(policy);
+    }
+
+    Ok(policies)
+}
+
+/// Looks up a managed policy an identity refers to in the dump's `Policies` list.
+fn managed_policy(
+    details: &Value,
+    arn: &str,
+) -> Result<Policy, Box<dyn std::error::Error + Send + Sync>> {
+    let (version_id, document) = default_version(details, arn).ok_or_else(|| {
+        format!(
+            "managed policy {} is used by the identity but not included in the dump",
+            arn
+        )
+    })?;
+    let mut policy = parse_document(document)?;
+    policy.origin = PolicyOrigin::Managed {
+        arn: arn.to_string(),
+        version_id: version_id.map(String::from),
+    };
+    Ok(policy)
+}
+
+/// Finds the default version of a managed policy in the dump's `Policies` list, returning its
+/// version ID and document.
+fn default_version<'a>(details: &'a Value, arn: &str) -> Option<(Option<&'a str>, &'a Value)> {
+    details["Policies"]
+        .as_array()?
+        .iter()
+        .find(|policy| policy["Arn"] == arn)?["PolicyVersionList"]
+        .as_array()?
+        .iter()
+        .find(|version| version["IsDefaultVersion"] == true)
+        .map(|version| (version["VersionId"].as_str(), &version["Document"]))
+}
+
+/// Parses a policy document that is either embedded as JSON (as the AWS CLI prints it) or
+/// URL-encoded (as the IAM API returns it).
+fn parse_document(document: &Value) -> Result<Policy, Box<dyn std::error::Error + Send + Sync>> {
+    match document {
+        Value::String(encoded) => decode_policy_document(encoded),
+        document => Ok(serde_json::from_value(document.clone())?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn authorization_details() -> Value {
+        json!({
+            "RoleDetailList": [
+                {
+                    "RoleName": "app",
+                    "Arn": "arn:aws:iam::123456789012:role/app",
+                    "AssumeRolePolicyDocument": "%7B%22Version%22%3A%222012-10-17%22%2C%22Statement%22%3A%5B%7B%22Effect%22%3A%22Allow%22%2C%22Principal%22%3A%7B%22Service%22%3A%22lambda.amazonaws.com%22%7D%2C%22Action%22%3A%22sts%3AAssumeRole%22%7D%5D%7D",
+                    "RolePolicyList": [{
+                        "PolicyName": "queue",
+                        "PolicyDocument": {
+                            "Version": "2012-10-17",
+                            "Statement": [{ "Effect": "Allow", "Action": "sqs:SendMessage", "Resource": "*" }]
+                        }
+                    }],
+                    "AttachedManagedPolicies": [{
+                        "PolicyName": "ReadOnly",
+                        "PolicyArn": "arn:aws:iam::123456789012:policy/ReadOnly"
+                    }],
+                    "PermissionsBoundary": {
+                        "PermissionsBoundaryType": "Policy",
+                        "PermissionsBoundaryArn": "arn:aws:iam::123456789012:policy/Boundary"
+                    }
+                },
+                { "RoleName": "other", "RolePolicyList": [], "AttachedManagedPolicies": [] }
+            ],
+            "UserDetailList": [{
+                "UserName": "alice",
+                "UserPolicyList": [{
+                    "PolicyName": "logs",
+                    "PolicyDocument": {
+                        "Version": "2012-10-17",
+                        "Statement": [{ "Effect": "Allow", "Action": "logs:GetLogEvents", "Resource": "*" }]
+                    }
+                }],
+                "GroupList": ["developers"],
+                "AttachedManagedPolicies": []
+            }],
+            "GroupDetailList": [{
+                "GroupName": "developers",
+                "GroupPolicyList": [],
+                "AttachedManagedPolicies": [{
+                    "PolicyName": "ReadOnly",
+                    "PolicyArn": "arn:aws:iam::123456789012:policy/ReadOnly"
+                }]
+            }],
+            "Policies": [{
+                "Arn": "arn:aws:iam::123456789012:policy/ReadOnly",
+                "DefaultVersionId": "v2",
+                "PolicyVersionList": [
+                    { "VersionId": "v1", "IsDefaultVersion": false, "Document": { "Version": "2012-10-17", "Statement": [] } },
+                    { "VersionId": "v2", "IsDefaultVersion": true, "Document": "%7B%22Version%22%3A%222012-10-17%22%2C%22Statement%22%3A%5B%7B%22Effect%22%3A%22Allow%22%2C%22Action%22%3A%22s3%3AGet%2A%22%2C%22Resource%22%3A%22%2A%22%7D%5D%7D" }
+                ]
+            }, {
+                "Arn": "arn:aws:iam::123456789012:policy/Boundary",
+                "DefaultVersionId": "v1",
+                "PolicyVersionList": [
+                    { "VersionId": "v1", "IsDefaultVersion": true, "Document": { "Version": "2012-10-17", "Statement": [{ "Effect": "Allow", "Action": "s3:*", "Resource": "*" }] } }
+                ]
+            }]
+        })
+    }
+
+    #[test]
+    fn test_parse_single_document_and_array() {
+        let document = json!({
+            "Version": "2012-10-17",
+            "Statement": { "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*" }
+        });
+
+        let single = parse_policy_file(document.clone(), "policy.json", None)
+            .unwrap()
+            .policies;
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].origin.to_string(), "file:policy.json");
+        assert_eq!(single[0].statements.len(), 1);
+
+        let array = parse_policy_file(json!([document.clone(), document]), "all.json", None)
+            .unwrap()
+            .policies;
+        assert_eq!(array.len(), 2);
+        assert_eq!(array[1].origin.to_string(), "file:all.json#1");
+    }
+
+    #[test]
+    fn test_parse_authorization_details() {
+        let role = parse_policy_file(
+            authorization_details(),
+            "details.json",
+            Some(&Identity::role("app")),
+        )
+        .unwrap();
+        let policies = &role.policies;
+
+        assert_eq!(policies.len(), 2);
+        assert_eq!(
+            policies[0].origin,
+            PolicyOrigin::Managed {
+                arn: "arn:aws:iam::123456789012:policy/ReadOnly".to_string(),
+                version_id: Some("v2".to_string()),
+            }
+        );
+        assert_eq!(policies[0].statements[0].action, vec!["s3:Get*"]);
+        assert_eq!(
+            policies[1].origin,
+            PolicyOrigin::Inline {
+                name: "queue".to_string()
+            }
+        );
+        assert_eq!(policies[1].statements[0].action, vec!["sqs:SendMessage"]);
+        assert_eq!(
+            role.permissions_boundary.unwrap().origin.to_string(),
+            "boundary:arn:aws:iam::123456789012:policy/Boundary"
+        );
+    }
+
+    #[test]
+    fn test_parse_authorization_details_for_users_and_groups() {
+        let user = parse_policy_file(
+            authorization_details(),
+            "details.json",
+            Some(&Identity::user("alice")),
+        )
+        .unwrap();
+        let origins: Vec<String> = user
+            .policies
+            .iter()
+            .map(|policy| policy.origin.to_string())
+            .collect();
+
+        assert_eq!(
+            origins,
+            vec![
+                "inline:logs",
+                "group:developers:managed:arn:aws:iam::123456789012:policy/ReadOnly",
+            ]
+        );
+        assert!(user.permissions_boundary.is_none());
+
+        let group = parse_policy_file(
+            authorization_details(),
+            "details.json",
+            Some(&Identity::group("developers")),
+        )
+        .unwrap();
+        assert_eq!(group.policies.len(), 1);
+        assert_eq!(group.policies[0].statements[0].action, vec!["s3:Get*"]);
+    }
+
+    #[test]
+    fn test_parse_resource_policy() {
+        let key = "arn:aws:kms:us-east-1:123456789012:key/1234";
+        let cli_output = json!({
+            "Policy": "{\"Version\":\"2012-10-17\",\"Statement\":[{\"Effect\":\"Allow\",\"Principal\":{\"AWS\":\"arn:aws:iam::123456789012:root\"},\"Action\":\"kms:*\",\"Resource\":\"*\"}]}",
+            "PolicyName": "default"
+        });
+        let policy = parse_resource_policy(&cli_output, Some(key)).unwrap();
+        assert_eq!(policy.arn, key);
+        assert_eq!(policy.service(), "kms");
+        assert_eq!(
+            policy.policy.origin.to_string(),
+            format!("resource:{}", key)
+        );
+        assert!(parse_resource_policy(&cli_output, None).is_err());
+
+        let topic = json!({
+            "Attributes": {
+                "TopicArn": "arn:aws:sns:us-east-1:123456789012:alerts",
+                "Policy": "{\"Version\":\"2012-10-17\",\"Statement\":[]}"
+            }
+        });
+        let policy = parse_resource_policy(&topic, None).unwrap();
+        assert_eq!(policy.arn, "arn:aws:sns:us-east-1:123456789012:alerts");
+
+        let wrapped = json!({
+            "ResourceArn": "arn:aws:s3:::bucket",
+            "Policy": { "Version": "2012-10-17", "Statement": [{ "Effect": "Deny", "Principal": "*", "Action": "s3:*", "Resource": "arn:aws:s3:::bucket/*" }] }
+        });
+        let policy = parse_resource_policy(&wrapped, None).unwrap();
+        assert_eq!(policy.account_id(), None);
+        assert_eq!(policy.policy.statements.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_trust_policy() {
+        let trust = parse_trust_policy(&authorization_details(), Some("app"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(trust.statements[0].action, vec!["sts:AssumeRole"]);
+        assert!(trust.statements[0].principal.is_some());
+
+        assert!(parse_trust_policy(&authorization_details(), Some("other"))
+            .unwrap()
+            .is_none());
+        assert!(
+            parse_trust_policy(&json!({ "Version": "2012-10-17", "Statement": [] }), None)
+                .unwrap()
+                .is_none()
+        );
+
+        let document = json!({
+            "Version": "2012-10-17",
+            "Statement": {
+                "Effect": "Allow",
+                "Principal": { "Service": "ec2.amazonaws.com" },
+                "Action": "sts:AssumeRole"
+            }
+        });
+        let trust = parse_trust_policy(&document, Some("app")).unwrap().unwrap();
+        assert_eq!(trust.statements.len(), 1);
+
+        let identity_policy = json!({
+            "Version": "2012-10-17",
+            "Statement": [{ "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*" }]
+        });
+        assert!(parse_trust_policy(&identity_policy, None)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_parse_authorization_details_requires_role_selection() {
+        let error = parse_policy_file(authorization_details(), "details.json", None).unwrap_err();
+        assert!(error.to_string().contains("--role"));
+
+        let error = parse_policy_file(
+            authorization_details(),
+            "details.json",
+            Some(&Identity::role("missing")),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("missing"));
+
+        let no_roles = json!({ "RoleDetailList": [], "Policies": [] });
+        let error = parse_policy_file(no_roles, "details.json", None).unwrap_err();
+        assert!(error.to_string().contains("contain no roles"));
+    }
+
+    #[test]
+    fn test_parse_snapshot_checks_role() {
+        let snapshot = serde_json::to_value(Snapshot::new(
+            "app",
+            "123456789012",
+            IdentityPolicies::default(),
+            None,
+        ))
+        .unwrap();
+
+        assert!(
+            parse_policy_file(snapshot.clone(), "app.json", Some(&Identity::role("app"))).is_ok()
+        );
+        assert!(parse_policy_file(snapshot.clone(), "app.json", None).is_ok());
+        let error = parse_policy_file(snapshot.clone(), "app.json", Some(&Identity::role("other")))
+            .unwrap_err();
+        assert!(error.to_string().contains("snapshot is of role app"));
+        let error = parse_policy_file(snapshot.clone(), "app.json", Some(&Identity::user("app")))
+            .unwrap_err();
+        assert!(error.to_string().contains("not user:app"));
+
+        let other_account: Identity = "arn:aws:iam::210987654321:role/app".parse().unwrap();
+        let error = parse_policy_file(snapshot, "app.json", Some(&other_account)).unwrap_err();
+        assert!(error.to_string().contains("account 123456789012"));
+    }
+}
@@ -1,47 +1,553 @@
-use aws::client::get_aws_client;
-use aws::policy::fetch_role_policy;
-use capability::{extract_capabilities_from_policies, CapabilityComparisonRow};
-use std::io::stdout;
+use aws::cache::PolicyCache;
+use aws::client::{get_aws_client, AwsClient, ClientOptions};
+use aws::iam::Policy;
+use aws::identity::IdentityKind;
+use aws::policy::IamPolicySource;
+use capability::{
+    extract_boundary_capabilities, extract_capabilities_from_policies, CapabilityComparisonRow,
+};
+use catalog::ActionCatalog;
+use cloudtrail::{actions_missing_from, load_events};
+use condition::RequestContext;
+use escalation::{find_escalation_paths, EscalationRules};
+use evaluate::{evaluate, Request, SimulationRow};
+use futures::future;
+use generate::generate_policy;
+use input::{unique_labels, PolicyInput};
+use lint::lint_policies;
+use local::{
+    load_resource_control_policies, load_resource_policies, load_service_control_policies,
+};
+use resource::{evaluate_access, AccessRow};
+use snapshot::Snapshot;
+use source::PolicySource;
+use std::collections::HashMap;
+use std::io::{stdout, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use trust::{extract_trust_rows, TrustComparisonRow};
 
 mod aws;
 mod capability;
+mod catalog;
 mod cli;
+mod cloudtrail;
 mod compare;
+mod condition;
+mod escalation;
+mod evaluate;
+mod generate;
+mod input;
+mod lint;
+mod local;
 mod output;
+mod resource;
+mod severity;
+mod snapshot;
+mod source;
+mod trust;
+mod wildcard;
 
 #[tokio::main]
 async fn main() {
     let args = cli::fetch_cli_args();
-    let aws_client = get_aws_client().await.unwrap();
     let mut writer = stdout();
 
     match args {
         cli::IamCapable::Compare {
             role1,
+            role1_policy_file,
+            role1_policy_dir,
+            role1_profile,
             role2,
+            role2_policy_file,
+            role2_policy_dir,
+            role2_profile,
+            roles,
+            semantic,
+            trust,
+            aws,
+            organization,
+            apply_boundary,
+            mode,
+            assert_subset,
             output_format,
+            expand_actions,
+            action_catalog,
         } => {
-            let policy1 = fetch_role_policy(&aws_client.iam, &aws_client.account_id, &role1)
-                .await
+            let catalog = load_catalog(expand_actions, action_catalog);
+            let mode = mode.unwrap_or_default();
+            let (inputs, profiles): (Vec<PolicyInput>, Vec<Option<String>>) = [
+                (
+                    PolicyInput::from_optional_args(role1, role1_policy_file, role1_policy_dir),
+                    role1_profile,
+                ),
+                (
+                    PolicyInput::from_optional_args(role2, role2_policy_file, role2_policy_dir),
+                    role2_profile,
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(input, profile)| input.map(|input| (input, profile)))
+            .chain(roles.into_iter().map(|input| (input, None)))
+            .unzip();
+            if inputs.len() < 2 {
+                exit_with_error("compare needs at least two roles");
+            }
+            if semantic && inputs.len() > 2 {
+                exit_with_error("--semantic compares exactly two roles");
+            }
+
+            let ceilings = load_organization_policies(&organization);
+            let aws_clients = connect_with_profiles(&inputs, &profiles, &aws.into()).await;
+            let live_sources: Vec<Option<Arc<dyn PolicySource>>> = aws_clients
+                .iter()
+                .map(|aws_client| live_source(aws_client.as_ref()))
+                .collect();
+            let labels = unique_labels(&inputs);
+
+            if trust {
+                let trust_policies = future::join_all(
+                    inputs
+                        .iter()
+                        .zip(&live_sources)
+                        .map(|(input, source)| input.load_trust_policy(source.as_ref())),
+                )
+                .await;
+                let trusts = inputs.iter().zip(&aws_clients).zip(trust_policies).map(
+                    |((input, aws_client), trust_policy)| {
+                        trust_policy
+                            .unwrap()
+                            .map(|policy| {
+                                extract_trust_rows(&policy, input.account_id(aws_client.as_ref()))
+                            })
+                            .unwrap_or_default()
+                    },
+                );
+                let roles = labels.iter().cloned().zip(trusts).collect();
+                let rows = compare::compare_trust_policies(roles, mode);
+                let header_roles = if mode.shows_roles() { &labels[..] } else { &[] };
+                output::format::print_with_header(
+                    output_format,
+                    &TrustComparisonRow::header(header_roles),
+                    &rows,
+                    &mut writer,
+                );
+                return;
+            }
+
+            // The roles load side by side; each client's throttle bounds the requests in flight.
+            let loaded = future::join_all(
+                inputs
+                    .iter()
+                    .zip(&live_sources)
+                    .map(|(input, source)| input.load(source.as_ref())),
+            )
+            .await;
+            let mut policies = Vec::new();
+            for role in loaded {
+                let mut role_policies = role.unwrap().into_policies(apply_boundary);
+                role_policies.extend(ceilings.iter().cloned());
+                policies.push(role_policies);
+            }
+
+            if semantic {
+                let policy2 = policies.pop().unwrap();
+                let policy1 = policies.pop().unwrap();
+                let rows =
+                    compare::compare_policies_semantically(policy1, policy2, catalog.as_ref());
+                output::format::print(output_format, &rows, &mut writer);
+            } else if assert_subset {
+                let roles = labels.iter().cloned().zip(policies).collect();
+                let rows = compare::subset_violations(roles, catalog.as_ref());
+                output::format::print_with_header(
+                    output_format,
+                    &CapabilityComparisonRow::header(&labels),
+                    &rows,
+                    &mut writer,
+                );
+                if !rows.is_empty() {
+                    writer.flush().unwrap();
+                    eprintln!(
+                        "{} capabilities are not granted by {}",
+                        rows.len(),
+                        labels[0]
+                    );
+                    std::process::exit(1);
+                }
+            } else {
+                let roles = labels.iter().cloned().zip(policies).collect();
+                let rows = compare::compare_policies(roles, mode, catalog.as_ref());
+                let header_roles = if mode.shows_roles() { &labels[..] } else { &[] };
+                output::format::print_with_header(
+                    output_format,
+                    &CapabilityComparisonRow::header(header_roles),
+                    &rows,
+                    &mut writer,
+                );
+            }
+        }
+        cli::IamCapable::Fetch {
+            identity,
+            aws,
+            organization,
+            apply_boundary,
+            trust,
+            output_format,
+            expand_actions,
+            action_catalog,
+        } => {
+            let catalog = load_catalog(expand_actions, action_catalog);
+            // Fetch the policies for the single role, user or group
+            let input = identity.into_input().unwrap();
+            let aws_client = connect_if_needed(std::slice::from_ref(&input), &aws.into()).await;
+            let source = live_source(aws_client.as_ref());
+
+            if trust {
+                let rows = match input.load_trust_policy(source.as_ref()).await.unwrap() {
+                    Some(policy) => {
+                        extract_trust_rows(&policy, input.account_id(aws_client.as_ref()))
+                    }
+                    None if input.is_live() => {
+                        eprintln!("{} has no trust policy", input.label());
+                        Vec::new()
+                    }
+                    None => exit_with_error(&format!(
+                        "{} holds no trust policy; pass a trust policy document, an authorization \
+                         details dump or a snapshot",
+                        input.label()
+                    )),
+                };
+                output::format::print(output_format, &rows, &mut writer);
+                return;
+            }
+
+            let identity_policies = input.load(source.as_ref()).await.unwrap();
+            let boundary = identity_policies.permissions_boundary.clone();
+
+            let mut policies = identity_policies.into_policies(apply_boundary);
+            policies.extend(load_organization_policies(&organization));
+
+            let mut rows = extract_capabilities_from_policies(policies, catalog.as_ref());
+            if let Some(boundary) = boundary {
+                rows.extend(extract_boundary_capabilities(boundary, catalog.as_ref()));
+            }
+            output::format::print(output_format, &rows, &mut writer);
+        }
+        cli::IamCapable::Snapshot { role, aws, out } => {
+            if role.kind != IdentityKind::Role {
+                exit_with_error("snapshot only supports roles");
+            }
+            let aws_client = get_aws_client(&aws.into()).await.unwrap();
+            let account_id = role
+                .account_id
+                .clone()
+                .unwrap_or_else(|| aws_client.account_id.clone());
+            let input = PolicyInput::Live(role.clone());
+            let source = input
+                .source(live_source(Some(&aws_client)).as_ref())
                 .unwrap();
-            let policy2 = fetch_role_policy(&aws_client.iam, &aws_client.account_id, &role2)
+            let policies = source.load(input.identity()).await.unwrap();
+            let trust_policy = source.trust_policy(input.identity()).await.unwrap();
+
+            let snapshot = Snapshot::new(&role.name, &account_id, policies, trust_policy);
+            snapshot.write(&out).unwrap();
+            eprintln!(
+                "Saved {} policies of role {} to {}",
+                snapshot.policies.len(),
+                role.name,
+                out.display()
+            );
+        }
+        cli::IamCapable::Simulate {
+            identity,
+            aws,
+            organization,
+            action,
+            resource,
+            context,
+            output_format,
+        } => {
+            let context = match context {
+                Some(path) => RequestContext::from_file(&path)
+                    .unwrap_or_else(|e| exit_with_error(&e.to_string())),
+                None => RequestContext::default(),
+            };
+            let input = identity.into_input().unwrap();
+            let aws_client = connect_if_needed(std::slice::from_ref(&input), &aws.into()).await;
+            let source = live_source(aws_client.as_ref());
+            // Simulation follows AWS's evaluation logic, which always applies the boundary.
+            let mut policies = input
+                .load(source.as_ref())
                 .await
-                .unwrap();
-            let rows: Vec<CapabilityComparisonRow> = compare::compare_policies(policy1, policy2);
+                .unwrap()
+                .into_policies(true);
+            policies.extend(load_organization_policies(&organization));
+            let request = Request {
+                action,
+                resource,
+                context,
+            };
 
-            output::format::print(output_format, &rows, &mut writer)
+            let evaluation = evaluate(&policies, &request);
+            let rows = SimulationRow::from_evaluation(&request, &evaluation);
+            output::format::print(output_format, &rows, &mut writer);
         }
-        cli::IamCapable::Fetch {
+        cli::IamCapable::Access {
             role,
+            files,
+            aws,
+            organization,
+            resource_policy,
+            action,
+            resource,
+            context,
             output_format,
         } => {
-            // Fetch the policies for the single role
-            let policies = fetch_role_policy(&aws_client.iam, &aws_client.account_id, &role)
+            let context = match context {
+                Some(path) => RequestContext::from_file(&path)
+                    .unwrap_or_else(|e| exit_with_error(&e.to_string())),
+                None => RequestContext::default(),
+            };
+            let resource_policies = load_resource_policies(&resource_policy)
+                .unwrap_or_else(|e| exit_with_error(&e.to_string()));
+            let input = files.into_input(Some(role)).unwrap();
+            let aws_client = connect_if_needed(std::slice::from_ref(&input), &aws.into()).await;
+            let source = live_source(aws_client.as_ref());
+            let mut policies = input
+                .load(source.as_ref())
                 .await
-                .unwrap();
+                .unwrap()
+                .into_policies(true);
+            policies.extend(load_organization_policies(&organization));
+
+            // Resource policies name principals by ARN, so fill in the role's account.
+            let mut principal = input.identity().cloned().unwrap();
+            principal.account_id = input.account_id(aws_client.as_ref()).map(str::to_string);
 
-            let rows = extract_capabilities_from_policies(policies);
+            let resources = if resource.is_empty() {
+                resource_policies
+                    .iter()
+                    .map(|policy| policy.arn.clone())
+                    .collect()
+            } else {
+                resource
+            };
+
+            let mut rows = Vec::new();
+            for resource in &resources {
+                let resource_policy = resource_policies
+                    .iter()
+                    .find(|policy| policy.covers(resource));
+                for action in &action {
+                    let request = Request {
+                        action: action.clone(),
+                        resource: resource.clone(),
+                        context: context.clone(),
+                    };
+                    let evaluation =
+                        evaluate_access(&policies, resource_policy, &principal, &request);
+                    rows.push(AccessRow::from_evaluation(&request, &evaluation));
+                }
+            }
             output::format::print(output_format, &rows, &mut writer);
         }
+        cli::IamCapable::Escalation {
+            identity,
+            aws,
+            organization,
+            rules,
+            output_format,
+        } => {
+            let rules = EscalationRules::load(rules.as_deref())
+                .unwrap_or_else(|e| exit_with_error(&e.to_string()));
+            eprintln!(
+                "Checking {} escalation rules, version {}",
+                rules.rules.len(),
+                rules.version
+            );
+            let input = identity.into_input().unwrap();
+            let aws_client = connect_if_needed(std::slice::from_ref(&input), &aws.into()).await;
+            let source = live_source(aws_client.as_ref());
+            // Paths the permissions boundary or control policies block are not open.
+            let mut policies = input
+                .load(source.as_ref())
+                .await
+                .unwrap()
+                .into_policies(true);
+            policies.extend(load_organization_policies(&organization));
+
+            let rows = find_escalation_paths(&policies, &rules);
+            output::format::print(output_format, &rows, &mut writer);
+        }
+        cli::IamCapable::Lint {
+            identity,
+            aws,
+            action_catalog,
+            output_format,
+        } => {
+            let catalog = ActionCatalog::load(action_catalog.as_deref())
+                .unwrap_or_else(|e| exit_with_error(&e.to_string()));
+            eprintln!(
+                "Checking actions against action catalog {}",
+                catalog.version
+            );
+            let input = identity.into_input().unwrap();
+            let aws_client = connect_if_needed(std::slice::from_ref(&input), &aws.into()).await;
+            let source = live_source(aws_client.as_ref());
+            let policies = input
+                .load(source.as_ref())
+                .await
+                .unwrap()
+                .into_policies(true);
+
+            let findings = lint_policies(&policies, &catalog);
+            output::format::print(output_format, &findings, &mut writer);
+        }
+        cli::IamCapable::Generate {
+            role,
+            cloudtrail_dir,
+            collapse_wildcards,
+            out,
+            action_catalog,
+        } => {
+            if role.kind != IdentityKind::Role {
+                exit_with_error("generate only supports roles");
+            }
+            let events =
+                load_events(&cloudtrail_dir).unwrap_or_else(|e| exit_with_error(&e.to_string()));
+            // Calls refused for lack of permissions were never allowed, so they are not granted.
+            let calls: Vec<_> = events
+                .iter()
+                .filter(|event| event.is_made_by(&role) && !event.is_access_denied())
+                .flat_map(|event| event.calls())
+                .collect();
+            eprintln!(
+                "Read {} CloudTrail events from {}, {} of them calls by role {}",
+                events.len(),
+                cloudtrail_dir.display(),
+                calls.len(),
+                role.name
+            );
+            if calls.is_empty() {
+                exit_with_error(&format!(
+                    "no calls by role {} to generate a policy from",
+                    role.name
+                ));
+            }
+
+            let catalog = ActionCatalog::load(action_catalog.as_deref())
+                .unwrap_or_else(|e| exit_with_error(&e.to_string()));
+            for action in actions_missing_from(&calls, &catalog) {
+                eprintln!(
+                    "Warning: {} is not in action catalog {}; check which action authorizes the call",
+                    action, catalog.version
+                );
+            }
+
+            let policy = generate_policy(&calls, collapse_wildcards);
+            let document = serde_json::to_string_pretty(&policy).unwrap() + "\n";
+            match out {
+                Some(path) => std::fs::write(&path, document).unwrap_or_else(|e| {
+                    exit_with_error(&format!("failed to write {}: {}", path.display(), e))
+                }),
+                None => writer.write_all(document.as_bytes()).unwrap(),
+            }
+        }
+        cli::IamCapable::Cache(cli::CacheCommand::Clear { cache_dir }) => {
+            let dir = cache_dir
+                .or_else(PolicyCache::default_dir)
+                .unwrap_or_else(|| exit_with_error("no cache directory; pass --cache-dir"));
+            let removed = PolicyCache::new(dir.clone()).clear().unwrap();
+            eprintln!(
+                "Removed {} cached policy documents from {}",
+                removed,
+                dir.display()
+            );
+        }
     }
 }
+
+/// Loads the action catalog when wildcard actions should be expanded.
+fn load_catalog(expand_actions: bool, action_catalog: Option<PathBuf>) -> Option<ActionCatalog> {
+    if expand_actions {
+        let catalog = ActionCatalog::load(action_catalog.as_deref()).unwrap();
+        eprintln!("Expanding actions with action catalog {}", catalog.version);
+        Some(catalog)
+    } else {
+        None
+    }
+}
+
+/// Loads the service and resource control policies that cap every role's capabilities.
+fn load_organization_policies(organization: &cli::OrganizationArgs) -> Vec<Policy> {
+    let mut policies = load_service_control_policies(&organization.scp_file).unwrap();
+    policies.extend(load_resource_control_policies(&organization.rcp_file).unwrap());
+    policies
+}
+
+/// Connects to AWS only if one of the inputs is a live role, so local files work without
+/// credentials.
+async fn connect_if_needed(inputs: &[PolicyInput], options: &ClientOptions) -> Option<AwsClient> {
+    if inputs.iter().any(needs_aws) {
+        Some(get_aws_client(options).await.unwrap())
+    } else {
+        None
+    }
+}
+
+/// Like `connect_if_needed`, but connects once per profile used by a live input, with `None`
+/// standing for the profile in `options`. Returns the client of each input, in order.
+async fn connect_with_profiles(
+    inputs: &[PolicyInput],
+    profiles: &[Option<String>],
+    options: &ClientOptions,
+) -> Vec<Option<AwsClient>> {
+    let mut connected: HashMap<&Option<String>, AwsClient> = HashMap::new();
+    let mut clients = Vec::new();
+
+    for (input, profile) in inputs.iter().zip(profiles) {
+        if !needs_aws(input) {
+            clients.push(None);
+            continue;
+        }
+        if !connected.contains_key(profile) {
+            let client = get_aws_client(&options.with_profile(profile.clone()))
+                .await
+                .unwrap();
+            connected.insert(profile, client);
+        }
+        clients.push(connected.get(profile).cloned());
+    }
+
+    clients
+}
+
+/// Returns true if the input is read from IAM, and so needs AWS credentials. Live identities
+/// come from the fixture instead when one is set.
+fn needs_aws(input: &PolicyInput) -> bool {
+    #[cfg(feature = "test-fixtures")]
+    if std::env::var_os(source::FIXTURE_ENV).is_some() {
+        return false;
+    }
+    input.is_live()
+}
+
+/// Returns the source to read live identities from: IAM through `aws_client`, or, in builds
+/// with the `test-fixtures` feature, the fixture named by `IAM_CAPABLE_FIXTURE` when it is set.
+fn live_source(aws_client: Option<&AwsClient>) -> Option<Arc<dyn PolicySource>> {
+    #[cfg(feature = "test-fixtures")]
+    if let Some(fixture) = source::MemoryPolicySource::from_env() {
+        let fixture = fixture.unwrap_or_else(|e| exit_with_error(&e.to_string()));
+        return Some(Arc::new(fixture));
+    }
+    aws_client.map(|aws_client| {
+        Arc::new(IamPolicySource::new(aws_client.clone())) as Arc<dyn PolicySource>
+    })
+}
+
+/// Reports invalid arguments the way structopt does, without a panic backtrace.
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(2);
+}
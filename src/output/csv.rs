@@ -2,15 +2,21 @@ use super::format::OutputSerializable;
 use csv::Writer;
 use std::io::Write;
 
-pub fn write_csv<T: OutputSerializable, W: Write>(rows: &[T], writer: W) -> Result<(), csv::Error> {
+/// Writes `rows` as CSV under `header`, which is usually `T::csv_header()` but may be computed
+/// at runtime for tables whose columns depend on the input (e.g. one column per compared role).
+pub fn write_csv<T: OutputSerializable, W: Write, H: AsRef<[u8]>>(
+    header: &[H],
+    rows: &[T],
+    writer: W,
+) -> Result<(), csv::Error> {
     let mut csv_writer = Writer::from_writer(writer);
 
     // Write the header
-    csv_writer.write_record(T::csv_header())?;
+    csv_writer.write_record(header)?;
 
     // Write rows
     for row in rows {
-        csv_writer.write_record(&row.csv_record())?;
+        csv_writer.write_record(row.csv_record())?;
     }
 
     csv_writer.flush()?;
@@ -24,15 +30,23 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
+    fn roles() -> Vec<String> {
+        vec!["Role1".to_string(), "Role2".to_string()]
+    }
+
+    fn header() -> Vec<String> {
+        CapabilityComparisonRow::header(&roles())
+    }
+
     #[test]
     fn test_write_csv_empty_rows() {
         let rows: Vec<CapabilityComparisonRow> = Vec::new();
         let mut writer = Cursor::new(Vec::new());
 
-        assert!(write_csv(&rows, &mut writer).is_ok());
+        assert!(write_csv(&header(), &rows, &mut writer).is_ok());
 
         let content = String::from_utf8(writer.into_inner()).unwrap();
-        assert_eq!(content, "Resource,Action,Role1,Role2\n");
+        assert_eq!(content, "Resource,Action,Condition,Role1,Role2\n");
     }
 
     // Test case: single row
@@ -41,17 +55,18 @@ mod tests {
         let rows = vec![CapabilityComparisonRow {
             resource: String::from("Resource1"),
             action: String::from("Action1"),
-            has_capability1: true,
-            has_capability2: false,
+            condition: String::new(),
+            roles: roles(),
+            has_capability: vec![true, false],
         }];
         let mut writer = Cursor::new(Vec::new());
 
-        assert!(write_csv(&rows, &mut writer).is_ok());
+        assert!(write_csv(&header(), &rows, &mut writer).is_ok());
 
         let content = String::from_utf8(writer.into_inner()).unwrap();
         assert_eq!(
             content,
-            "Resource,Action,Role1,Role2\nResource1,Action1,true,false\n"
+            "Resource,Action,Condition,Role1,Role2\nResource1,Action1,,true,false\n"
         );
     }
 
@@ -62,24 +77,26 @@ mod tests {
             CapabilityComparisonRow {
                 resource: String::from("Resource1"),
                 action: String::from("Action1"),
-                has_capability1: true,
-                has_capability2: false,
+                condition: String::new(),
+                roles: roles(),
+                has_capability: vec![true, false],
             },
             CapabilityComparisonRow {
                 resource: String::from("Resource2"),
                 action: String::from("Action2"),
-                has_capability1: false,
-                has_capability2: true,
+                condition: String::new(),
+                roles: roles(),
+                has_capability: vec![false, true],
             },
         ];
         let mut writer = Cursor::new(Vec::new());
 
-        assert!(write_csv(&rows, &mut writer).is_ok());
+        assert!(write_csv(&header(), &rows, &mut writer).is_ok());
 
         let content = String::from_utf8(writer.into_inner()).unwrap();
         assert_eq!(
         content,
-        "Resource,Action,Role1,Role2\nResource1,Action1,true,false\nResource2,Action2,false,true\n"
+        "Resource,Action,Condition,Role1,Role2\nResource1,Action1,,true,false\nResource2,Action2,,false,true\n"
     );
     }
 }
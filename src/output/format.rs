@@ -32,11 +32,21 @@ pub fn print<T: OutputSerializable, W: Write>(
     output_format: OutputFormat,
     data: &[T],
     writer: &mut W,
+) {
+    print_with_header(output_format, &T::csv_header(), data, writer)
+}
+
+/// Like `print`, but with a CSV header computed at runtime; JSON output is unaffected.
+pub fn print_with_header<T: OutputSerializable, W: Write, H: AsRef<[u8]>>(
+    output_format: OutputFormat,
+    header: &[H],
+    data: &[T],
+    writer: &mut W,
 ) {
     match output_format {
         OutputFormat::Csv => {
             // Output as CSV
-            if let Err(e) = write_csv(data, writer) {
+            if let Err(e) = write_csv(header, data, writer) {
                 eprintln!("Error writing CSV: {}", e);
             }
         }
@@ -20,6 +20,10 @@ mod tests {
     struct JsonCapabilityRow {
         resource: String,
         action: String,
+        roles: JsonRoles,
+    }
+    #[derive(serde::Deserialize)]
+    struct JsonRoles {
         role1: bool,
         role2: bool,
     }
@@ -30,14 +34,16 @@ mod tests {
             CapabilityComparisonRow {
                 resource: "arn:aws:s3:::mybucket".to_string(),
                 action: "s3:ListBucket".to_string(),
-                has_capability1: true,
-                has_capability2: false,
+                condition: String::new(),
+                roles: vec!["role1".to_string(), "role2".to_string()],
+                has_capability: vec![true, false],
             },
             CapabilityComparisonRow {
                 resource: "arn:aws:s3:::mybucket/*".to_string(),
                 action: "s3:GetObject".to_string(),
-                has_capability1: false,
-                has_capability2: true,
+                condition: String::new(),
+                roles: vec!["role1".to_string(), "role2".to_string()],
+                has_capability: vec![false, true],
             },
         ];
 
@@ -50,13 +56,13 @@ mod tests {
 
         assert_eq!(deserialized_rows[0].resource, "arn:aws:s3:::mybucket");
         assert_eq!(deserialized_rows[0].action, "s3:ListBucket");
-        assert_eq!(deserialized_rows[0].role1, true);
-        assert_eq!(deserialized_rows[0].role2, false);
+        assert!(deserialized_rows[0].roles.role1);
+        assert!(!deserialized_rows[0].roles.role2);
 
         assert_eq!(deserialized_rows[1].resource, "arn:aws:s3:::mybucket/*");
         assert_eq!(deserialized_rows[1].action, "s3:GetObject");
-        assert_eq!(deserialized_rows[1].role1, false);
-        assert_eq!(deserialized_rows[1].role2, true);
+        assert!(!deserialized_rows[1].roles.role1);
+        assert!(deserialized_rows[1].roles.role2);
 
         Ok(())
     }
@@ -0,0 +1,624 @@
+use serde_json::json;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::aws::iam::{Effect, Policy, Principal};
+use crate::aws::identity::{Identity, IdentityKind};
+use crate::evaluate::{
+    ceilings_allow, evaluate, statement_matches, Decision, MatchedStatement, Request,
+};
+use crate::output::format::OutputSerializable;
+
+/// The resource-based policy of an S3 bucket, KMS key, SQS queue, SNS topic or Lambda
+/// function.
+#[derive(Debug, Clone)]
+pub struct ResourcePolicy {
+    /// The ARN of the resource the policy is attached to.
+    pub arn: String,
+    /// The account owning the resource, when given alongside an ARN that does not name one.
+    pub owner_account_id: Option<String>,
+    pub policy: Policy,
+}
+
+impl ResourcePolicy {
+    /// The service of the resource, e.g. `s3` or `kms`.
+    pub fn service(&self) -> &str {
+        self.arn.split(':').nth(2).unwrap_or_default()
+    }
+
+    /// The account of the resource: the owner it was given with, or the account of its ARN.
+    /// `None` for an S3 bucket given without its owner, since bucket ARNs do not name one.
+    pub fn account_id(&self) -> Option<&str> {
+        self.owner_account_id.as_deref().or_else(|| {
+            self.arn
+                .split(':')
+                .nth(4)
+                .filter(|account| !account.is_empty())
+        })
+    }
+
+    /// Returns true if the policy applies to `resource`: the resource itself, or an object in
+    /// it, such as a key of a bucket.
+    pub fn covers(&self, resource: &str) -> bool {
+        resource == self.arn
+            || resource
+                .strip_prefix(self.arn.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// A `--resource-policy` argument: a JSON file, optionally preceded by the ARN of the resource
+/// it belongs to (`ARN=PATH`) when the file does not name it, and the account owning the
+/// resource (`ARN@ACCOUNT=PATH`) when the ARN does not name it, as for S3 buckets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourcePolicyArg {
+    pub arn: Option<String>,
+    pub account_id: Option<String>,
+    pub path: PathBuf,
+}
+
+impl FromStr for ResourcePolicyArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (arn, path) = match s.split_once('=') {
+            Some((arn, path)) if arn.starts_with("arn:") => (Some(arn), path),
+            _ => (None, s),
+        };
+        if path.is_empty() {
+            return Err(format!("Missing path in {}", s));
+        }
+        let (arn, account_id) = match arn.and_then(|arn| arn.rsplit_once('@')) {
+            Some((arn, account_id)) => {
+                if account_id.len() != 12 || !account_id.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(format!("Invalid account ID {:?} in {}", account_id, s));
+                }
+                (Some(arn), Some(account_id.to_string()))
+            }
+            None => (arn, None),
+        };
+        Ok(ResourcePolicyArg {
+            arn: arn.map(str::to_string),
+            account_id,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// How a resource policy treats the principal of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResourceGrant {
+    /// No statement of the policy applies to the principal, or there is no policy.
+    None,
+    /// A statement allows the principal's account, delegating the decision to its identity
+    /// policies.
+    Account,
+    /// A statement allows the principal itself (or everyone).
+    Principal,
+    /// A statement denies the principal.
+    Deny,
+}
+
+impl fmt::Display for ResourceGrant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceGrant::None => write!(f, "none"),
+            ResourceGrant::Account => write!(f, "allows-account"),
+            ResourceGrant::Principal => write!(f, "allows-principal"),
+            ResourceGrant::Deny => write!(f, "denies"),
+        }
+    }
+}
+
+/// The combined decision of the identity and resource policies for a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessEvaluation {
+    pub decision: Decision,
+    /// The decision of the identity policies (and their ceilings) alone.
+    pub identity_decision: Decision,
+    pub resource_grant: ResourceGrant,
+    /// Why the request was decided the way it was.
+    pub reason: &'static str,
+    /// The statements that determined the decision.
+    pub statements: Vec<MatchedStatement>,
+}
+
+/// Evaluates a request by `identity` against its identity policies and the resource policy of
+/// the resource, following AWS's rules for combining them:
+///
+/// - An explicit deny in either policy wins.
+/// - A KMS key policy must allow the request itself, either to the principal or, when the
+///   identity policies also allow it, to the principal's account. Identity policies alone are
+///   never enough.
+/// - Within one account, either policy allowing the request is enough.
+/// - Across accounts, both the identity policies and the resource policy must allow it.
+///
+/// Ceilings among the identity policies (the permissions boundary, service and resource
+/// control policies) also cap what the resource policy grants.
+///
+/// # Arguments
+///
+/// * `identity_policies` - The identity's policies, including its ceilings.
+/// * `resource_policy` - The policy of the resource, if it has one.
+/// * `identity` - The principal making the request; its `account_id` decides whether the
+///   request crosses accounts.
+/// * `request` - The action, resource and context to evaluate.
+///
+/// # Returns
+///
+/// The AccessEvaluation.
+pub fn evaluate_access(
+    identity_policies: &[Policy],
+    resource_policy: Option<&ResourcePolicy>,
+    identity: &Identity,
+    request: &Request,
+) -> AccessEvaluation {
+    let identity_evaluation = evaluate(identity_policies, request);
+    let identity_decision = identity_evaluation.decision;
+
+    let mut resource_allows = Vec::new();
+    // The allows naming the principal itself rather than its account.
+    let mut principal_allows = Vec::new();
+    let mut resource_denies = Vec::new();
+    let mut grant = ResourceGrant::None;
+    if let Some(resource_policy) = resource_policy {
+        let source = resource_policy.policy.origin.to_string();
+        for (index, statement) in resource_policy.policy.statements.iter().enumerate() {
+            if !statement_matches(statement, request) {
+                continue;
+            }
+            let applies = match (&statement.principal, &statement.not_principal) {
+                (_, Some(not_principal)) => match principal_grant(not_principal, identity) {
+                    ResourceGrant::None => ResourceGrant::Principal,
+                    _ => ResourceGrant::None,
+                },
+                (Some(principal), None) => principal_grant(principal, identity),
+                (None, None) => ResourceGrant::None,
+            };
+            if applies == ResourceGrant::None {
+                continue;
+            }
+            let matched = MatchedStatement {
+                source: source.clone(),
+                index,
+                sid: statement.sid.clone(),
+                effect: statement.effect,
+            };
+            match statement.effect {
+                Effect::Allow => {
+                    grant = grant.max(applies);
+                    if applies == ResourceGrant::Principal {
+                        principal_allows.push(matched.clone());
+                    }
+                    resource_allows.push(matched);
+                }
+                Effect::Deny => resource_denies.push(matched),
+            }
+        }
+    }
+    if !resource_denies.is_empty() {
+        grant = ResourceGrant::Deny;
+    }
+
+    let decide = |decision: Decision, reason: &'static str, statements: Vec<MatchedStatement>| {
+        AccessEvaluation {
+            decision,
+            identity_decision,
+            resource_grant: grant,
+            reason,
+            statements,
+        }
+    };
+
+    if identity_decision == Decision::ExplicitDeny || !resource_denies.is_empty() {
+        let mut statements = identity_evaluation.statements;
+        if identity_decision != Decision::ExplicitDeny {
+            statements.clear();
+        }
+        statements.extend(resource_denies);
+        return decide(Decision::ExplicitDeny, "explicitly denied", statements);
+    }
+
+    let identity_allows = identity_decision == Decision::Allowed;
+    let principal_allowed =
+        grant == ResourceGrant::Principal && ceilings_allow(identity_policies, request);
+    let with_both = || {
+        identity_evaluation
+            .statements
+            .iter()
+            .cloned()
+            .chain(resource_allows.iter().cloned())
+            .collect::<Vec<_>>()
+    };
+
+    let resource_policy = match resource_policy {
+        Some(resource_policy) => resource_policy,
+        None if identity_allows => {
+            return decide(
+                Decision::Allowed,
+                "allowed by the identity policies",
+                identity_evaluation.statements,
+            )
+        }
+        None => {
+            return decide(
+                Decision::ImplicitDeny,
+                "not allowed by the identity policies",
+                Vec::new(),
+            )
+        }
+    };
+
+    if resource_policy.service() == "kms" {
+        return if principal_allowed {
+            decide(
+                Decision::Allowed,
+                "allowed by the key policy",
+                principal_allows,
+            )
+        } else if grant == ResourceGrant::Account && identity_allows {
+            decide(
+                Decision::Allowed,
+                "the key policy allows the account and the identity policies allow the request",
+                with_both(),
+            )
+        } else {
+            decide(
+                Decision::ImplicitDeny,
+                "the key policy must allow the principal, or its account with the identity policies allowing the request too",
+                Vec::new(),
+            )
+        };
+    }
+
+    // An identity given by name alone is assumed to be in the resource's account, as in
+    // `aws_principal_grant`. A resource of unknown account may belong to anyone, so it takes the
+    // stricter cross-account rule.
+    let cross_account = match (resource_policy.account_id(), identity.account_id.as_deref()) {
+        (Some(resource_account), Some(account)) => resource_account != account,
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+
+    if cross_account {
+        if identity_allows && grant != ResourceGrant::None {
+            decide(
+                Decision::Allowed,
+                "allowed by both the identity and the resource policy",
+                with_both(),
+            )
+        } else if resource_policy.account_id().is_none() {
+            decide(
+                Decision::ImplicitDeny,
+                "the resource's account is unknown (give it as ARN@ACCOUNT=PATH), so both the identity and the resource policy must allow it",
+                Vec::new(),
+            )
+        } else {
+            decide(
+                Decision::ImplicitDeny,
+                "cross-account access needs both the identity and the resource policy to allow it",
+                Vec::new(),
+            )
+        }
+    } else if identity_allows {
+        decide(
+            Decision::Allowed,
+            "allowed by the identity policies",
+            identity_evaluation.statements,
+        )
+    } else if principal_allowed {
+        decide(
+            Decision::Allowed,
+            "allowed by the resource policy",
+            principal_allows,
+        )
+    } else {
+        decide(
+            Decision::ImplicitDeny,
+            "neither the identity nor the resource policy allows the request",
+            Vec::new(),
+        )
+    }
+}
+
+/// Works out whether a `Principal` element names `identity` itself, its account, or neither.
+fn principal_grant(principal: &Principal, identity: &Identity) -> ResourceGrant {
+    let principals = match principal {
+        Principal::Wildcard => return ResourceGrant::Principal,
+        Principal::Typed(principals) => principals,
+    };
+
+    principals
+        .get("AWS")
+        .into_iter()
+        .flatten()
+        .map(|value| aws_principal_grant(value, identity))
+        .max()
+        .unwrap_or(ResourceGrant::None)
+}
+
+fn aws_principal_grant(value: &str, identity: &Identity) -> ResourceGrant {
+    if value == "*" {
+        return ResourceGrant::Principal;
+    }
+    let account = identity.account_id.as_deref();
+    if value.len() == 12 && value.bytes().all(|b| b.is_ascii_digit()) {
+        return if account == Some(value) {
+            ResourceGrant::Account
+        } else {
+            ResourceGrant::None
+        };
+    }
+
+    // arn:PARTITION:SERVICE::ACCOUNT:RESOURCE
+    let parts: Vec<&str> = value.splitn(6, ':').collect();
+    if parts.len() != 6 || parts[0] != "arn" {
+        return ResourceGrant::None;
+    }
+    let (service, principal_account, resource) = (parts[2], parts[4], parts[5]);
+    // An identity given by name alone is assumed to be in the resource's account.
+    if account.is_some_and(|account| account != principal_account) {
+        return ResourceGrant::None;
+    }
+
+    if resource == "root" {
+        return if account.is_some() {
+            ResourceGrant::Account
+        } else {
+            ResourceGrant::None
+        };
+    }
+
+    let names_identity = match (service, identity.kind) {
+        ("iam", IdentityKind::Role) => resource
+            .strip_prefix("role/")
+            .is_some_and(|path| path.rsplit('/').next() == Some(identity.name.as_str())),
+        ("iam", IdentityKind::User) => resource
+            .strip_prefix("user/")
+            .is_some_and(|path| path.rsplit('/').next() == Some(identity.name.as_str())),
+        ("sts", IdentityKind::Role) => resource
+            .strip_prefix("assumed-role/")
+            .is_some_and(|rest| rest.split('/').next() == Some(identity.name.as_str())),
+        _ => false,
+    };
+
+    if names_identity {
+        ResourceGrant::Principal
+    } else {
+        ResourceGrant::None
+    }
+}
+
+/// Represents a row in the access report, one per resource and action.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AccessRow {
+    pub resource: String,
+    pub action: String,
+    pub decision: Decision,
+    pub identity_policy: Decision,
+    pub resource_policy: ResourceGrant,
+    pub reason: String,
+    /// The determining statements, as `source#sid` (or `source#index` without a `Sid`).
+    pub statements: Vec<String>,
+}
+
+impl AccessRow {
+    pub fn from_evaluation(request: &Request, evaluation: &AccessEvaluation) -> Self {
+        AccessRow {
+            resource: request.resource.clone(),
+            action: request.action.clone(),
+            decision: evaluation.decision,
+            identity_policy: evaluation.identity_decision,
+            resource_policy: evaluation.resource_grant,
+            reason: evaluation.reason.to_string(),
+            statements: evaluation
+                .statements
+                .iter()
+                .map(|matched| match &matched.sid {
+                    Some(sid) => format!("{}#{}", matched.source, sid),
+                    None => format!("{}#{}", matched.source, matched.index),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl OutputSerializable for AccessRow {
+    fn csv_header() -> Vec<&'static str> {
+        vec![
+            "Resource",
+            "Action",
+            "Decision",
+            "Identity Policy",
+            "Resource Policy",
+            "Reason",
+            "Statements",
+        ]
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.resource.clone(),
+            self.action.clone(),
+            self.decision.to_string(),
+            self.identity_policy.to_string(),
+            self.resource_policy.to_string(),
+            self.reason.clone(),
+            self.statements.join("; "),
+        ]
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        json!({
+            "resource": self.resource,
+            "action": self.action,
+            "decision": self.decision.to_string(),
+            "identity_policy": self.identity_policy.to_string(),
+            "resource_policy": self.resource_policy.to_string(),
+            "reason": self.reason,
+            "statements": self.statements
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::iam::PolicyOrigin;
+
+    fn resource_policy(arn: &str, statements: serde_json::Value) -> ResourcePolicy {
+        ResourcePolicy {
+            arn: arn.to_string(),
+            owner_account_id: None,
+            policy: Policy::from_statements(
+                PolicyOrigin::Resource {
+                    arn: arn.to_string(),
+                },
+                statements,
+            ),
+        }
+    }
+
+    fn identity_policy(action: &str, resource: &str) -> Vec<Policy> {
+        vec![Policy::from_statements(
+            PolicyOrigin::Inline {
+                name: "app".to_string(),
+            },
+            json!([{ "Effect": "Allow", "Action": action, "Resource": resource }]),
+        )]
+    }
+
+    fn app() -> Identity {
+        "arn:aws:iam::111111111111:role/service/app"
+            .parse()
+            .unwrap()
+    }
+
+    const KEY: &str = "arn:aws:kms:us-east-1:111111111111:key/1234";
+
+    #[test]
+    fn test_kms_key_policy_is_required() {
+        let decrypt = Request::without_context("kms:Decrypt", KEY);
+        let identity = identity_policy("kms:Decrypt", "*");
+
+        let no_grant = resource_policy(
+            KEY,
+            json!([{ "Effect": "Allow", "Principal": { "AWS": "arn:aws:iam::111111111111:role/admin" }, "Action": "kms:*", "Resource": "*" }]),
+        );
+        let evaluation = evaluate_access(&identity, Some(&no_grant), &app(), &decrypt);
+        assert_eq!(evaluation.decision, Decision::ImplicitDeny);
+        assert_eq!(evaluation.identity_decision, Decision::Allowed);
+
+        let account = resource_policy(
+            KEY,
+            json!([{ "Sid": "EnableIam", "Effect": "Allow", "Principal": { "AWS": "arn:aws:iam::111111111111:root" }, "Action": "kms:*", "Resource": "*" }]),
+        );
+        let evaluation = evaluate_access(&identity, Some(&account), &app(), &decrypt);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+        assert_eq!(evaluation.resource_grant, ResourceGrant::Account);
+        let evaluation = evaluate_access(&[], Some(&account), &app(), &decrypt);
+        assert_eq!(evaluation.decision, Decision::ImplicitDeny);
+
+        let direct = resource_policy(
+            KEY,
+            json!([{ "Effect": "Allow", "Principal": { "AWS": "arn:aws:iam::111111111111:role/app" }, "Action": "kms:Decrypt", "Resource": "*" }]),
+        );
+        let evaluation = evaluate_access(&[], Some(&direct), &app(), &decrypt);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+        assert_eq!(evaluation.resource_grant, ResourceGrant::Principal);
+    }
+
+    #[test]
+    fn test_same_and_cross_account_access() {
+        let get_object = Request::without_context("s3:GetObject", "arn:aws:s3:::bucket/key");
+        let mut bucket = resource_policy(
+            "arn:aws:s3:::bucket",
+            json!([{ "Effect": "Allow", "Principal": { "AWS": "arn:aws:sts::111111111111:assumed-role/app/session" }, "Action": "s3:GetObject", "Resource": "arn:aws:s3:::bucket/*" }]),
+        );
+        assert!(bucket.covers("arn:aws:s3:::bucket/key"));
+        assert!(!bucket.covers("arn:aws:s3:::bucket-logs/key"));
+
+        // Bucket ARNs name no account, so without an owner the bucket may be in another one.
+        let evaluation = evaluate_access(&[], Some(&bucket), &app(), &get_object);
+        assert_eq!(evaluation.decision, Decision::ImplicitDeny);
+        assert!(evaluation.reason.contains("ARN@ACCOUNT=PATH"));
+
+        // Within an account, the bucket policy alone is enough.
+        bucket.owner_account_id = Some("111111111111".to_string());
+        let evaluation = evaluate_access(&[], Some(&bucket), &app(), &get_object);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+        assert_eq!(
+            evaluation.statements[0].source,
+            "resource:arn:aws:s3:::bucket"
+        );
+
+        bucket.owner_account_id = Some("222222222222".to_string());
+        let evaluation = evaluate_access(&[], Some(&bucket), &app(), &get_object);
+        assert_eq!(evaluation.decision, Decision::ImplicitDeny);
+        let identity = identity_policy("s3:GetObject", "arn:aws:s3:::bucket/*");
+        let evaluation = evaluate_access(&identity, Some(&bucket), &app(), &get_object);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+
+        let queue_arn = "arn:aws:sqs:us-east-1:222222222222:jobs";
+        let send = Request::without_context("sqs:SendMessage", queue_arn);
+        let queue = resource_policy(
+            queue_arn,
+            json!([{ "Effect": "Allow", "Principal": { "AWS": "111111111111" }, "Action": "sqs:SendMessage", "Resource": queue_arn }]),
+        );
+        let identity = identity_policy("sqs:SendMessage", queue_arn);
+
+        let evaluation = evaluate_access(&[], Some(&queue), &app(), &send);
+        assert_eq!(evaluation.decision, Decision::ImplicitDeny);
+        let evaluation = evaluate_access(&identity, Some(&queue), &app(), &send);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+        let evaluation = evaluate_access(&identity, None, &app(), &send);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+    }
+
+    #[test]
+    fn test_resource_policy_deny_wins() {
+        let topic_arn = "arn:aws:sns:us-east-1:111111111111:alerts";
+        let publish = Request::without_context("sns:Publish", topic_arn);
+        let topic = resource_policy(
+            topic_arn,
+            json!([{ "Sid": "NoOne", "Effect": "Deny", "Principal": "*", "Action": "sns:*", "Resource": topic_arn }]),
+        );
+
+        let evaluation = evaluate_access(
+            &identity_policy("sns:Publish", topic_arn),
+            Some(&topic),
+            &app(),
+            &publish,
+        );
+        assert_eq!(evaluation.decision, Decision::ExplicitDeny);
+        assert_eq!(evaluation.resource_grant, ResourceGrant::Deny);
+        let row = AccessRow::from_evaluation(&publish, &evaluation);
+        assert_eq!(
+            row.statements,
+            vec![format!("resource:{}#NoOne", topic_arn)]
+        );
+    }
+
+    #[test]
+    fn test_parse_resource_policy_arg() {
+        let arg: ResourcePolicyArg = format!("{}=key-policy.json", KEY).parse().unwrap();
+        assert_eq!(arg.arn.as_deref(), Some(KEY));
+        assert_eq!(arg.path, PathBuf::from("key-policy.json"));
+
+        let arg: ResourcePolicyArg = "policies/a=b.json".parse().unwrap();
+        assert_eq!(arg.arn, None);
+        assert_eq!(arg.path, PathBuf::from("policies/a=b.json"));
+
+        assert!(format!("{}=", KEY).parse::<ResourcePolicyArg>().is_err());
+
+        let arg: ResourcePolicyArg = "arn:aws:s3:::bucket@111111111111=bucket-policy.json"
+            .parse()
+            .unwrap();
+        assert_eq!(arg.arn.as_deref(), Some("arn:aws:s3:::bucket"));
+        assert_eq!(arg.account_id.as_deref(), Some("111111111111"));
+        assert!("arn:aws:s3:::bucket@prod=bucket-policy.json"
+            .parse::<ResourcePolicyArg>()
+            .is_err());
+    }
+}
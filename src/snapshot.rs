@@ -0,0 +1,182 @@
+use chrono::{SecondsFormat, Utc};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::aws::iam::{IdentityPolicies, Policy, PolicyOrigin};
+
+/// The `format` marker that identifies a snapshot file, so the local loader can tell it apart
+/// from a plain policy document.
+pub const SNAPSHOT_FORMAT: &str = "iam-capable-snapshot";
+
+/// The snapshot layout version; bump it when the layout changes incompatibly.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// A role's resolved policies at a point in time, written by `snapshot` and readable anywhere
+/// `--policy-file` is accepted.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub format: String,
+    pub version: u32,
+    pub role: String,
+    pub account_id: String,
+    /// When the policies were fetched, in RFC 3339.
+    pub fetched_at: String,
+    pub policies: Vec<SnapshotPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions_boundary: Option<SnapshotPolicy>,
+    /// Who may assume the role. Absent from snapshots taken before trust policies were saved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trust_policy: Option<Policy>,
+}
+
+/// A policy document together with where it came from.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SnapshotPolicy {
+    pub origin: PolicyOrigin,
+    pub document: Policy,
+}
+
+impl Snapshot {
+    /// Captures the policies and trust policy of `role`, timestamped now.
+    pub fn new(
+        role: &str,
+        account_id: &str,
+        policies: IdentityPolicies,
+        trust_policy: Option<Policy>,
+    ) -> Self {
+        Snapshot {
+            format: SNAPSHOT_FORMAT.to_string(),
+            version: SNAPSHOT_VERSION,
+            role: role.to_string(),
+            account_id: account_id.to_string(),
+            fetched_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            policies: policies
+                .policies
+                .into_iter()
+                .map(SnapshotPolicy::from)
+                .collect(),
+            permissions_boundary: policies.permissions_boundary.map(SnapshotPolicy::from),
+            trust_policy,
+        }
+    }
+
+    /// Returns true if `value` looks like a snapshot rather than a policy document.
+    pub fn is_snapshot(value: &Value) -> bool {
+        value["format"] == SNAPSHOT_FORMAT
+    }
+
+    /// Parses a snapshot, rejecting layouts newer than this build understands.
+    pub fn from_value(value: Value) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let snapshot: Snapshot = serde_json::from_value(value)?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} is newer than the supported version {}",
+                snapshot.version, SNAPSHOT_VERSION
+            )
+            .into());
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(path, contents + "\n")
+            .map_err(|e| format!("Failed to write snapshot {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Returns the snapshot's policies with their original origins restored.
+    pub fn into_identity_policies(self) -> IdentityPolicies {
+        IdentityPolicies {
+            policies: self.policies.into_iter().map(Policy::from).collect(),
+            permissions_boundary: self.permissions_boundary.map(Policy::from),
+        }
+    }
+}
+
+impl From<Policy> for SnapshotPolicy {
+    fn from(policy: Policy) -> Self {
+        SnapshotPolicy {
+            origin: policy.origin.clone(),
+            document: policy,
+        }
+    }
+}
+
+impl From<SnapshotPolicy> for Policy {
+    fn from(snapshot_policy: SnapshotPolicy) -> Self {
+        let mut policy = snapshot_policy.document;
+        policy.origin = snapshot_policy.origin;
+        policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut policy: Policy = serde_json::from_value(json!({
+            "Version": "2012-10-17",
+            "Statement": [{ "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*" }]
+        }))
+        .unwrap();
+        policy.origin = PolicyOrigin::Managed {
+            arn: "arn:aws:iam::123456789012:policy/Read".to_string(),
+            version_id: Some("v3".to_string()),
+        };
+
+        let boundary = Policy {
+            origin: PolicyOrigin::PermissionsBoundary {
+                arn: "arn:aws:iam::123456789012:policy/Boundary".to_string(),
+                version_id: None,
+            },
+            ..policy.clone()
+        };
+        let role = IdentityPolicies {
+            policies: vec![policy],
+            permissions_boundary: Some(boundary),
+        };
+
+        let snapshot = Snapshot::new("app", "123456789012", role, None);
+        let value = serde_json::to_value(&snapshot).unwrap();
+
+        assert!(Snapshot::is_snapshot(&value));
+        assert_eq!(value["policies"][0]["origin"]["type"], "managed");
+        assert_eq!(value["policies"][0]["origin"]["version_id"], "v3");
+
+        let role = Snapshot::from_value(value)
+            .unwrap()
+            .into_identity_policies();
+        let policies = role.policies;
+        assert_eq!(policies.len(), 1);
+        assert_eq!(
+            policies[0].origin.to_string(),
+            "managed:arn:aws:iam::123456789012:policy/Read"
+        );
+        assert_eq!(policies[0].statements[0].action, vec!["s3:GetObject"]);
+        assert!(matches!(
+            role.permissions_boundary.unwrap().origin,
+            PolicyOrigin::PermissionsBoundary { .. }
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_newer_versions() {
+        let value = json!({
+            "format": SNAPSHOT_FORMAT,
+            "version": SNAPSHOT_VERSION + 1,
+            "role": "app",
+            "account_id": "123456789012",
+            "fetched_at": "2026-01-01T00:00:00Z",
+            "policies": []
+        });
+
+        assert!(Snapshot::from_value(value).is_err());
+    }
+}
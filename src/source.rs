@@ -0,0 +1,226 @@
+use async_trait::async_trait;
+
+use crate::aws::iam::{IdentityPolicies, Policy};
+use crate::aws::identity::Identity;
+#[cfg(feature = "test-fixtures")]
+use crate::snapshot::SnapshotPolicy;
+
+/// Somewhere the policies of IAM roles, users and groups can be read from: the IAM API, local
+/// files, or an in-memory fixture.
+///
+/// Every method takes the identity to read. `None` selects the source's only identity, which
+/// local files can do when they hold the policies of a single, unnamed role.
+#[async_trait]
+pub trait PolicySource: Send + Sync {
+    /// Returns the identity policies: attached managed and inline policies, and for a user
+    /// those inherited from its groups.
+    async fn identity_policies(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Returns the permissions boundary of a role or user, if it has one.
+    async fn permissions_boundary(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Returns the trust policy of a role, naming who may assume it. Users and groups have
+    /// none.
+    async fn trust_policy(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Returns the identity policies together with the permissions boundary.
+    async fn load(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<IdentityPolicies, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(IdentityPolicies {
+            policies: self.identity_policies(identity).await?,
+            permissions_boundary: self.permissions_boundary(identity).await?,
+        })
+    }
+}
+
+/// Environment variable naming a fixture file that live identities are read from instead of
+/// IAM, so integration tests can run the full command line without AWS.
+#[cfg(feature = "test-fixtures")]
+pub const FIXTURE_ENV: &str = "IAM_CAPABLE_FIXTURE";
+
+/// A fixed set of identities and their policies, for tests.
+#[cfg(any(test, feature = "test-fixtures"))]
+#[derive(Default)]
+pub struct MemoryPolicySource {
+    identities: Vec<(Identity, IdentityPolicies, Option<Policy>)>,
+}
+
+#[cfg(any(test, feature = "test-fixtures"))]
+impl MemoryPolicySource {
+    /// Adds an identity with its policies and, for a role, its trust policy.
+    pub fn with_identity(
+        mut self,
+        identity: Identity,
+        policies: IdentityPolicies,
+        trust_policy: Option<Policy>,
+    ) -> Self {
+        self.identities.push((identity, policies, trust_policy));
+        self
+    }
+
+    fn find(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<
+        &(Identity, IdentityPolicies, Option<Policy>),
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        match identity {
+            Some(identity) => self
+                .identities
+                .iter()
+                .find(|(candidate, _, _)| candidate == identity)
+                .ok_or_else(|| format!("{} is not in the fixture", identity).into()),
+            None if self.identities.len() == 1 => Ok(&self.identities[0]),
+            None => Err("the fixture holds several identities; select one".into()),
+        }
+    }
+}
+
+/// An identity in a fixture file, with its policies in the layout of a snapshot.
+#[cfg(feature = "test-fixtures")]
+#[derive(serde_derive::Deserialize)]
+struct FixtureIdentity {
+    /// A role name, `user:NAME`, `group:NAME` or an IAM ARN, as `--role` accepts them.
+    identity: String,
+    #[serde(default)]
+    policies: Vec<SnapshotPolicy>,
+    #[serde(default)]
+    permissions_boundary: Option<SnapshotPolicy>,
+    #[serde(default)]
+    trust_policy: Option<Policy>,
+}
+
+#[cfg(feature = "test-fixtures")]
+impl MemoryPolicySource {
+    /// Reads the fixture file named by `IAM_CAPABLE_FIXTURE`, if the variable is set.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the variable is not set, otherwise a Result containing the fixture, or an
+    /// Error if the file cannot be read or holds an invalid identity.
+    pub fn from_env() -> Option<Result<Self, Box<dyn std::error::Error + Send + Sync>>> {
+        let path = std::env::var_os(FIXTURE_ENV)?;
+        Some(MemoryPolicySource::from_file(std::path::Path::new(&path)))
+    }
+
+    /// Reads a fixture file: a JSON array of identities, each with its policies, permissions
+    /// boundary and trust policy.
+    pub fn from_file(
+        path: &std::path::Path,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read fixture {}: {}", path.display(), e))?;
+        let identities: Vec<FixtureIdentity> = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid fixture {}: {}", path.display(), e))?;
+
+        let mut source = MemoryPolicySource::default();
+        for fixture in identities {
+            source = source.with_identity(
+                fixture.identity.parse()?,
+                IdentityPolicies {
+                    policies: fixture.policies.into_iter().map(Policy::from).collect(),
+                    permissions_boundary: fixture.permissions_boundary.map(Policy::from),
+                },
+                fixture.trust_policy,
+            );
+        }
+        Ok(source)
+    }
+}
+
+#[cfg(any(test, feature = "test-fixtures"))]
+#[async_trait]
+impl PolicySource for MemoryPolicySource {
+    async fn identity_policies(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Vec<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.find(identity)?.1.policies.clone())
+    }
+
+    async fn permissions_boundary(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.find(identity)?.1.permissions_boundary.clone())
+    }
+
+    async fn trust_policy(
+        &self,
+        identity: Option<&Identity>,
+    ) -> Result<Option<Policy>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.find(identity)?.2.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::iam::PolicyOrigin;
+    use crate::capability::{extract_capabilities_from_policies, CapabilityStatus};
+    use serde_json::json;
+
+    fn policy(action: &str, origin: PolicyOrigin) -> Policy {
+        let mut policy: Policy = serde_json::from_value(json!({
+            "Version": "2012-10-17",
+            "Statement": [{ "Effect": "Allow", "Action": action, "Resource": "*" }]
+        }))
+        .unwrap();
+        policy.origin = origin;
+        policy
+    }
+
+    #[tokio::test]
+    async fn test_memory_source_loads_identities() {
+        let source = MemoryPolicySource::default()
+            .with_identity(
+                Identity::role("app"),
+                IdentityPolicies {
+                    policies: vec![policy(
+                        "s3:*",
+                        PolicyOrigin::Inline {
+                            name: "storage".to_string(),
+                        },
+                    )],
+                    permissions_boundary: Some(policy(
+                        "s3:GetObject",
+                        PolicyOrigin::PermissionsBoundary {
+                            arn: "arn:aws:iam::123456789012:policy/Boundary".to_string(),
+                            version_id: None,
+                        },
+                    )),
+                },
+                Some(policy("sts:AssumeRole", PolicyOrigin::Unknown)),
+            )
+            .with_identity(Identity::user("alice"), IdentityPolicies::default(), None);
+
+        let app = source
+            .load(Some(&Identity::role("app")))
+            .await
+            .unwrap()
+            .into_policies(true);
+        let rows = extract_capabilities_from_policies(app, None);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].status, CapabilityStatus::PartiallyDenied);
+
+        let trust = source.trust_policy(Some(&Identity::role("app"))).await;
+        assert!(trust.unwrap().is_some());
+        let alice = source.load(Some(&Identity::user("alice"))).await.unwrap();
+        assert!(alice.policies.is_empty());
+
+        assert!(source.load(None).await.is_err());
+        assert!(source.load(Some(&Identity::group("app"))).await.is_err());
+    }
+}
@@ -0,0 +1,503 @@
+use serde_json::json;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::aws::iam::{Effect, Policy, PolicyStatement, Principal};
+use crate::output::format::OutputSerializable;
+use crate::wildcard::PatternSet;
+
+/// A risky pattern in a trust policy statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrustFinding {
+    /// Anyone can assume the role (`"Principal": "*"`, `"AWS": "*"`, or `NotPrincipal` with
+    /// `Allow`), unless a condition narrows it down to an organization, account or ARN (see
+    /// `NARROWING_CONDITION_KEYS`).
+    WildcardPrincipal,
+    /// A principal in another account can assume the role without presenting an
+    /// `sts:ExternalId`, leaving it open to the confused deputy problem.
+    MissingExternalId,
+    /// Any identity of a web identity provider can assume the role, since no condition tests
+    /// the token's subject (`<provider>:sub`).
+    UnrestrictedWebIdentity,
+}
+
+impl fmt::Display for TrustFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrustFinding::WildcardPrincipal => write!(f, "wildcard-principal"),
+            TrustFinding::MissingExternalId => write!(f, "missing-external-id"),
+            TrustFinding::UnrestrictedWebIdentity => write!(f, "unrestricted-web-identity"),
+        }
+    }
+}
+
+/// Condition keys (lowercased) that restrict a wildcard principal to the principals of an
+/// organization, account or ARN, or to calls on behalf of a given source.
+const NARROWING_CONDITION_KEYS: &[&str] = &[
+    "aws:principalorgid",
+    "aws:principalorgpaths",
+    "aws:principalaccount",
+    "aws:principalarn",
+    "aws:sourceaccount",
+    "aws:sourcearn",
+    "aws:sourceorgid",
+    "aws:sourceorgpaths",
+    "aws:sourceowner",
+];
+
+/// A principal allowed to assume a role, with the action and condition it is allowed under.
+#[derive(Debug, Clone)]
+pub struct TrustRow {
+    /// `AWS`, `Service`, `Federated`, `CanonicalUser`, or `*` for everyone.
+    pub principal_type: String,
+    pub principal: String,
+    pub action: String,
+    /// The statement's `Condition` block as compact JSON, or empty if there is none.
+    pub condition: String,
+    pub findings: Vec<TrustFinding>,
+}
+
+// Two rows describe the same trust regardless of the findings derived from it.
+impl PartialEq for TrustRow {
+    fn eq(&self, other: &Self) -> bool {
+        self.principal_type == other.principal_type
+            && self.principal == other.principal
+            && self.action == other.action
+            && self.condition == other.condition
+    }
+}
+
+impl Eq for TrustRow {}
+
+impl Hash for TrustRow {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.principal_type.hash(state);
+        self.principal.hash(state);
+        self.action.hash(state);
+        self.condition.hash(state);
+    }
+}
+
+impl TrustRow {
+    fn findings_text(&self) -> String {
+        findings_text(&self.findings)
+    }
+}
+
+/// Renders findings for a CSV cell, separated by `;`.
+fn findings_text(findings: &[TrustFinding]) -> String {
+    findings
+        .iter()
+        .map(TrustFinding::to_string)
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn findings_json(findings: &[TrustFinding]) -> serde_json::Value {
+    json!(findings
+        .iter()
+        .map(TrustFinding::to_string)
+        .collect::<Vec<_>>())
+}
+
+impl OutputSerializable for TrustRow {
+    fn csv_header() -> Vec<&'static str> {
+        vec![
+            "Principal Type",
+            "Principal",
+            "Action",
+            "Condition",
+            "Findings",
+        ]
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        vec![
+            self.principal_type.clone(),
+            self.principal.clone(),
+            self.action.clone(),
+            self.condition.clone(),
+            self.findings_text(),
+        ]
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        json!({
+            "principal_type": self.principal_type,
+            "principal": self.principal,
+            "action": self.action,
+            "condition": self.condition,
+            "findings": findings_json(&self.findings)
+        })
+    }
+}
+
+/// Represents a row in the trust comparison table.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TrustComparisonRow {
+    pub principal_type: String,
+    pub principal: String,
+    pub action: String,
+    pub condition: String,
+    /// The names of the compared roles, in the order they were given.
+    pub roles: Vec<String>,
+    /// Whether each role in `roles` trusts the principal.
+    pub trusted: Vec<bool>,
+    /// The findings of each role in `roles` for the principal, empty for roles that do not
+    /// trust it. Without per-role columns, a single entry holding the findings of any role.
+    pub findings: Vec<Vec<TrustFinding>>,
+}
+
+impl TrustComparisonRow {
+    /// The CSV header for a comparison of `roles`: the fixed columns followed by two columns
+    /// per role, whether it trusts the principal and its findings. Without roles, a single
+    /// `Findings` column follows instead.
+    pub fn header(roles: &[String]) -> Vec<String> {
+        let fixed = Self::csv_header().into_iter().map(String::from);
+        if roles.is_empty() {
+            return fixed.chain(["Findings".to_string()]).collect();
+        }
+        fixed
+            .chain(
+                roles
+                    .iter()
+                    .flat_map(|role| [role.clone(), format!("{} Findings", role)]),
+            )
+            .collect()
+    }
+}
+
+impl OutputSerializable for TrustComparisonRow {
+    /// The fixed columns only; use `TrustComparisonRow::header` for the role columns.
+    fn csv_header() -> Vec<&'static str> {
+        vec!["Principal Type", "Principal", "Action", "Condition"]
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        let fixed = vec![
+            self.principal_type.clone(),
+            self.principal.clone(),
+            self.action.clone(),
+            self.condition.clone(),
+        ];
+        if self.roles.is_empty() {
+            return fixed
+                .into_iter()
+                .chain(self.findings.iter().map(|findings| findings_text(findings)))
+                .collect();
+        }
+        fixed
+            .into_iter()
+            .chain(
+                self.trusted
+                    .iter()
+                    .zip(&self.findings)
+                    .flat_map(|(trusted, findings)| [trusted.to_string(), findings_text(findings)]),
+            )
+            .collect()
+    }
+
+    /// Per-role values are nested under `roles`, keyed by role, so a role label can never
+    /// clash with a fixed field.
+    fn to_json_value(&self) -> serde_json::Value {
+        let mut value = json!({
+            "principal_type": self.principal_type,
+            "principal": self.principal,
+            "action": self.action,
+            "condition": self.condition
+        });
+        if self.roles.is_empty() {
+            value["findings"] = findings_json(self.findings.first().map_or(&[], Vec::as_slice));
+            return value;
+        }
+        let roles: serde_json::Map<String, serde_json::Value> = self
+            .roles
+            .iter()
+            .zip(&self.trusted)
+            .zip(&self.findings)
+            .map(|((role, trusted), findings)| {
+                (
+                    role.clone(),
+                    json!({ "trusted": trusted, "findings": findings_json(findings) }),
+                )
+            })
+            .collect();
+        value["roles"] = serde_json::Value::Object(roles);
+        value
+    }
+}
+
+/// Extracts who may assume a role from its trust policy, one row per principal and action of
+/// each `Allow` statement, and flags risky statements.
+///
+/// # Arguments
+///
+/// * `trust_policy` - The role's `AssumeRolePolicyDocument`.
+/// * `account_id` - The role's own account, whose principals need no `sts:ExternalId`. When
+///   unknown, every account principal without one is flagged.
+///
+/// # Returns
+///
+/// A Vec of TrustRow.
+pub fn extract_trust_rows(trust_policy: &Policy, account_id: Option<&str>) -> Vec<TrustRow> {
+    let mut rows = Vec::new();
+
+    for stmt in &trust_policy.statements {
+        if stmt.effect != Effect::Allow {
+            continue;
+        }
+        let condition = stmt
+            .condition
+            .as_ref()
+            .map(|condition| serde_json::to_string(condition).unwrap_or_default())
+            .unwrap_or_default();
+        let actions = if stmt.not_action.is_empty() {
+            stmt.action.clone()
+        } else {
+            vec![format!("NotAction({})", stmt.not_action.join(", "))]
+        };
+
+        for (principal_type, principal) in statement_principals(stmt) {
+            let findings = principal_findings(stmt, &principal_type, &principal, account_id);
+            for action in &actions {
+                rows.push(TrustRow {
+                    principal_type: principal_type.clone(),
+                    principal: principal.clone(),
+                    action: action.clone(),
+                    condition: condition.clone(),
+                    findings: findings.clone(),
+                });
+            }
+        }
+    }
+
+    rows
+}
+
+/// Returns the `(type, identifier)` pairs a statement allows. `NotPrincipal` is rendered as a
+/// single `NotPrincipal(...)` entry of type `*`, since it allows everyone else.
+fn statement_principals(stmt: &PolicyStatement) -> Vec<(String, String)> {
+    if let Some(not_principal) = &stmt.not_principal {
+        let excluded = match not_principal {
+            Principal::Wildcard => "*".to_string(),
+            Principal::Typed(principals) => principals
+                .iter()
+                .flat_map(|(kind, ids)| ids.iter().map(move |id| format!("{}:{}", kind, id)))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        return vec![("*".to_string(), format!("NotPrincipal({})", excluded))];
+    }
+
+    match &stmt.principal {
+        Some(Principal::Wildcard) => vec![("*".to_string(), "*".to_string())],
+        Some(Principal::Typed(principals)) => principals
+            .iter()
+            .flat_map(|(kind, ids)| ids.iter().map(move |id| (kind.clone(), id.clone())))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn principal_findings(
+    stmt: &PolicyStatement,
+    principal_type: &str,
+    principal: &str,
+    account_id: Option<&str>,
+) -> Vec<TrustFinding> {
+    let actions = PatternSet::from_element(&stmt.action, &stmt.not_action).to_lowercase();
+    let mut findings = Vec::new();
+
+    if (principal_type == "*" || (principal_type == "AWS" && principal == "*"))
+        && !has_condition_key(stmt, |key| NARROWING_CONDITION_KEYS.contains(&key))
+    {
+        findings.push(TrustFinding::WildcardPrincipal);
+    }
+
+    if principal_type == "AWS"
+        && actions.contains("sts:assumerole")
+        && principal_account(principal).is_some_and(|account| Some(account) != account_id)
+        && !has_condition_key(stmt, |key| key == "sts:externalid")
+    {
+        findings.push(TrustFinding::MissingExternalId);
+    }
+
+    if principal_type == "Federated"
+        && actions.contains("sts:assumerolewithwebidentity")
+        && !has_condition_key(stmt, |key| key.ends_with(":sub"))
+    {
+        findings.push(TrustFinding::UnrestrictedWebIdentity);
+    }
+
+    findings
+}
+
+/// The account of an `AWS` principal given as an account ID or an IAM ARN.
+fn principal_account(principal: &str) -> Option<&str> {
+    if principal.len() == 12 && principal.bytes().all(|b| b.is_ascii_digit()) {
+        return Some(principal);
+    }
+    principal
+        .strip_prefix("arn:")
+        .and_then(|rest| rest.split(':').nth(3))
+        .filter(|account| !account.is_empty())
+}
+
+/// Returns true if any operator of the statement's condition tests a key (lowercased) that
+/// satisfies `predicate`.
+fn has_condition_key(stmt: &PolicyStatement, predicate: impl Fn(&str) -> bool) -> bool {
+    stmt.condition.as_ref().is_some_and(|condition| {
+        condition
+            .values()
+            .flat_map(|keys| keys.keys())
+            .any(|key| predicate(&key.to_lowercase()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aws::iam::PolicyOrigin;
+
+    #[test]
+    fn test_extract_trust_rows_flags_findings() {
+        let policy = Policy::from_statements(
+            PolicyOrigin::Unknown,
+            json!([
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": ["arn:aws:iam::111111111111:root", "222222222222"] },
+                    "Action": "sts:AssumeRole"
+                },
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": "arn:aws:iam::333333333333:role/vendor" },
+                    "Action": "sts:AssumeRole",
+                    "Condition": { "StringEquals": { "sts:ExternalId": "secret" } }
+                },
+                {
+                    "Effect": "Allow",
+                    "Principal": { "Federated": "arn:aws:iam::111111111111:oidc-provider/token.actions.githubusercontent.com" },
+                    "Action": "sts:AssumeRoleWithWebIdentity",
+                    "Condition": { "StringEquals": { "token.actions.githubusercontent.com:aud": "sts.amazonaws.com" } }
+                },
+                {
+                    "Effect": "Allow",
+                    "Principal": "*",
+                    "Action": ["sts:AssumeRole", "sts:TagSession"]
+                },
+                {
+                    "Effect": "Allow",
+                    "Principal": { "Service": "lambda.amazonaws.com" },
+                    "Action": "sts:AssumeRole"
+                }
+            ]),
+        );
+
+        let rows = extract_trust_rows(&policy, Some("111111111111"));
+        let summary: Vec<(&str, &str, String)> = rows
+            .iter()
+            .map(|row| {
+                (
+                    row.principal.as_str(),
+                    row.action.as_str(),
+                    row.findings_text(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "arn:aws:iam::111111111111:root",
+                    "sts:AssumeRole",
+                    String::new()
+                ),
+                (
+                    "222222222222",
+                    "sts:AssumeRole",
+                    "missing-external-id".to_string()
+                ),
+                (
+                    "arn:aws:iam::333333333333:role/vendor",
+                    "sts:AssumeRole",
+                    String::new()
+                ),
+                (
+                    "arn:aws:iam::111111111111:oidc-provider/token.actions.githubusercontent.com",
+                    "sts:AssumeRoleWithWebIdentity",
+                    "unrestricted-web-identity".to_string()
+                ),
+                ("*", "sts:AssumeRole", "wildcard-principal".to_string()),
+                ("*", "sts:TagSession", "wildcard-principal".to_string()),
+                ("lambda.amazonaws.com", "sts:AssumeRole", String::new()),
+            ]
+        );
+        assert_eq!(rows[3].principal_type, "Federated");
+        assert!(rows[3]
+            .condition
+            .contains("token.actions.githubusercontent.com:aud"));
+    }
+
+    #[test]
+    fn test_extract_trust_rows_without_account() {
+        let policy = Policy::from_statements(
+            PolicyOrigin::Unknown,
+            json!([
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": "arn:aws:iam::111111111111:root" },
+                    "Action": "sts:*"
+                },
+                {
+                    "Effect": "Allow",
+                    "NotPrincipal": { "AWS": "arn:aws:iam::111111111111:root" },
+                    "Action": "sts:AssumeRole"
+                },
+                {
+                    "Effect": "Deny",
+                    "Principal": "*",
+                    "Action": "sts:AssumeRole"
+                }
+            ]),
+        );
+
+        let rows = extract_trust_rows(&policy, None);
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].findings, vec![TrustFinding::MissingExternalId]);
+        assert_eq!(rows[1].principal_type, "*");
+        assert_eq!(
+            rows[1].principal,
+            "NotPrincipal(AWS:arn:aws:iam::111111111111:root)"
+        );
+        assert_eq!(rows[1].findings, vec![TrustFinding::WildcardPrincipal]);
+    }
+
+    #[test]
+    fn test_wildcard_principal_narrowed_by_condition() {
+        let policy = Policy::from_statements(
+            PolicyOrigin::Unknown,
+            json!([
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": "*" },
+                    "Action": "sts:AssumeRole",
+                    "Condition": { "StringEquals": { "aws:PrincipalOrgID": "o-abc123" } }
+                },
+                {
+                    "Effect": "Allow",
+                    "Principal": "*",
+                    "Action": "sts:AssumeRole",
+                    "Condition": { "Bool": { "aws:SecureTransport": "true" } }
+                }
+            ]),
+        );
+
+        let rows = extract_trust_rows(&policy, Some("111111111111"));
+
+        assert!(rows[0].findings.is_empty());
+        assert_eq!(rows[1].findings, vec![TrustFinding::WildcardPrincipal]);
+    }
+}
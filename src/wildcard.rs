@@ -0,0 +1,219 @@
+//! IAM-style wildcard patterns, where `*` matches any sequence of characters and `?` matches
+//! exactly one character.
+//!
+//! Matching is case-sensitive; callers comparing actions (which IAM treats case-insensitively)
+//! are expected to lowercase both sides first.
+
+/// Returns true if the concrete `value` matches `pattern`.
+pub fn matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    // matched[j] is true if the pattern consumed so far matches value[..j].
+    let mut matched = vec![false; value.len() + 1];
+    matched[0] = true;
+
+    for token in pattern {
+        let mut next = vec![false; value.len() + 1];
+        for j in 0..=value.len() {
+            next[j] = match token {
+                '*' => matched[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matched[j - 1],
+                c => j > 0 && matched[j - 1] && value[j - 1] == c,
+            };
+        }
+        matched = next;
+    }
+
+    matched[value.len()]
+}
+
+/// Returns true if every value matched by `narrow` is also matched by `broad`.
+///
+/// Both arguments may contain wildcards; wildcards in `narrow` can only be covered by
+/// wildcards in `broad` (e.g. `s3:*` covers `s3:Get*`, but `s3:Get*` does not cover `s3:*`).
+pub fn covers(broad: &str, narrow: &str) -> bool {
+    let broad: Vec<char> = broad.chars().collect();
+    let narrow: Vec<char> = narrow.chars().collect();
+    let (m, n) = (broad.len(), narrow.len());
+
+    // table[i][j] is true if broad[i..] covers narrow[j..].
+    let mut table = vec![vec![false; n + 1]; m + 1];
+    table[m][n] = true;
+
+    for i in (0..m).rev() {
+        for j in (0..=n).rev() {
+            table[i][j] = match broad[i] {
+                '*' => table[i + 1][j] || (j < n && table[i][j + 1]),
+                '?' => j < n && narrow[j] != '*' && table[i + 1][j + 1],
+                c => j < n && narrow[j] == c && table[i + 1][j + 1],
+            };
+        }
+    }
+
+    table[0][0]
+}
+
+/// Returns true if at least one value is matched by both patterns.
+pub fn overlaps(a: &str, b: &str) -> bool {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let (m, n) = (a.len(), b.len());
+
+    // table[i][j] is true if a[i..] and b[j..] share a matching value.
+    let mut table = vec![vec![false; n + 1]; m + 1];
+    table[m][n] = true;
+
+    for i in (0..=m).rev() {
+        for j in (0..=n).rev() {
+            if i == m && j == n {
+                continue;
+            }
+            let a_star = i < m && a[i] == '*';
+            let b_star = j < n && b[j] == '*';
+
+            table[i][j] = if a_star || b_star {
+                (a_star && (table[i + 1][j] || (j < n && table[i][j + 1])))
+                    || (b_star && (table[i][j + 1] || (i < m && table[i + 1][j])))
+            } else if i < m && j < n {
+                (a[i] == '?' || b[j] == '?' || a[i] == b[j]) && table[i + 1][j + 1]
+            } else {
+                false
+            };
+        }
+    }
+
+    table[0][0]
+}
+
+/// A set of values described by wildcard patterns, either as the union of the patterns (an
+/// `Action`/`Resource` element) or as everything except the patterns (`NotAction`/`NotResource`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternSet {
+    Include(Vec<String>),
+    Exclude(Vec<String>),
+}
+
+impl PatternSet {
+    /// Builds the set for an element and its negated counterpart, preferring the negated one
+    /// when present (a valid statement only has one of them).
+    pub fn from_element(patterns: &[String], not_patterns: &[String]) -> Self {
+        if not_patterns.is_empty() {
+            PatternSet::Include(patterns.to_vec())
+        } else {
+            PatternSet::Exclude(not_patterns.to_vec())
+        }
+    }
+
+    /// Returns a copy of the set with every pattern lowercased, for case-insensitive actions.
+    pub fn to_lowercase(&self) -> Self {
+        let lower = |patterns: &[String]| patterns.iter().map(|p| p.to_lowercase()).collect();
+        match self {
+            PatternSet::Include(patterns) => PatternSet::Include(lower(patterns)),
+            PatternSet::Exclude(patterns) => PatternSet::Exclude(lower(patterns)),
+        }
+    }
+
+    /// Returns true if the concrete `value` is a member of the set.
+    pub fn contains(&self, value: &str) -> bool {
+        match self {
+            PatternSet::Include(patterns) => patterns.iter().any(|p| matches(p, value)),
+            PatternSet::Exclude(patterns) => !patterns.iter().any(|p| matches(p, value)),
+        }
+    }
+
+    /// Returns true if every value in `other` is also in this set.
+    ///
+    /// This is conservative: a pattern only covered by the union of several patterns is not
+    /// recognised as covered.
+    pub fn covers(&self, other: &PatternSet) -> bool {
+        match (self, other) {
+            (PatternSet::Include(broad), PatternSet::Include(narrow)) => {
+                narrow.iter().all(|n| broad.iter().any(|b| covers(b, n)))
+            }
+            (PatternSet::Include(broad), PatternSet::Exclude(_)) => broad.iter().any(|b| b == "*"),
+            (PatternSet::Exclude(excluded), PatternSet::Include(narrow)) => narrow
+                .iter()
+                .all(|n| !excluded.iter().any(|e| overlaps(e, n))),
+            (PatternSet::Exclude(excluded), PatternSet::Exclude(other_excluded)) => excluded
+                .iter()
+                .all(|e| other_excluded.iter().any(|o| covers(o, e))),
+        }
+    }
+
+    /// Returns true if at least one value is in both sets.
+    pub fn overlaps(&self, other: &PatternSet) -> bool {
+        match (self, other) {
+            (PatternSet::Include(a), PatternSet::Include(b)) => {
+                a.iter().any(|a| b.iter().any(|b| overlaps(a, b)))
+            }
+            (PatternSet::Include(included), PatternSet::Exclude(excluded))
+            | (PatternSet::Exclude(excluded), PatternSet::Include(included)) => included
+                .iter()
+                .any(|i| !excluded.iter().any(|e| covers(e, i))),
+            (PatternSet::Exclude(_), PatternSet::Exclude(_)) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("s3:*", "s3:GetObject"));
+        assert!(matches("s3:Get*", "s3:GetObject"));
+        assert!(matches("s3:GetObjec?", "s3:GetObject"));
+        assert!(matches("*", ""));
+        assert!(!matches("s3:Get*", "s3:PutObject"));
+        assert!(!matches("s3:GetObject", "s3:GetObjectAcl"));
+        assert!(matches(
+            "arn:aws:s3:::bucket/*/logs",
+            "arn:aws:s3:::bucket/a/b/logs"
+        ));
+    }
+
+    #[test]
+    fn test_covers() {
+        assert!(covers("*", "s3:Get*"));
+        assert!(covers("s3:*", "s3:Get*"));
+        assert!(covers(
+            "arn:aws:s3:::bucket/*",
+            "arn:aws:s3:::bucket/logs/*"
+        ));
+        assert!(covers("s3:GetObject", "s3:GetObject"));
+        assert!(!covers("s3:Get*", "s3:*"));
+        assert!(!covers("s3:GetObject?", "s3:GetObject*"));
+        assert!(!covers(
+            "arn:aws:s3:::bucket/logs/*",
+            "arn:aws:s3:::bucket/*"
+        ));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        assert!(overlaps("s3:Get*", "s3:*Object"));
+        assert!(overlaps("*", "anything"));
+        assert!(overlaps("s3:Get?bject", "s3:G*"));
+        assert!(!overlaps("s3:Get*", "s3:Put*"));
+        assert!(!overlaps("arn:aws:s3:::a/*", "arn:aws:s3:::b/*"));
+    }
+
+    #[test]
+    fn test_pattern_set_covers_and_overlaps() {
+        let all_but_iam = PatternSet::Exclude(vec!["iam:*".to_string()]);
+        let s3_get = PatternSet::Include(vec!["s3:Get*".to_string()]);
+        let iam_pass = PatternSet::Include(vec!["iam:PassRole".to_string()]);
+        let everything = PatternSet::Include(vec!["*".to_string()]);
+
+        assert!(all_but_iam.covers(&s3_get));
+        assert!(!all_but_iam.covers(&iam_pass));
+        assert!(!all_but_iam.overlaps(&iam_pass));
+        assert!(everything.covers(&all_but_iam));
+        assert!(!s3_get.covers(&all_but_iam));
+        assert!(all_but_iam.overlaps(&s3_get));
+        assert!(PatternSet::Exclude(vec!["iam:Pass*".to_string()])
+            .covers(&PatternSet::Exclude(vec!["iam:*".to_string()])));
+    }
+}
@@ -0,0 +1,313 @@
+//! Runs the command line against the fixtures in `tests/fixtures`, without AWS credentials.
+
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn fixture(name: &str) -> String {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+        .display()
+        .to_string()
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_iam-capable"))
+        .args(args)
+        // Make sure nothing reaches AWS, even on a machine with credentials.
+        .env("AWS_ACCESS_KEY_ID", "")
+        .env("AWS_SECRET_ACCESS_KEY", "")
+        .output()
+        .expect("failed to run iam-capable")
+}
+
+fn json_output(output: &Output) -> Value {
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).expect("output is not JSON")
+}
+
+#[test]
+fn test_fetch_user_with_inherited_group_policies() {
+    let details = fixture("authorization-details.json");
+    let output = run(&[
+        "fetch",
+        "--user",
+        "alice",
+        "--policy-file",
+        &details,
+        "--output-format",
+        "json",
+    ]);
+
+    let rows = json_output(&output);
+    let sources: Vec<&str> = rows
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["source"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        sources,
+        vec![
+            "inline:logs",
+            "group:developers:managed:arn:aws:iam::123456789012:policy/ReadOnly",
+        ]
+    );
+}
+
+#[test]
+fn test_compare_assert_subset_fails_on_extra_grants() {
+    let approved = fixture("approved.json");
+    let details = fixture("authorization-details.json");
+    let output = run(&[
+        "compare",
+        "--role1-policy-file",
+        &approved,
+        "--role2",
+        "app",
+        "--role2-policy-file",
+        &details,
+        "--assert-subset",
+    ]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("arn:aws:sqs:us-east-1:123456789012:jobs,sqs:SendMessage,,false,true"));
+    assert!(!stdout.contains("s3:GetObject"));
+}
+
+#[test]
+fn test_compare_rejects_mode_with_assert_subset() {
+    let approved = fixture("approved.json");
+    let output = run(&[
+        "compare",
+        "--role1-policy-file",
+        &approved,
+        "--role2-policy-file",
+        &approved,
+        "--mode",
+        "union",
+        "--assert-subset",
+    ]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+}
+
+#[test]
+fn test_simulate_against_local_role() {
+    let details = fixture("authorization-details.json");
+    let output = run(&[
+        "simulate",
+        "--role",
+        "arn:aws:iam::123456789012:role/app",
+        "--policy-file",
+        &details,
+        "--action",
+        "sqs:SendMessage",
+        "--resource",
+        "arn:aws:sqs:us-east-1:123456789012:jobs",
+        "--output-format",
+        "json",
+    ]);
+
+    let rows = json_output(&output);
+    assert_eq!(rows[0]["decision"], "allowed");
+    assert_eq!(rows[0]["source"], "inline:queue");
+}
+
+#[test]
+fn test_fetch_trust_flags_missing_external_id() {
+    let details = fixture("authorization-details.json");
+    let output = run(&[
+        "fetch",
+        "--role",
+        "arn:aws:iam::123456789012:role/app",
+        "--policy-file",
+        &details,
+        "--trust",
+        "--output-format",
+        "json",
+    ]);
+
+    let rows = json_output(&output);
+    let rows = rows.as_array().unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["principal"], "lambda.amazonaws.com");
+    assert_eq!(rows[0]["findings"], serde_json::json!([]));
+    assert_eq!(rows[1]["principal"], "arn:aws:iam::210987654321:root");
+    assert_eq!(
+        rows[1]["findings"],
+        serde_json::json!(["missing-external-id"])
+    );
+}
+
+#[test]
+fn test_fetch_trust_from_plain_trust_policy() {
+    let trust = fixture("trust-policy.json");
+    let output = run(&[
+        "fetch",
+        "--policy-file",
+        &trust,
+        "--trust",
+        "--output-format",
+        "json",
+    ]);
+
+    let rows = json_output(&output);
+    assert_eq!(rows[0]["principal"], "arn:aws:iam::210987654321:root");
+    assert_eq!(
+        rows[0]["findings"],
+        serde_json::json!(["missing-external-id"])
+    );
+
+    // An identity policy holds no trust policy, which is an error rather than an empty table.
+    let output = run(&[
+        "fetch",
+        "--policy-file",
+        &fixture("approved.json"),
+        "--trust",
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("holds no trust policy"));
+}
+
+#[test]
+fn test_access_combines_key_policy() {
+    let details = fixture("authorization-details.json");
+    let key = "arn:aws:kms:us-east-1:123456789012:key/1234abcd";
+    let key_policy = format!("{}={}", key, fixture("key-policy.json"));
+    let output = run(&[
+        "access",
+        "--role",
+        "arn:aws:iam::123456789012:role/app",
+        "--policy-file",
+        &details,
+        "--resource-policy",
+        &key_policy,
+        "--action",
+        "kms:Decrypt",
+        "--action",
+        "kms:Encrypt",
+        "--output-format",
+        "json",
+    ]);
+
+    let rows = json_output(&output);
+    assert_eq!(rows[0]["action"], "kms:Decrypt");
+    assert_eq!(rows[0]["decision"], "allowed");
+    assert_eq!(rows[0]["identity_policy"], "implicit-deny");
+    assert_eq!(
+        rows[0]["statements"],
+        serde_json::json!([format!("resource:{}#AllowWorkerDecrypt", key)])
+    );
+    // The key policy delegates to IAM for everything else, but the role's policies do not
+    // allow encrypting.
+    assert_eq!(rows[1]["decision"], "implicit-deny");
+    assert_eq!(rows[1]["resource_policy"], "allows-account");
+}
+
+#[test]
+fn test_generate_from_gzipped_cloudtrail_logs() {
+    let logs = fixture("cloudtrail");
+    let output = run(&["generate", "--role", "app", "--cloudtrail-dir", &logs]);
+
+    let policy = json_output(&output);
+    assert_eq!(policy["Version"], "2012-10-17");
+    assert_eq!(
+        policy["Statement"],
+        serde_json::json!([
+            {
+                "Effect": "Allow",
+                "Action": ["s3:GetObject"],
+                "Resource": ["arn:aws:s3:::reports/2026/a.csv", "arn:aws:s3:::reports/2026/b.csv"]
+            },
+            {
+                "Effect": "Allow",
+                "Action": ["sqs:SendMessage"],
+                "Resource": ["arn:aws:sqs:us-east-1:123456789012:jobs"]
+            }
+        ])
+    );
+}
+
+/// Runs the command line with live identities read from the in-memory fixture
+/// `tests/fixtures/memory-source.json` instead of IAM.
+#[cfg(feature = "test-fixtures")]
+fn run_with_fixture(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_iam-capable"))
+        .args(args)
+        .env("IAM_CAPABLE_FIXTURE", fixture("memory-source.json"))
+        .env("AWS_ACCESS_KEY_ID", "")
+        .env("AWS_SECRET_ACCESS_KEY", "")
+        .output()
+        .expect("failed to run iam-capable")
+}
+
+#[cfg(feature = "test-fixtures")]
+#[test]
+fn test_live_identities_from_memory_fixture() {
+    let output = run_with_fixture(&[
+        "fetch",
+        "--role",
+        "app",
+        "--apply-boundary",
+        "--output-format",
+        "json",
+    ]);
+    let rows = json_output(&output);
+    let statuses: Vec<(&str, &str)> = rows
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row["action"].as_str().unwrap(),
+                row["status"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert!(statuses.contains(&("s3:*", "partially-denied")));
+    // The boundary's own rows are marked as a ceiling, not as grants.
+    assert!(rows.as_array().unwrap().iter().all(|row| {
+        let from_boundary = row["source"].as_str().unwrap().starts_with("boundary:");
+        row["kind"] == if from_boundary { "ceiling" } else { "grant" }
+    }));
+    assert!(rows
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|row| row["kind"] == "ceiling"));
+
+    // Simulation always applies the boundary, which only allows s3:GetObject.
+    let output = run_with_fixture(&[
+        "simulate",
+        "--role",
+        "app",
+        "--action",
+        "s3:PutObject",
+        "--resource",
+        "arn:aws:s3:::bucket/key",
+        "--output-format",
+        "json",
+    ]);
+    assert_eq!(json_output(&output)[0]["decision"], "implicit-deny");
+
+    let output = run_with_fixture(&[
+        "fetch",
+        "--trust",
+        "--role",
+        "app",
+        "--output-format",
+        "json",
+    ]);
+    assert_eq!(json_output(&output)[0]["principal"], "lambda.amazonaws.com");
+
+    let output = run_with_fixture(&["fetch", "--user", "alice", "--output-format", "json"]);
+    assert_eq!(json_output(&output)[0]["source"], "inline:logs");
+}